@@ -1,4 +1,8 @@
-use std::ops::Shr;
+use core::fmt;
+use std::ops::{
+    BitAnd, BitAndAssign, BitOr, BitOrAssign, BitXor, BitXorAssign, Not, Shl, ShlAssign, Shr,
+    ShrAssign,
+};
 
 use crate::prelude::*;
 
@@ -16,139 +20,257 @@ const INDEX64: [u8; 64] = [
    25, 14, 19,  9, 13,  8,  7,  6
 ];
 
-pub trait BitboardU64: Sized + Copy + Shr<u8, Output = Self> + PartialEq {
-    const EMPTY: Self;
-    const FILE_A: Self;
-    const RANK_1: Self;
-
-    fn clear_bit(&mut self, idx: Square);
-    fn pop_bit(&mut self) -> Square;
-    fn count_bits(&self) -> u8;
-    fn at(&self, idx: Square) -> bool;
-    fn set(&mut self, idx: Square);
-    fn unset(&mut self, idx: Square);
-    fn last_bit(&self) -> Square;
-    fn set_occupancy(&self, index: usize, bits_in_mask: u8) -> Self;
-    fn trimmed_mul(&self, other: Self) -> Self;
-    fn safe_shl(&self, other: u8) -> Self;
-    fn bit_pos_iter(mut self) -> impl Iterator<Item = Square> {
-        std::iter::from_fn(move || {
-            if self == Self::EMPTY {
-                return None;
-            }
+/// A set of squares, one bit each.
+///
+/// Little-endian rank-file mapping.
+/// See the [Chess Programming Wiki](https://www.chessprogramming.org/Square_Mapping_Considerations#Little-Endian_Rank-File_Mapping).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+#[repr(transparent)]
+pub struct Bitboard(pub u64);
 
-            let sq = self.pop_bit();
+impl Bitboard {
+    pub const EMPTY: Bitboard = Bitboard(0);
+    pub const FILE_A: Bitboard = Bitboard(0x0101010101010101);
+    pub const RANK_1: Bitboard = Bitboard(0xff);
 
-            return Some(sq);
-        })
+    pub fn rank(rank: Rank) -> Self {
+        Self::RANK_1 << (rank.index() * 8)
     }
 
-    fn rank(rank: u8) -> Self {
-        Self::RANK_1.safe_shl(rank * 8)
+    pub fn file(file: File) -> Self {
+        Self::FILE_A << file.index()
     }
 
-    fn file(file: u8) -> Self {
-        Self::FILE_A.safe_shl(file)
+    pub fn clear_bit(&mut self, idx: Square) {
+        *self &= !idx.to_bitboard();
     }
 
-    fn pretty(&self) -> String {
-        let mut s = String::from("\n  a b c d e f g h\n");
+    pub fn pop_bit(&mut self) -> Square {
+        let sq = self.last_bit();
+        self.clear_bit(sq);
+
+        sq
+    }
 
-        for rank in (0..8u8).rev() {
-            for file in 0..8u8 {
-                let idx = rank * 8 + file;
+    pub fn count_bits(self) -> u8 {
+        self.0.count_ones() as u8
+    }
 
-                if idx % 8 == 0 {
-                    s.push_str(&format!("{} ", rank + 1));
-                }
+    pub fn count_ones(self) -> u32 {
+        self.0.count_ones()
+    }
 
-                if self.at(idx) {
-                    s.push_str("X ");
-                } else {
-                    s.push_str(". ");
-                }
+    pub fn is_empty(self) -> bool {
+        self == Self::EMPTY
+    }
+
+    pub fn at(self, idx: Square) -> bool {
+        !(self & idx.to_bitboard()).is_empty()
+    }
+
+    pub fn set(&mut self, idx: Square) {
+        *self |= idx.to_bitboard();
+    }
 
-                if idx % 8 == 7 {
-                    s.push_str(&format!("{}\n", rank + 1));
-                }
+    pub fn unset(&mut self, idx: Square) {
+        *self &= !idx.to_bitboard();
+    }
+
+    /// The lowest square in the set, which must not be empty
+    pub fn last_bit(self) -> Square {
+        let bits = self.0;
+        Square::from_index(
+            INDEX64[((bits & !bits.wrapping_sub(1)).wrapping_mul(DEBRUIJN64) >> 58) as usize],
+        )
+    }
+
+    /// The low 64 bits of the product, as magic indexing wants them
+    pub fn trimmed_mul(self, other: u64) -> u64 {
+        self.0.wrapping_mul(other)
+    }
+
+    pub fn bit_pos_iter(self) -> Squares {
+        Squares(self)
+    }
+
+    pub fn set_occupancy(self, index: usize, bits_in_mask: u8) -> Bitboard {
+        let mut this = self;
+        let mut occupancy = Bitboard::EMPTY;
+
+        for bit in 0..bits_in_mask {
+            let popped = this.pop_bit();
+
+            if index & (1 << bit) != 0 {
+                occupancy |= popped.to_bitboard();
             }
         }
 
-        s.push_str("  a b c d e f g h\n");
-
-        return s;
+        occupancy
     }
 }
 
-/// Little-endian rank-file mapping.
-/// See the [Chess Programming Wiki](https://www.chessprogramming.org/Square_Mapping_Considerations#Little-Endian_Rank-File_Mapping).
-pub type Bitboard = u64;
+/// The squares of a bitboard, lowest first
+pub struct Squares(Bitboard);
 
-impl const BitboardU64 for Bitboard {
-    const EMPTY: Bitboard = 0;
-    const FILE_A: Self = 0x0101010101010101u64;
-    const RANK_1: Self = 0xffu64;
+impl Iterator for Squares {
+    type Item = Square;
 
-    fn clear_bit(&mut self, idx: u8) {
-        *self &= !(1 << idx);
+    fn next(&mut self) -> Option<Square> {
+        (!self.0.is_empty()).then(|| self.0.pop_bit())
     }
 
-    fn pop_bit(&mut self) -> Square {
-        let sq = self.last_bit();
-        self.clear_bit(sq);
-
-        return sq;
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let len = self.0.count_ones() as usize;
+        (len, Some(len))
     }
+}
+
+impl ExactSizeIterator for Squares {}
 
-    fn count_bits(&self) -> u8 {
-        return self.count_ones() as u8;
+impl IntoIterator for Bitboard {
+    type Item = Square;
+    type IntoIter = Squares;
+
+    fn into_iter(self) -> Squares {
+        Squares(self)
     }
+}
 
-    fn at(&self, idx: u8) -> bool {
-        return self & (1 << idx) != 0;
+impl FromIterator<Square> for Bitboard {
+    fn from_iter<I: IntoIterator<Item = Square>>(iter: I) -> Self {
+        iter.into_iter()
+            .fold(Bitboard::EMPTY, |bb, sq| bb | sq.to_bitboard())
     }
+}
+
+macro_rules! bit_op {
+    ($op:ident, $fn:ident, $assign:ident, $assign_fn:ident) => {
+        impl $op for Bitboard {
+            type Output = Bitboard;
+
+            fn $fn(self, rhs: Bitboard) -> Bitboard {
+                Bitboard(self.0.$fn(rhs.0))
+            }
+        }
+
+        impl $assign for Bitboard {
+            fn $assign_fn(&mut self, rhs: Bitboard) {
+                self.0.$assign_fn(rhs.0);
+            }
+        }
+    };
+}
+
+bit_op!(BitAnd, bitand, BitAndAssign, bitand_assign);
+bit_op!(BitOr, bitor, BitOrAssign, bitor_assign);
+bit_op!(BitXor, bitxor, BitXorAssign, bitxor_assign);
 
-    fn set(&mut self, idx: u8) {
-        *self |= 1 << idx;
+impl Not for Bitboard {
+    type Output = Bitboard;
+
+    fn not(self) -> Bitboard {
+        Bitboard(!self.0)
     }
+}
+
+/// Shifting everything off the board leaves it empty instead of panicking
+impl Shl<u8> for Bitboard {
+    type Output = Bitboard;
 
-    fn unset(&mut self, idx: u8) {
-        *self &= !(1 << idx);
+    fn shl(self, rhs: u8) -> Bitboard {
+        Bitboard(self.0.checked_shl(rhs as u32).unwrap_or(0))
     }
+}
+
+impl Shr<u8> for Bitboard {
+    type Output = Bitboard;
 
-    fn last_bit(&self) -> u8 {
-        INDEX64[(((self & !self.wrapping_sub(1)).wrapping_mul(DEBRUIJN64)) >> 58) as usize]
+    fn shr(self, rhs: u8) -> Bitboard {
+        Bitboard(self.0.checked_shr(rhs as u32).unwrap_or(0))
     }
+}
 
-    fn trimmed_mul(&self, other: Self) -> Self {
-        let thisu128 = *self as u128;
-        let otheru128 = other as u128;
-        let product = thisu128 * otheru128;
-        let trim64 = product & u64::MAX as u128;
+impl ShlAssign<u8> for Bitboard {
+    fn shl_assign(&mut self, rhs: u8) {
+        *self = *self << rhs;
+    }
+}
 
-        return trim64 as u64;
+impl ShrAssign<u8> for Bitboard {
+    fn shr_assign(&mut self, rhs: u8) {
+        *self = *self >> rhs;
     }
+}
 
-    fn safe_shl(&self, other: u8) -> Self {
-        let thisu128 = *self as u128;
-        let shifted = thisu128 << other;
-        let trim64 = shifted & u64::MAX as u128;
+impl From<Square> for Bitboard {
+    fn from(sq: Square) -> Self {
+        sq.to_bitboard()
+    }
+}
 
-        return trim64 as u64;
+impl fmt::LowerHex for Bitboard {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::LowerHex::fmt(&self.0, f)
     }
+}
 
-    fn set_occupancy(&self, index: usize, bits_in_mask: u8) -> Bitboard {
-        let mut this = *self;
-        let mut occupancy = 0u64;
+/// The board with an X on every square in the set
+impl fmt::Display for Bitboard {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "\n  a b c d e f g h")?;
 
-        for bit in 0..bits_in_mask {
-            let popped = this.pop_bit();
+        for rank in Rank::ALL.into_iter().rev() {
+            write!(f, "{rank} ")?;
 
-            if index & (1 << bit) != 0 {
-                occupancy |= popped.to_bitboard();
+            for file in File::ALL {
+                let mark = if self.at(Square::new(rank, file)) {
+                    "X"
+                } else {
+                    "."
+                };
+                write!(f, "{mark} ")?;
             }
+
+            writeln!(f, "{rank}")?;
         }
 
-        return occupancy;
+        writeln!(f, "  a b c d e f g h")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn squares(names: &[&str]) -> Vec<Square> {
+        names.iter().map(|name| name.parse().unwrap()).collect()
+    }
+
+    #[test]
+    fn iterates_lowest_square_first() {
+        let expected = squares(&["a1", "h1", "e4", "d5", "h8"]);
+        let bitboard: Bitboard = expected.iter().rev().copied().collect();
+
+        assert_eq!(bitboard.into_iter().collect::<Vec<_>>(), expected);
+        assert_eq!(bitboard.bit_pos_iter().len(), 5);
+    }
+
+    #[test]
+    fn iterates_nothing_when_empty() {
+        assert_eq!(Bitboard::EMPTY.into_iter().next(), None);
+        assert_eq!(Bitboard::EMPTY.bit_pos_iter().len(), 0);
+    }
+
+    #[test]
+    fn ranks_and_files() {
+        assert_eq!(
+            Bitboard::rank(Rank::Second).into_iter().collect::<Vec<_>>(),
+            squares(&["a2", "b2", "c2", "d2", "e2", "f2", "g2", "h2"])
+        );
+        assert_eq!(
+            Bitboard::file(File::C).into_iter().collect::<Vec<_>>(),
+            squares(&["c1", "c2", "c3", "c4", "c5", "c6", "c7", "c8"])
+        );
+        assert_eq!(Bitboard::from_iter(Square::every()), !Bitboard::EMPTY);
     }
 }
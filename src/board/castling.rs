@@ -1,18 +1,95 @@
+use crate::prelude::*;
+
+/// Who can still castle, as the file of the rook each side would castle
+/// with. Recording the file is what makes Chess960 work, where the rooks
+/// and king can start on any file
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 pub struct CastlingRights {
-    pub kingside_white: bool,
-    pub queenside_white: bool,
-    pub kingside_black: bool,
-    pub queenside_black: bool,
+    pub kingside_white: Option<File>,
+    pub queenside_white: Option<File>,
+    pub kingside_black: Option<File>,
+    pub queenside_black: Option<File>,
 }
 
 impl CastlingRights {
+    /// Every right, with the rooks in the corners
     pub fn new() -> Self {
         CastlingRights {
-            kingside_white: true,
-            queenside_white: true,
-            kingside_black: true,
-            queenside_black: true,
+            kingside_white: Some(File::H),
+            queenside_white: Some(File::A),
+            kingside_black: Some(File::H),
+            queenside_black: Some(File::A),
+        }
+    }
+
+    pub fn none() -> Self {
+        CastlingRights {
+            kingside_white: None,
+            queenside_white: None,
+            kingside_black: None,
+            queenside_black: None,
+        }
+    }
+
+    /// Whether either side can still castle either way
+    pub fn any(&self) -> bool {
+        self.flags().into_iter().any(|flag| flag)
+    }
+
+    /// White kingside, white queenside, black kingside, black queenside
+    pub fn flags(&self) -> [bool; 4] {
+        [
+            self.kingside_white.is_some(),
+            self.queenside_white.is_some(),
+            self.kingside_black.is_some(),
+            self.queenside_black.is_some(),
+        ]
+    }
+
+    /// The file of the rook `color` can castle with towards `kingside`
+    pub fn rook_file(&self, color: Color, kingside: bool) -> Option<File> {
+        *self.get(color, kingside)
+    }
+
+    pub fn set(&mut self, color: Color, kingside: bool, rook_file: Option<File>) {
+        *self.get_mut(color, kingside) = rook_file;
+    }
+
+    /// Forget both rights of `color`, once its king has moved
+    pub fn remove_color(&mut self, color: Color) {
+        self.set(color, true, None);
+        self.set(color, false, None);
+    }
+
+    /// Forget the right that castles with the rook on `square`, once it has
+    /// moved or been taken
+    pub fn remove_rook(&mut self, color: Color, square: Square) {
+        if square.rank() != Rank::First.relative(color) {
+            return;
+        }
+
+        for kingside in [true, false] {
+            if self.rook_file(color, kingside) == Some(square.file()) {
+                self.set(color, kingside, None);
+            }
+        }
+    }
+
+    fn get(&self, color: Color, kingside: bool) -> &Option<File> {
+        match (color, kingside) {
+            (Color::White, true) => &self.kingside_white,
+            (Color::White, false) => &self.queenside_white,
+            (Color::Black, true) => &self.kingside_black,
+            (Color::Black, false) => &self.queenside_black,
+        }
+    }
+
+    fn get_mut(&mut self, color: Color, kingside: bool) -> &mut Option<File> {
+        match (color, kingside) {
+            (Color::White, true) => &mut self.kingside_white,
+            (Color::White, false) => &mut self.queenside_white,
+            (Color::Black, true) => &mut self.kingside_black,
+            (Color::Black, false) => &mut self.queenside_black,
         }
     }
 }
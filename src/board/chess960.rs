@@ -0,0 +1,92 @@
+// Chess960 starting positions, numbered 0 to 959 as Scharnagl did
+//
+// The white pieces are placed in a fixed order, each choice taking a digit
+// of the number: the light-squared bishop, the dark-squared bishop, the
+// queen, then both knights. The king goes between the two rooks on the
+// squares left over, and black mirrors white.
+use crate::prelude::{gui::PieceType, *};
+
+/// How many starting positions there are
+pub const POSITIONS: u16 = 960;
+
+/// Files of both knights, among the five squares left after the bishops and queen
+const KNIGHTS: [(usize, usize); 10] = [
+    (0, 1),
+    (0, 2),
+    (0, 3),
+    (0, 4),
+    (1, 2),
+    (1, 3),
+    (1, 4),
+    (2, 3),
+    (2, 4),
+    (3, 4),
+];
+
+/// The white back rank of the starting position `index`
+fn back_rank(index: u16) -> [PieceType; 8] {
+    let mut rank = [None; 8];
+    let mut n = index as usize;
+
+    // a1 is dark, so light squares are on odd files
+    rank[2 * (n % 4) + 1] = Some(PieceType::Bishop);
+    n /= 4;
+
+    rank[2 * (n % 4)] = Some(PieceType::Bishop);
+    n /= 4;
+
+    let place = |rank: &mut [Option<PieceType>; 8], nth: usize, piece: PieceType| {
+        let file = (0..8).filter(|&f| rank[f].is_none()).nth(nth).unwrap();
+        rank[file] = Some(piece);
+    };
+
+    place(&mut rank, n % 6, PieceType::Queen);
+    n /= 6;
+
+    // the second knight goes first, so the first one's index doesn't move
+    let (first, second) = KNIGHTS[n];
+    place(&mut rank, second, PieceType::Knight);
+    place(&mut rank, first, PieceType::Knight);
+
+    for piece in [PieceType::Rook, PieceType::King, PieceType::Rook] {
+        place(&mut rank, 0, piece);
+    }
+
+    rank.map(Option::unwrap)
+}
+
+impl Position {
+    /// Chess960 starting position number `index`, `None` past 959
+    pub fn from_960_index(index: u16) -> Option<Self> {
+        if index >= POSITIONS {
+            return None;
+        }
+
+        let mut position = Position::empty();
+
+        for (file, kind) in File::ALL.into_iter().zip(back_rank(index)) {
+            for color in [Color::White, Color::Black] {
+                let rank = Rank::First.relative(color);
+                let pawn_rank = Rank::Second.relative(color);
+
+                position.put(Square::new(rank, file), gui::Piece { color, kind });
+                position.put(
+                    Square::new(pawn_rank, file),
+                    gui::Piece {
+                        color,
+                        kind: PieceType::Pawn,
+                    },
+                );
+            }
+        }
+
+        let rooks = (position.rooks & Bitboard::rank(Rank::First)).bit_pos_iter().collect_vec();
+
+        for color in [Color::White, Color::Black] {
+            position.castling_rights.set(color, false, Some(rooks[0].file()));
+            position.castling_rights.set(color, true, Some(rooks[1].file()));
+        }
+
+        Some(position)
+    }
+}
@@ -0,0 +1,375 @@
+use core::fmt;
+
+use super::validate::PositionError;
+use crate::prelude::{gui::PieceType, *};
+
+pub const STARTPOS: &str = "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1";
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum FenError {
+    MissingField(&'static str),
+    InvalidPiece(char),
+    InvalidRank(usize),
+    /// The placement doesn't have 8 ranks, but this many
+    RankCount(usize),
+    InvalidTurn(String),
+    InvalidCastling(String),
+    InvalidEnPassant(String),
+    /// Parsed fine, but not a position that can come up in a game
+    Invalid(PositionError),
+}
+
+impl fmt::Display for FenError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            FenError::MissingField(field) => write!(f, "missing {field}"),
+            FenError::InvalidPiece(c) => write!(f, "invalid piece '{c}'"),
+            FenError::InvalidRank(rank) => write!(f, "rank {rank} does not have 8 squares"),
+            FenError::RankCount(count) => write!(f, "{count} ranks instead of 8"),
+            FenError::InvalidTurn(s) => write!(f, "invalid side to move '{s}'"),
+            FenError::InvalidCastling(s) => write!(f, "invalid castling rights '{s}'"),
+            FenError::InvalidEnPassant(s) => write!(f, "invalid en passant square '{s}'"),
+            FenError::Invalid(e) => write!(f, "{e}"),
+        }
+    }
+}
+
+impl std::error::Error for FenError {}
+
+pub fn piece_from_char(c: char) -> Option<PieceType> {
+    match c.to_ascii_lowercase() {
+        'p' => Some(PieceType::Pawn),
+        'n' => Some(PieceType::Knight),
+        'b' => Some(PieceType::Bishop),
+        'r' => Some(PieceType::Rook),
+        'q' => Some(PieceType::Queen),
+        'k' => Some(PieceType::King),
+        _ => None,
+    }
+}
+
+fn piece_to_char(piece: gui::Piece) -> char {
+    let c = match piece.kind {
+        PieceType::Pawn => 'p',
+        PieceType::Knight => 'n',
+        PieceType::Bishop => 'b',
+        PieceType::Rook => 'r',
+        PieceType::Queen => 'q',
+        PieceType::King => 'k',
+    };
+
+    match piece.color {
+        Color::White => c.to_ascii_uppercase(),
+        Color::Black => c,
+    }
+}
+
+impl Position {
+    /// An empty board, white to move, without castling rights
+    pub fn empty() -> Self {
+        Position {
+            n_white: Bitboard::EMPTY,
+            n_black: Bitboard::EMPTY,
+            pawns: Bitboard::EMPTY,
+            knights: Bitboard::EMPTY,
+            bishops: Bitboard::EMPTY,
+            rooks: Bitboard::EMPTY,
+            queens: Bitboard::EMPTY,
+            kings: Bitboard::EMPTY,
+
+            turn: Color::White,
+            ep_target: None,
+
+            castling_rights: CastlingRights::none(),
+        }
+    }
+
+    /// Parse a position from Forsyth-Edwards Notation
+    ///
+    /// The halfmove clock and fullmove number are optional and ignored. The
+    /// position is checked with [`Position::validate`]
+    pub fn from_fen(fen: &str) -> Result<Self, FenError> {
+        let position = Self::parse_fen(fen, true)?;
+        position.validate().map_err(FenError::Invalid)?;
+
+        Ok(position)
+    }
+
+    /// Parse a position without checking it can come up in a game, for
+    /// setups still being edited. Castling rights without their king and
+    /// rook in place are dropped instead of refused
+    pub fn from_fen_unchecked(fen: &str) -> Result<Self, FenError> {
+        Self::parse_fen(fen, false)
+    }
+
+    fn parse_fen(fen: &str, strict: bool) -> Result<Self, FenError> {
+        let mut fields = fen.split_whitespace();
+        let mut position = Position::empty();
+
+        let placement = fields.next().ok_or(FenError::MissingField("placement"))?;
+
+        let rank_count = placement.split('/').count();
+
+        if rank_count != 8 {
+            return Err(FenError::RankCount(rank_count));
+        }
+
+        for (i, rank_str) in placement.split('/').enumerate() {
+            let rank = Rank::from_index(7 - i as u8);
+            let mut file = 0u8;
+
+            for c in rank_str.chars() {
+                if let Some(skip) = c.to_digit(10) {
+                    if !(1..=8).contains(&skip) {
+                        return Err(FenError::InvalidPiece(c));
+                    }
+
+                    file = file
+                        .checked_add(skip as u8)
+                        .filter(|&file| file <= 8)
+                        .ok_or(FenError::InvalidRank(8 - i))?;
+                    continue;
+                }
+
+                if file > 7 {
+                    return Err(FenError::InvalidRank(8 - i));
+                }
+
+                let kind = piece_from_char(c).ok_or(FenError::InvalidPiece(c))?;
+                let color = if c.is_ascii_uppercase() {
+                    Color::White
+                } else {
+                    Color::Black
+                };
+
+                let square = Square::new(rank, File::from_index(file));
+                position.put(square, gui::Piece { color, kind });
+                file += 1;
+            }
+
+            if file != 8 {
+                return Err(FenError::InvalidRank(8 - i));
+            }
+        }
+
+        let turn = fields.next().ok_or(FenError::MissingField("side to move"))?;
+
+        position.turn = match turn {
+            "w" => Color::White,
+            "b" => Color::Black,
+            _ => return Err(FenError::InvalidTurn(turn.to_string())),
+        };
+
+        let castling = fields.next().unwrap_or("-");
+
+        if castling != "-" {
+            for c in castling.chars() {
+                let parsed = position.parse_castling(c);
+
+                if parsed.is_none() && (strict || !c.is_ascii_alphabetic()) {
+                    return Err(FenError::InvalidCastling(castling.to_string()));
+                }
+            }
+        }
+
+        let ep_target = fields.next().unwrap_or("-");
+
+        if ep_target != "-" {
+            let square = ep_target
+                .parse()
+                .map_err(|_| FenError::InvalidEnPassant(ep_target.to_string()))?;
+
+            position.ep_target = Some(square);
+        }
+
+        Ok(position)
+    }
+
+    /// Write the position as Forsyth-Edwards Notation
+    pub fn fen(&self) -> String {
+        let mut fen = String::new();
+
+        for rank in Rank::ALL.into_iter().rev() {
+            let mut empty = 0;
+
+            for file in File::ALL {
+                match self.full_piece_at(Square::new(rank, file)) {
+                    Some(piece) => {
+                        if empty > 0 {
+                            fen.push_str(&empty.to_string());
+                            empty = 0;
+                        }
+
+                        fen.push(piece_to_char(piece));
+                    }
+                    None => empty += 1,
+                }
+            }
+
+            if empty > 0 {
+                fen.push_str(&empty.to_string());
+            }
+
+            if rank != Rank::First {
+                fen.push('/');
+            }
+        }
+
+        fen.push_str(match self.turn {
+            Color::White => " w ",
+            Color::Black => " b ",
+        });
+
+        let mut castling = String::new();
+
+        for (color, kingside) in [
+            (Color::White, true),
+            (Color::White, false),
+            (Color::Black, true),
+            (Color::Black, false),
+        ] {
+            if let Some(c) = self.castling_char(color, kingside) {
+                castling.push(c);
+            }
+        }
+
+        if castling.is_empty() {
+            castling.push('-');
+        }
+
+        fen.push_str(&castling);
+        fen.push(' ');
+
+        match self.ep_target {
+            Some(square) => fen.push_str(&square.to_string()),
+            None => fen.push('-'),
+        }
+
+        fen.push_str(" 0 1");
+
+        fen
+    }
+
+    /// Files of the rooks of `color` on its back rank, and the king's file
+    fn back_rank(&self, color: Color) -> Option<(File, Vec<File>)> {
+        let own = self.pieces_of_col(color) & Bitboard::rank(Rank::First.relative(color));
+        let king = (self.kings & own).bit_pos_iter().next()?;
+        let rooks = (self.rooks & own).bit_pos_iter().map(|sq| sq.file()).collect();
+
+        Some((king.file(), rooks))
+    }
+
+    /// Add one castling right, either `KQkq` for the outermost rook as X-FEN
+    /// has it, or the rook's file as in Shredder-FEN
+    pub(crate) fn parse_castling(&mut self, c: char) -> Option<()> {
+        let color = if c.is_ascii_uppercase() {
+            Color::White
+        } else {
+            Color::Black
+        };
+
+        let (king_file, rooks) = self.back_rank(color)?;
+
+        let (kingside, rook_file) = match c.to_ascii_lowercase() {
+            'k' => (true, rooks.into_iter().filter(|&f| f > king_file).max()?),
+            'q' => (false, rooks.into_iter().filter(|&f| f < king_file).min()?),
+            c => {
+                let file = File::from_char(c)?;
+
+                if !rooks.contains(&file) || file == king_file {
+                    return None;
+                }
+
+                (file > king_file, file)
+            }
+        };
+
+        self.castling_rights.set(color, kingside, Some(rook_file));
+
+        Some(())
+    }
+
+    /// `KQkq` when the right is with the outermost rook, which is always the
+    /// case outside Chess960, otherwise the rook's file
+    fn castling_char(&self, color: Color, kingside: bool) -> Option<char> {
+        let rook_file = self.castling_rights.rook_file(color, kingside)?;
+
+        let outermost = self.back_rank(color).is_none_or(|(king_file, rooks)| {
+            rooks.into_iter().all(|f| {
+                if kingside {
+                    f <= king_file || f <= rook_file
+                } else {
+                    f >= king_file || f >= rook_file
+                }
+            })
+        });
+
+        let c = match (outermost, kingside) {
+            (true, true) => 'k',
+            (true, false) => 'q',
+            (false, _) => (b'a' + rook_file.index()) as char,
+        };
+
+        Some(match color {
+            Color::White => c.to_ascii_uppercase(),
+            Color::Black => c,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn needs_eight_ranks() {
+        assert_eq!(
+            Position::from_fen("4k3/8/4K3 w - - 0 1").err(),
+            Some(FenError::RankCount(3))
+        );
+        assert_eq!(
+            Position::from_fen("4k3/8/8/8/8/8/8/4K3/8 w - - 0 1").err(),
+            Some(FenError::RankCount(9))
+        );
+    }
+
+    #[test]
+    fn ranks_need_eight_files() {
+        assert_eq!(
+            Position::from_fen("4k3/8/8/8/8/8/7/4K3 w - - 0 1").err(),
+            Some(FenError::InvalidRank(2))
+        );
+        assert_eq!(
+            Position::from_fen("4k3/8/8/44p/8/8/8/4K3 w - - 0 1").err(),
+            Some(FenError::InvalidRank(5))
+        );
+    }
+
+    #[test]
+    fn skips_are_one_to_eight() {
+        assert_eq!(
+            Position::from_fen("4k3/8/8/08/8/8/8/4K3 w - - 0 1").err(),
+            Some(FenError::InvalidPiece('0'))
+        );
+        assert_eq!(
+            Position::from_fen("4k3/8/8/9/8/8/8/4K3 w - - 0 1").err(),
+            Some(FenError::InvalidPiece('9'))
+        );
+    }
+
+    #[test]
+    fn long_skips_dont_overflow() {
+        let rank = "8".repeat(40);
+        let fen = format!("4k3/8/8/{rank}/8/8/8/4K3 w - - 0 1");
+
+        assert_eq!(
+            Position::from_fen(&fen).err(),
+            Some(FenError::InvalidRank(5))
+        );
+    }
+
+    #[test]
+    fn round_trips() {
+        assert_eq!(Position::from_fen(STARTPOS).unwrap().fen(), STARTPOS);
+    }
+}
@@ -1,5 +1,11 @@
 pub mod bitboard;
 pub mod castling;
+pub mod chess960;
 pub mod color;
+pub mod fen;
+pub mod moves;
 pub mod position;
+pub mod san;
 pub mod square;
+pub mod validate;
+pub mod zobrist;
@@ -0,0 +1,127 @@
+use core::fmt;
+
+use crate::prelude::{gui::PieceType, *};
+
+/// A move in long algebraic notation, like UCI uses
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Move {
+    pub from: Square,
+    pub to: Square,
+    pub promotion: Option<PieceType>,
+}
+
+impl Move {
+    /// A move that isn't a promotion
+    pub fn new(from: Square, to: Square) -> Self {
+        Move {
+            from,
+            to,
+            promotion: None,
+        }
+    }
+}
+
+impl fmt::Display for Move {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}{}", self.from, self.to)?;
+
+        match self.promotion {
+            Some(PieceType::Knight) => write!(f, "n"),
+            Some(PieceType::Bishop) => write!(f, "b"),
+            Some(PieceType::Rook) => write!(f, "r"),
+            Some(PieceType::Queen) => write!(f, "q"),
+            _ => Ok(()),
+        }
+    }
+}
+
+impl Position {
+    /// Every legal move for the side to move
+    pub fn legal_moves(&self) -> Vec<Move> {
+        let mut moves = vec![];
+
+        for from in self.pieces_of_turn().bit_pos_iter() {
+            let promoting =
+                self.pawns.at(from) && matches!(from.rank(), Rank::Second | Rank::Seventh);
+
+            for to in self.moves_of(from).bit_pos_iter() {
+                if promoting && matches!(to.rank(), Rank::First | Rank::Eighth) {
+                    for promotion in [
+                        PieceType::Queen,
+                        PieceType::Knight,
+                        PieceType::Rook,
+                        PieceType::Bishop,
+                    ] {
+                        moves.push(Move {
+                            from,
+                            to,
+                            promotion: Some(promotion),
+                        });
+                    }
+                } else {
+                    moves.push(Move::new(from, to));
+                }
+            }
+        }
+
+        moves
+    }
+
+    /// Make a move, promoting to a queen if the move doesn't say otherwise
+    pub fn play(&mut self, mv: Move) {
+        if let Some(promote) = self.make_move(mv.from, mv.to) {
+            promote(self, mv.promotion.unwrap_or(PieceType::Queen));
+        }
+    }
+
+    /// Pass, for null-move pruning. Never legal, and never while in check
+    pub fn play_null(&mut self) {
+        self.turn.swap();
+        self.ep_target = None;
+    }
+
+    /// Whether the move captures a piece, including en passant. Castling
+    /// moves onto a friendly rook, so isn't one
+    pub fn is_capture(&self, mv: Move) -> bool {
+        self.pieces_of_col(self.turn.other()).at(mv.to)
+            || (self.pawns.at(mv.from) && self.ep_target == Some(mv.to))
+    }
+
+    /// The move as UCI writes it. Castling is the king taking its own rook
+    /// in Chess960, and the king moving two squares otherwise
+    pub fn to_uci(&self, mv: Move, chess960: bool) -> String {
+        if chess960 || !self.is_castle(mv) {
+            return mv.to_string();
+        }
+
+        let file = if mv.to.file() > mv.from.file() {
+            File::G
+        } else {
+            File::C
+        };
+
+        Move::new(mv.from, Square::new(mv.from.rank(), file)).to_string()
+    }
+
+    /// A line of moves played one after another from this position, each
+    /// written like [`Position::to_uci`] does, separated by spaces
+    pub fn line_to_uci(&self, line: &[Move], chess960: bool) -> String {
+        let mut position = *self;
+
+        line.iter()
+            .map(|&mv| {
+                let uci = position.to_uci(mv, chess960);
+                position.play(mv);
+
+                uci
+            })
+            .join(" ")
+    }
+
+    /// Find the legal move written in UCI notation
+    pub fn parse_uci(&self, s: &str, chess960: bool) -> Option<Move> {
+        self.legal_moves()
+            .into_iter()
+            .find(|&mv| self.to_uci(mv, chess960) == s)
+    }
+}
@@ -1,6 +1,4 @@
-use core::fmt;
-
-use crate::{movegen, prelude::*};
+use crate::{board::zobrist, movegen, prelude::*};
 
 #[derive(Clone, Copy)]
 pub enum SpecialMoveType {
@@ -11,6 +9,20 @@ pub enum SpecialMoveType {
     CastleKing,
 }
 
+/// One square back towards `color`'s side, where a pawn of `color` that
+/// just moved two squares passed
+fn behind(color: Color) -> Offset {
+    let rank = match color {
+        Color::White => -1,
+        Color::Black => 1,
+    };
+
+    Offset { rank, file: 0 }
+}
+
+/// A chess position: one bitboard per color and per piece type, plus the
+/// side to move, en passant square and castling rights. Cheap to copy, so
+/// moves are made on a copy rather than undone
 #[derive(Clone, Copy, Hash, PartialEq, Eq)]
 pub struct Position {
     pub n_white: Bitboard,
@@ -29,18 +41,19 @@ pub struct Position {
 }
 
 impl Position {
+    /// The standard starting position
     pub fn new() -> Self {
-        let n_white = Bitboard::rank(0) | Bitboard::rank(1);
-        let n_black = Bitboard::rank(6) | Bitboard::rank(7);
-        let backranks = Bitboard::rank(0) | Bitboard::rank(7);
+        let n_white = Bitboard::rank(Rank::First) | Bitboard::rank(Rank::Second);
+        let n_black = Bitboard::rank(Rank::Seventh) | Bitboard::rank(Rank::Eighth);
+        let backranks = Bitboard::rank(Rank::First) | Bitboard::rank(Rank::Eighth);
 
-        let rooks = backranks & (Bitboard::file(0) | Bitboard::file(7));
-        let knights = backranks & (Bitboard::file(1) | Bitboard::file(6));
-        let bishops = backranks & (Bitboard::file(2) | Bitboard::file(5));
-        let queens = backranks & Bitboard::file(3);
-        let kings = backranks & Bitboard::file(4);
+        let rooks = backranks & (Bitboard::file(File::A) | Bitboard::file(File::H));
+        let knights = backranks & (Bitboard::file(File::B) | Bitboard::file(File::G));
+        let bishops = backranks & (Bitboard::file(File::C) | Bitboard::file(File::F));
+        let queens = backranks & Bitboard::file(File::D);
+        let kings = backranks & Bitboard::file(File::E);
 
-        let pawns = Bitboard::rank(1) | Bitboard::rank(6);
+        let pawns = Bitboard::rank(Rank::Second) | Bitboard::rank(Rank::Seventh);
 
         Position {
             n_white,
@@ -59,14 +72,17 @@ impl Position {
         }
     }
 
+    /// Every square with a piece on it
     pub fn occupied(&self) -> Bitboard {
         self.n_white | self.n_black
     }
 
+    /// Every piece that isn't a pawn or king, of both colors
     pub fn pieces(&self) -> Bitboard {
         self.rooks | self.knights | self.bishops | self.queens
     }
 
+    /// The type of the piece on `square`, of either color
     pub fn piece_at(&self, square: Square) -> Option<gui::PieceType> {
         if self.pawns & square.to_bitboard() != Bitboard::EMPTY {
             return Some(gui::PieceType::Pawn);
@@ -101,13 +117,15 @@ impl Position {
         None
     }
 
-    fn full_piece_at(&self, square: Square) -> Option<gui::Piece> {
+    /// The piece on `square`, with its color
+    pub fn full_piece_at(&self, square: Square) -> Option<gui::Piece> {
         let color = self.color_at(square)?;
         let kind = self.piece_at(square)?;
 
         Some(gui::Piece { color, kind })
     }
 
+    /// What is on every square, from a1 to h8
     pub fn collect(&self) -> Vec<Option<gui::Piece>> {
         let mut pieces = vec![];
 
@@ -125,13 +143,15 @@ impl Position {
         }
     }
 
-    fn pieces_of_col(&self, color: Color) -> Bitboard {
+    /// Every piece of `color`
+    pub fn pieces_of_col(&self, color: Color) -> Bitboard {
         match color {
             Color::White => self.n_white,
             Color::Black => self.n_black,
         }
     }
 
+    /// Every piece of the side to move
     pub fn pieces_of_turn(&self) -> Bitboard {
         self.pieces_of_col(self.turn)
     }
@@ -147,6 +167,82 @@ impl Position {
         }
     }
 
+    /// Remove whatever is on `square`
+    pub fn clear(&mut self, square: Square) {
+        let mask = !square.to_bitboard();
+
+        for piece_type in gui::PieceType::every() {
+            *self.pieces_of_type_mut(piece_type) &= mask;
+        }
+
+        self.n_white &= mask;
+        self.n_black &= mask;
+    }
+
+    /// Place `piece` on `square`, replacing whatever was there
+    pub fn put(&mut self, square: Square, piece: gui::Piece) {
+        self.clear(square);
+
+        *self.pieces_of_type_mut(piece.kind) |= square.to_bitboard();
+        *self.pieces_of_col_mut(piece.color) |= square.to_bitboard();
+    }
+
+    /// Every piece of `piece_type`, of both colors
+    pub fn pieces_of_type(&self, piece_type: gui::PieceType) -> Bitboard {
+        match piece_type {
+            gui::PieceType::Pawn => self.pawns,
+            gui::PieceType::Knight => self.knights,
+            gui::PieceType::Bishop => self.bishops,
+            gui::PieceType::Rook => self.rooks,
+            gui::PieceType::Queen => self.queens,
+            gui::PieceType::King => self.kings,
+        }
+    }
+
+    fn zobrist_of(&self, piece_type: gui::PieceType) -> u64 {
+        let mut key = 0;
+
+        for color in [Color::White, Color::Black] {
+            let pieces = self.pieces_of_type(piece_type) & self.pieces_of_col(color);
+
+            for square in pieces.bit_pos_iter() {
+                key ^= zobrist::piece(color as usize, piece_type as usize, square.index());
+            }
+        }
+
+        key
+    }
+
+    /// Zobrist key of the pawns only, for the pawn hash table
+    pub fn pawn_key(&self) -> u64 {
+        self.zobrist_of(gui::PieceType::Pawn)
+    }
+
+    /// Zobrist key of the whole position
+    pub fn hash_key(&self) -> u64 {
+        let mut key = 0;
+
+        for piece_type in gui::PieceType::every() {
+            key ^= self.zobrist_of(piece_type);
+        }
+
+        for (i, flag) in self.castling_rights.flags().into_iter().enumerate() {
+            if flag {
+                key ^= zobrist::CASTLING[i];
+            }
+        }
+
+        if let Some(ep_target) = self.ep_target {
+            key ^= zobrist::EN_PASSANT[ep_target.file().index() as usize];
+        }
+
+        if self.turn == Color::Black {
+            key ^= zobrist::BLACK_TO_MOVE;
+        }
+
+        key
+    }
+
     fn try_find_special(&self, from: Square, to: Square) -> Option<SpecialMoveType> {
         let piece = self.piece_at(from)?;
 
@@ -156,21 +252,19 @@ impl Position {
                     Some(SpecialMoveType::PawnDouble)
                 } else if self.ep_target.is_some_and(|a| a == to) {
                     Some(SpecialMoveType::EnPassant)
-                } else if to.rank() == 0 || to.rank() == 7 {
+                } else if matches!(to.rank(), Rank::First | Rank::Eighth) {
                     Some(SpecialMoveType::Promo)
                 } else {
                     None
                 }
             }
             gui::PieceType::King => {
-                if from.distance_to(to) == 2 {
-                    if from.file() > to.file() {
-                        Some(SpecialMoveType::CastleQueen)
-                    } else {
-                        Some(SpecialMoveType::CastleKing)
-                    }
-                } else {
+                if !self.is_castle(Move::new(from, to)) {
                     None
+                } else if to.file() > from.file() {
+                    Some(SpecialMoveType::CastleKing)
+                } else {
+                    Some(SpecialMoveType::CastleQueen)
                 }
             }
             _ => None,
@@ -199,6 +293,12 @@ impl Position {
         Some(())
     }
 
+    /// Move the piece on `from` to `to` without checking that it's legal.
+    /// Castling is the king moving onto its own rook.
+    ///
+    /// Returns a function to pick the piece for a promotion, which has to be
+    /// called before anything else is done with the position. See
+    /// [`Position::play`] for the usual way to make a move
     pub fn make_move(
         &mut self,
         from: Square,
@@ -212,44 +312,23 @@ impl Position {
             return None;
         }
 
-        self._move(from, to)?;
+        match special {
+            // the king takes its own rook, then both go to their usual squares
+            Some(SpecialMoveType::CastleKing) => self.castle(from, to, File::G, File::F),
+            Some(SpecialMoveType::CastleQueen) => self.castle(from, to, File::C, File::D),
+            _ => self._move(from, to)?,
+        }
 
         self.turn.swap();
         self.ep_target = None;
 
         if let Some(special) = special {
             match special {
-                SpecialMoveType::CastleKing => {
-                    let rook_from = Square::new(to.rank(), 7);
-                    let rook_to = Square::new(to.rank(), 5);
-
-                    self._move(rook_from, rook_to)?;
-                }
-                SpecialMoveType::CastleQueen => {
-                    let rook_from = Square::new(to.rank(), 0);
-                    let rook_to = Square::new(to.rank(), 3);
-
-                    self._move(rook_from, rook_to)?;
-                }
                 SpecialMoveType::PawnDouble => {
-                    self.ep_target = Some(to.try_add(
-                        match color {
-                            Color::White => -1,
-                            Color::Black => 1,
-                        },
-                        0,
-                    )?);
+                    self.ep_target = Some(to.try_add(behind(color))?);
                 }
                 SpecialMoveType::EnPassant => {
-                    let kill_pawn = to
-                        .try_add(
-                            match color {
-                                Color::White => -1,
-                                Color::Black => 1,
-                            },
-                            0,
-                        )?
-                        .to_bitboard();
+                    let kill_pawn = to.try_add(behind(color))?.to_bitboard();
 
                     *self.pieces_of_type_mut(gui::PieceType::Pawn) &= !kill_pawn;
                     *self.pieces_of_col_mut(color.other()) &= !kill_pawn;
@@ -259,33 +338,16 @@ impl Position {
         }
 
         if piece == gui::PieceType::King {
-            if color == Color::White {
-                self.castling_rights.kingside_white = false;
-                self.castling_rights.queenside_white = false;
-            } else {
-                self.castling_rights.kingside_black = false;
-                self.castling_rights.queenside_black = false;
-            }
+            self.castling_rights.remove_color(color);
         }
 
         if piece == gui::PieceType::Rook {
-            if color == Color::White {
-                if from == Square::new(0, 0) {
-                    self.castling_rights.queenside_white = false;
-                }
-                if from == Square::new(0, 7) {
-                    self.castling_rights.kingside_white = false;
-                }
-            } else {
-                if from == Square::new(7, 0) {
-                    self.castling_rights.queenside_black = false;
-                }
-                if from == Square::new(7, 7) {
-                    self.castling_rights.kingside_black = false;
-                }
-            }
+            self.castling_rights.remove_rook(color, from);
         }
 
+        // a rook that is taken can't castle either
+        self.castling_rights.remove_rook(color.other(), to);
+
         match special {
             Some(SpecialMoveType::Promo) => Some(|this, piece_type| {
                 this.promote(piece_type);
@@ -294,8 +356,40 @@ impl Position {
         }
     }
 
+    /// Castle with the king on `king_from` and the rook on `rook_from`
+    fn castle(&mut self, king_from: Square, rook_from: Square, king_file: File, rook_file: File) {
+        let color = self.turn;
+        let rank = king_from.rank();
+
+        self.clear(king_from);
+        self.clear(rook_from);
+
+        self.put(
+            Square::new(rank, king_file),
+            gui::Piece {
+                color,
+                kind: gui::PieceType::King,
+            },
+        );
+        self.put(
+            Square::new(rank, rook_file),
+            gui::Piece {
+                color,
+                kind: gui::PieceType::Rook,
+            },
+        );
+    }
+
+    /// Whether the move is castling, which is the king moving onto its own
+    /// rook
+    pub fn is_castle(&self, mv: Move) -> bool {
+        let own = self.pieces_of_col(self.turn);
+
+        (self.kings & own).at(mv.from) && (self.rooks & own).at(mv.to)
+    }
+
     fn promote(&mut self, to: gui::PieceType) {
-        let promotable = self.pawns & (Bitboard::rank(0) | Bitboard::rank(7));
+        let promotable = self.pawns & (Bitboard::rank(Rank::First) | Bitboard::rank(Rank::Eighth));
 
         for bit in promotable.bit_pos_iter() {
             let bit = bit.to_bitboard();
@@ -306,8 +400,16 @@ impl Position {
 
         self.pawns &= !promotable;
     }
+
+    /// Whether the king of `color` is attacked, `false` if it has none
     pub fn in_check(&self, color: Color) -> bool {
         let king = self.kings & self.pieces_of_col(color);
+
+        // only while setting up a position, see `Position::validate`
+        if king.is_empty() {
+            return false;
+        }
+
         let square = king.last_bit();
         let occupied = self.n_black | self.n_white;
 
@@ -323,12 +425,36 @@ impl Position {
         let bishop_check = king_as_bishop & check_bishop & self.pieces_of_col(color.other());
         let knight_check = king_as_knight & self.knights & self.pieces_of_col(color.other());
         let pawn_check = king_as_pawn & self.pawns & self.pieces_of_col(color.other());
+        // never a check, but it keeps a king from moving next to the other
+        let king_check =
+            movegen::king_attacks(square) & self.kings & self.pieces_of_col(color.other());
 
-        let in_check = rook_check | bishop_check | knight_check | pawn_check;
+        let in_check = rook_check | bishop_check | knight_check | pawn_check | king_check;
 
         in_check != Bitboard::EMPTY
     }
 
+    /// Whether any piece of `by` attacks `square`
+    pub fn is_attacked(&self, square: Square, by: Color) -> bool {
+        let occupied = self.occupied();
+        let attackers = self.pieces_of_col(by);
+
+        let rook_like = (self.rooks | self.queens) & attackers;
+        let bishop_like = (self.bishops | self.queens) & attackers;
+
+        // a pawn of `by` attacks the square if a pawn of the other color
+        // on the square would attack it
+        let pawns = movegen::pawn_attacks(square.to_bitboard(), by.other());
+
+        let attacks = movegen::rook(square, occupied) & rook_like
+            | movegen::bishop(square, occupied) & bishop_like
+            | movegen::knight(square) & self.knights & attackers
+            | pawns & self.pawns & attackers
+            | movegen::king_attacks(square) & self.kings & attackers;
+
+        attacks != Bitboard::EMPTY
+    }
+
     fn filter_checks(&self, mut moves: Bitboard, from: Square) -> Bitboard {
         for to_square in moves.bit_pos_iter() {
             let mut this = *self;
@@ -343,6 +469,8 @@ impl Position {
         moves
     }
 
+    /// The legal destinations of the piece on `pos`, empty if it isn't the
+    /// side to move's
     pub fn moves_of(&self, pos: Square) -> Bitboard {
         let Some(color) = self.color_at(pos) else {
             return Bitboard::EMPTY;
@@ -360,7 +488,11 @@ impl Position {
             Some(gui::PieceType::Bishop) => movegen::bishop(pos, occupied),
             Some(gui::PieceType::Rook) => movegen::rook(pos, occupied),
             Some(gui::PieceType::Queen) => movegen::queen(pos, occupied),
-            Some(gui::PieceType::King) => movegen::king(pos, self.castling_rights, color, *self),
+            // castling is the king taking its own rook, so don't mask those
+            Some(gui::PieceType::King) => {
+                let moves = movegen::king(pos, self.castling_rights, color, *self);
+                return self.filter_checks(moves, pos);
+            }
             None => Bitboard::EMPTY,
         };
 
@@ -373,3 +505,32 @@ impl Default for Position {
         Self::new()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn kings_never_stand_next_to_each_other() {
+        let position = Position::from_fen("8/8/8/8/8/3k4/8/3K4 w - - 0 1").unwrap();
+        let black_king = Square::new(Rank::Third, File::D);
+
+        for mv in position.legal_moves() {
+            assert!(
+                !movegen::king_attacks(black_king).at(mv.to),
+                "{mv} moves next to the other king"
+            );
+        }
+
+        assert!(!position.legal_moves().is_empty());
+    }
+
+    #[test]
+    fn enemy_king_takes_away_the_last_moves() {
+        // e2 and g2 are next to the black king, e1 and g1 covered by the pawn
+        let position = Position::from_fen("8/8/8/8/8/5k2/5p2/5K2 w - - 0 1").unwrap();
+
+        assert!(!position.in_check(Color::White));
+        assert!(position.legal_moves().is_empty(), "stalemate");
+    }
+}
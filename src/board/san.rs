@@ -0,0 +1,73 @@
+use super::fen::piece_from_char;
+use crate::prelude::{gui::PieceType, *};
+
+impl Position {
+    /// Find the legal move written in Standard Algebraic Notation,
+    /// like `Nbd7`, `exd5`, `O-O` or `e8=Q+`
+    pub fn parse_san(&self, san: &str) -> Option<Move> {
+        let san = san.trim_end_matches(['+', '#', '!', '?']);
+        let moves = self.legal_moves();
+
+        let castle = match san {
+            "O-O" | "0-0" => Some(true),
+            "O-O-O" | "0-0-0" => Some(false),
+            _ => None,
+        };
+
+        if let Some(kingside) = castle {
+            return moves
+                .into_iter()
+                .find(|&mv| self.is_castle(mv) && (mv.to.file() > mv.from.file()) == kingside);
+        }
+
+        let (body, promotion) = match san.split_once('=') {
+            Some((body, promotion)) => (body, Some(piece_from_char(promotion.chars().next()?)?)),
+            None => match san.char_indices().last()? {
+                // `e8Q`, without the equals sign
+                (i, c) if "NBRQ".contains(c) && i > 0 => (&san[..i], piece_from_char(c)),
+                _ => (san, None),
+            },
+        };
+
+        let (piece, rest) = match body.chars().next()? {
+            c @ ('N' | 'B' | 'R' | 'Q' | 'K') => (piece_from_char(c)?, &body[1..]),
+            _ => (PieceType::Pawn, body),
+        };
+
+        let rest = rest.replace(['x', '-', ':'], "");
+
+        if rest.len() < 2 {
+            return None;
+        }
+
+        let to = rest[rest.len() - 2..].parse().ok()?;
+        let disambiguation = &rest[..rest.len() - 2];
+
+        let mut candidates = moves.into_iter().filter(|mv| {
+            let file_matches = disambiguation
+                .chars()
+                .filter_map(File::from_char)
+                .all(|file| mv.from.file() == file);
+
+            let rank_matches = disambiguation
+                .chars()
+                .filter_map(Rank::from_char)
+                .all(|rank| mv.from.rank() == rank);
+
+            mv.to == to
+                && mv.promotion == promotion
+                && self.piece_at(mv.from) == Some(piece)
+                && file_matches
+                && rank_matches
+        });
+
+        let mv = candidates.next()?;
+
+        // ambiguous
+        if candidates.next().is_some() {
+            return None;
+        }
+
+        Some(mv)
+    }
+}
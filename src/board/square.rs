@@ -1,76 +1,317 @@
-use std::ops::Range;
+use core::fmt;
+use std::str::FromStr;
 
 use crate::prelude::*;
 
-pub trait SquareU8: Sized + PartialEq + Copy {
-    fn new(rank: u8, file: u8) -> Self;
-    fn rank(&self) -> u8;
-    fn file(&self) -> u8;
-    fn valid_square(&self) -> bool;
-    fn to_bitboard(&self) -> Bitboard;
-    fn try_add(&self, other_rank: i8, other_file: i8) -> Option<Self>;
-    fn get(&self) -> u8;
-    fn every() -> impl Iterator<Item = Self>;
-
-    fn distance_to(&self, other: Self) -> u8 {
+/// A column of the board
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[repr(u8)]
+pub enum File {
+    A,
+    B,
+    C,
+    D,
+    E,
+    F,
+    G,
+    H,
+}
+
+/// A row of the board, from white's side
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[repr(u8)]
+pub enum Rank {
+    First,
+    Second,
+    Third,
+    Fourth,
+    Fifth,
+    Sixth,
+    Seventh,
+    Eighth,
+}
+
+impl File {
+    pub const ALL: [File; 8] = [
+        File::A,
+        File::B,
+        File::C,
+        File::D,
+        File::E,
+        File::F,
+        File::G,
+        File::H,
+    ];
+
+    /// File number 0-7, panics on anything else
+    pub const fn from_index(index: u8) -> Self {
+        Self::ALL[index as usize]
+    }
+
+    pub const fn index(self) -> u8 {
+        self as u8
+    }
+
+    /// The file `delta` files to the right, if it is on the board
+    pub fn offset(self, delta: i8) -> Option<Self> {
+        let index = self as i8 + delta;
+        (0..8)
+            .contains(&index)
+            .then(|| Self::from_index(index as u8))
+    }
+
+    /// The same file from the other side of the board, a for h
+    pub const fn flip(self) -> Self {
+        Self::from_index(7 - self as u8)
+    }
+
+    pub fn from_char(c: char) -> Option<Self> {
+        ('a'..='h')
+            .contains(&c)
+            .then(|| Self::from_index(c as u8 - b'a'))
+    }
+}
+
+impl Rank {
+    pub const ALL: [Rank; 8] = [
+        Rank::First,
+        Rank::Second,
+        Rank::Third,
+        Rank::Fourth,
+        Rank::Fifth,
+        Rank::Sixth,
+        Rank::Seventh,
+        Rank::Eighth,
+    ];
+
+    /// Rank number 0-7, panics on anything else
+    pub const fn from_index(index: u8) -> Self {
+        Self::ALL[index as usize]
+    }
+
+    pub const fn index(self) -> u8 {
+        self as u8
+    }
+
+    /// The rank `delta` ranks up, if it is on the board
+    pub fn offset(self, delta: i8) -> Option<Self> {
+        let index = self as i8 + delta;
+        (0..8)
+            .contains(&index)
+            .then(|| Self::from_index(index as u8))
+    }
+
+    /// The same rank from black's side, 1 for 8
+    pub const fn flip(self) -> Self {
+        Self::from_index(7 - self as u8)
+    }
+
+    /// This rank as seen by `color`, so the first rank is its back rank
+    pub const fn relative(self, color: Color) -> Self {
+        match color {
+            Color::White => self,
+            Color::Black => self.flip(),
+        }
+    }
+
+    pub fn from_char(c: char) -> Option<Self> {
+        ('1'..='8')
+            .contains(&c)
+            .then(|| Self::from_index(c as u8 - b'1'))
+    }
+}
+
+impl fmt::Display for File {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", (b'a' + *self as u8) as char)
+    }
+}
+
+impl fmt::Display for Rank {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", (b'1' + *self as u8) as char)
+    }
+}
+
+/// A step across the board. The fields are named so a rank can't be
+/// passed as a file by accident
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Offset {
+    pub rank: i8,
+    pub file: i8,
+}
+
+/// One of the 64 squares, numbered a1, b1, ..., h8
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[repr(transparent)]
+pub struct Square(u8);
+
+impl Square {
+    pub const fn new(rank: Rank, file: File) -> Self {
+        Square(rank as u8 * 8 + file as u8)
+    }
+
+    /// Square number 0-63, panics on anything else
+    pub const fn from_index(index: u8) -> Self {
+        assert!(index < 64, "square out of range");
+        Square(index)
+    }
+
+    pub const fn index(self) -> usize {
+        self.0 as usize
+    }
+
+    pub const fn rank(self) -> Rank {
+        Rank::from_index(self.0 / 8)
+    }
+
+    pub const fn file(self) -> File {
+        File::from_index(self.0 % 8)
+    }
+
+    pub const fn to_bitboard(self) -> Bitboard {
+        Bitboard(1 << self.0)
+    }
+
+    /// The square `offset` away, if it is on the board
+    pub fn try_add(self, offset: Offset) -> Option<Square> {
+        let rank = self.rank().offset(offset.rank)?;
+        let file = self.file().offset(offset.file)?;
+
+        Some(Square::new(rank, file))
+    }
+
+    /// How many king moves apart the squares are
+    pub fn distance_to(self, other: Self) -> u8 {
         let rank = (self.rank() as i8 - other.rank() as i8).abs();
         let file = (self.file() as i8 - other.file() as i8).abs();
 
         rank.max(file) as u8
     }
 
-    fn pretty(&self) -> String {
-        if !self.valid_square() {
-            return String::from("Invalid square");
-        }
+    /// Whether the square is dark, like a1 and h8
+    pub const fn is_dark(self) -> bool {
+        (self.rank() as u8 + self.file() as u8).is_multiple_of(2)
+    }
 
-        let file = { ('a' as u8 + self.file()) as char };
-        let rank = { ('1' as u8 + self.rank()) as char };
+    /// Mirrored top to bottom, a1 for a8
+    pub const fn flip_rank(self) -> Self {
+        Square(self.0 ^ 56)
+    }
 
-        return format!("{}{}", file, rank);
+    /// Mirrored left to right, a1 for h1
+    pub const fn flip_file(self) -> Self {
+        Square(self.0 ^ 7)
     }
-}
 
-/// Number 0-63
-pub type Square = u8;
+    /// This square as seen by `color`, mirrored top to bottom for black
+    pub const fn relative(self, color: Color) -> Self {
+        match color {
+            Color::White => self,
+            Color::Black => self.flip_rank(),
+        }
+    }
 
-impl const SquareU8 for Square {
-    fn new(rank: u8, file: u8) -> Square {
-        rank * 8 + file
+    /// Every square, from a1 to h8
+    pub fn every() -> impl DoubleEndedIterator<Item = Square> + ExactSizeIterator {
+        (0..64).map(Square)
     }
+}
 
-    fn rank(&self) -> u8 {
-        self / 8
+impl fmt::Display for Square {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}{}", self.file(), self.rank())
     }
+}
 
-    fn file(&self) -> u8 {
-        self % 8
+impl fmt::Debug for Square {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{self}")
     }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParseSquareError(pub String);
 
-    fn valid_square(&self) -> bool {
-        *self < 64
+impl fmt::Display for ParseSquareError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "invalid square '{}'", self.0)
     }
+}
+
+impl std::error::Error for ParseSquareError {}
+
+impl FromStr for Square {
+    type Err = ParseSquareError;
 
-    fn to_bitboard(&self) -> Bitboard {
-        1 << self
+    /// A square like `e4`
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let parse = || {
+            let mut chars = s.chars();
+            let file = File::from_char(chars.next()?)?;
+            let rank = Rank::from_char(chars.next()?)?;
+
+            chars.next().is_none().then(|| Square::new(rank, file))
+        };
+
+        parse().ok_or_else(|| ParseSquareError(s.to_string()))
     }
+}
 
-    fn try_add(&self, other_rank: i8, other_file: i8) -> Option<Square> {
-        let rank = self.rank() as i8 + other_rank;
-        let file = self.file() as i8 + other_file;
+#[cfg(test)]
+mod tests {
+    use super::*;
 
-        if rank < 0 || rank > 7 || file < 0 || file > 7 {
-            return None;
+    #[test]
+    fn parses_and_prints_every_square() {
+        for square in Square::every() {
+            let name = square.to_string();
+
+            assert_eq!(name.parse(), Ok(square));
         }
 
-        return Some(Square::new(rank as u8, file as u8));
+        assert_eq!("a1".parse(), Ok(Square::from_index(0)));
+        assert_eq!("h1".parse(), Ok(Square::from_index(7)));
+        assert_eq!("a8".parse(), Ok(Square::from_index(56)));
+        assert_eq!(Square::new(Rank::Fourth, File::E).to_string(), "e4");
     }
 
-    fn every() -> Range<Square> {
-        0..64
+    #[test]
+    fn rejects_bad_squares() {
+        for name in ["", "e", "4e", "e9", "i4", "e44", "E4"] {
+            assert_eq!(
+                name.parse::<Square>(),
+                Err(ParseSquareError(name.to_string())),
+                "{name}"
+            );
+        }
     }
 
-    fn get(&self) -> u8 {
-        *self
+    #[test]
+    fn try_add_moves_rank_and_file_separately() {
+        let e4 = Square::new(Rank::Fourth, File::E);
+
+        // up one rank and over two files, a knight's jump
+        assert_eq!(
+            e4.try_add(Offset { rank: 1, file: 2 }),
+            Some(Square::new(Rank::Fifth, File::G))
+        );
+        assert_eq!(
+            e4.try_add(Offset { rank: -3, file: -4 }),
+            Some(Square::new(Rank::First, File::A))
+        );
+    }
+
+    #[test]
+    fn try_add_stops_at_the_edges() {
+        let h1 = Square::new(Rank::First, File::H);
+
+        // off the h-file, where a plain index add would wrap onto a2
+        assert_eq!(h1.try_add(Offset { rank: 0, file: 1 }), None);
+        assert_eq!(h1.try_add(Offset { rank: -1, file: 0 }), None);
+        assert_eq!(
+            Square::new(Rank::Eighth, File::A).try_add(Offset { rank: 1, file: 0 }),
+            None
+        );
     }
 }
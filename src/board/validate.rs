@@ -0,0 +1,276 @@
+// Sanity checks for positions that weren't reached by playing moves
+//
+// Every field of `Position` is public, so a FEN or the GUI's editor can put
+// together boards that no game reaches. Move generation and search assume
+// what is checked here, like each side having exactly one king.
+use core::fmt;
+
+use crate::prelude::*;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PositionError {
+    /// Two pieces, or both colors, on one square
+    Overlap(Square),
+    /// A piece without a color, or a color without a piece
+    Uncolored(Square),
+    KingCount(Color, u32),
+    PawnOnBackRank(Square),
+    /// The side that just moved left its king in check
+    OpponentInCheck,
+    Castling {
+        color: Color,
+        kingside: bool,
+    },
+    EnPassant(Square),
+}
+
+impl fmt::Display for PositionError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PositionError::Overlap(square) => write!(f, "more than one piece on {square}"),
+            PositionError::Uncolored(square) => {
+                write!(f, "piece and color don't agree on {square}")
+            }
+            PositionError::KingCount(color, count) => write!(f, "{color} has {count} kings"),
+            PositionError::PawnOnBackRank(square) => write!(f, "pawn on {square}"),
+            PositionError::OpponentInCheck => write!(f, "the side not to move is in check"),
+            PositionError::Castling { color, kingside } => {
+                let side = if *kingside { "kingside" } else { "queenside" };
+                write!(f, "{color} can't castle {side} with this king and rook")
+            }
+            PositionError::EnPassant(square) => {
+                write!(f, "no pawn just moved past {square}")
+            }
+        }
+    }
+}
+
+impl std::error::Error for PositionError {}
+
+impl Position {
+    /// Check that the position could come up in a game: one king each, no
+    /// pawns on the first or eighth rank, bitboards that agree with each
+    /// other, the side not to move out of check, and castling rights and
+    /// en passant square that fit the board
+    pub fn validate(&self) -> Result<(), PositionError> {
+        self.validate_bitboards()?;
+
+        for color in [Color::White, Color::Black] {
+            let kings = (self.kings & self.pieces_of_col(color)).count_ones();
+
+            if kings != 1 {
+                return Err(PositionError::KingCount(color, kings));
+            }
+        }
+
+        let back_ranks = Bitboard::rank(Rank::First) | Bitboard::rank(Rank::Eighth);
+
+        if let Some(square) = (self.pawns & back_ranks).into_iter().next() {
+            return Err(PositionError::PawnOnBackRank(square));
+        }
+
+        self.validate_castling()?;
+        self.validate_en_passant()?;
+
+        if self.in_check(self.turn.other()) {
+            return Err(PositionError::OpponentInCheck);
+        }
+
+        Ok(())
+    }
+
+    fn validate_bitboards(&self) -> Result<(), PositionError> {
+        let mut pieces = Bitboard::EMPTY;
+
+        for piece_type in gui::PieceType::every() {
+            let of_type = self.pieces_of_type(piece_type);
+
+            if let Some(square) = (pieces & of_type).into_iter().next() {
+                return Err(PositionError::Overlap(square));
+            }
+
+            pieces |= of_type;
+        }
+
+        if let Some(square) = (self.n_white & self.n_black).into_iter().next() {
+            return Err(PositionError::Overlap(square));
+        }
+
+        match (pieces ^ self.occupied()).into_iter().next() {
+            Some(square) => Err(PositionError::Uncolored(square)),
+            None => Ok(()),
+        }
+    }
+
+    /// Each right needs the king and the rook on the back rank, with the
+    /// rook on that side of the king
+    fn validate_castling(&self) -> Result<(), PositionError> {
+        for color in [Color::White, Color::Black] {
+            let back_rank = Rank::First.relative(color);
+            let own = self.pieces_of_col(color);
+            let king = (self.kings & own).last_bit();
+
+            for kingside in [true, false] {
+                let Some(rook_file) = self.castling_rights.rook_file(color, kingside) else {
+                    continue;
+                };
+
+                let rook = Square::new(back_rank, rook_file);
+                let in_place = king.rank() == back_rank
+                    && (self.rooks & own).at(rook)
+                    && (rook_file > king.file()) == kingside;
+
+                if !in_place {
+                    return Err(PositionError::Castling { color, kingside });
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// The en passant square must be empty and behind a pawn of the side
+    /// that just moved, with the square that pawn came from empty too
+    fn validate_en_passant(&self) -> Result<(), PositionError> {
+        let Some(target) = self.ep_target else {
+            return Ok(());
+        };
+
+        let moved = self.turn.other();
+        let forward = match moved {
+            Color::White => 1,
+            Color::Black => -1,
+        };
+
+        let pawn = target.try_add(Offset {
+            rank: forward,
+            file: 0,
+        });
+        let start = target.try_add(Offset {
+            rank: -forward,
+            file: 0,
+        });
+
+        let occupied = self.occupied();
+        let plausible = target.rank() == Rank::Third.relative(moved)
+            && !occupied.at(target)
+            && pawn.is_some_and(|pawn| (self.pawns & self.pieces_of_col(moved)).at(pawn))
+            && start.is_some_and(|start| !occupied.at(start));
+
+        if plausible {
+            Ok(())
+        } else {
+            Err(PositionError::EnPassant(target))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::board::fen::FenError;
+
+    fn error(fen: &str) -> Option<PositionError> {
+        match Position::from_fen(fen) {
+            Ok(_) => None,
+            Err(FenError::Invalid(e)) => Some(e),
+            Err(e) => panic!("{fen} doesn't parse: {e}"),
+        }
+    }
+
+    fn square(name: &str) -> Square {
+        name.parse().unwrap()
+    }
+
+    #[test]
+    fn start_position_is_valid() {
+        assert_eq!(Position::new().validate(), Ok(()));
+    }
+
+    #[test]
+    fn two_kings() {
+        assert_eq!(
+            error("4k3/8/8/8/8/8/8/3KK3 w - - 0 1"),
+            Some(PositionError::KingCount(Color::White, 2))
+        );
+    }
+
+    #[test]
+    fn pawn_on_back_rank() {
+        assert_eq!(
+            error("4k3/8/8/8/8/8/8/P3K3 w - - 0 1"),
+            Some(PositionError::PawnOnBackRank(square("a1")))
+        );
+    }
+
+    #[test]
+    fn overlapping_bitboards() {
+        // a queen on the white king's square
+        let mut position = Position::new();
+        position.queens.set(square("e1"));
+
+        assert_eq!(
+            position.validate(),
+            Err(PositionError::Overlap(square("e1")))
+        );
+
+        // a square both white and black
+        let mut position = Position::new();
+        position.n_black.set(square("e2"));
+
+        assert_eq!(
+            position.validate(),
+            Err(PositionError::Overlap(square("e2")))
+        );
+    }
+
+    #[test]
+    fn piece_without_color() {
+        let mut position = Position::new();
+        position.n_white.unset(square("e2"));
+
+        assert_eq!(
+            position.validate(),
+            Err(PositionError::Uncolored(square("e2")))
+        );
+    }
+
+    #[test]
+    fn side_not_to_move_in_check() {
+        assert_eq!(
+            error("4k3/8/8/8/8/8/8/4R1K1 w - - 0 1"),
+            Some(PositionError::OpponentInCheck)
+        );
+    }
+
+    #[test]
+    fn castling_without_the_rook() {
+        let mut position = Position::new();
+        position.clear(square("h1"));
+
+        assert_eq!(
+            position.validate(),
+            Err(PositionError::Castling {
+                color: Color::White,
+                kingside: true
+            })
+        );
+    }
+
+    #[test]
+    fn implausible_en_passant() {
+        assert_eq!(error("4k3/8/8/8/4P3/8/8/4K3 b - e3 0 1"), None);
+
+        // no pawn in front of the square
+        assert_eq!(
+            error("4k3/8/8/8/8/8/8/4K3 b - e3 0 1"),
+            Some(PositionError::EnPassant(square("e3")))
+        );
+
+        // the wrong side's rank
+        assert_eq!(
+            error("4k3/8/8/8/4P3/8/8/4K3 w - e3 0 1"),
+            Some(PositionError::EnPassant(square("e3")))
+        );
+    }
+}
@@ -0,0 +1,44 @@
+// keys are generated at compile time from a fixed seed, so hashes are
+// identical between runs (and between builds)
+
+const SEED: u64 = 0x2545f4914f6cdd1d;
+
+/// splitmix64, see <https://prng.di.unimi.it/splitmix64.c>
+const fn splitmix(state: &mut u64) -> u64 {
+    *state = state.wrapping_add(0x9e3779b97f4a7c15);
+
+    let mut z = *state;
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d049bb133111eb);
+
+    z ^ (z >> 31)
+}
+
+const fn gen_keys<const N: usize>(offset: u64) -> [u64; N] {
+    let mut state = SEED ^ offset;
+    let mut keys = [0; N];
+    let mut i = 0;
+
+    while i < N {
+        keys[i] = splitmix(&mut state);
+        i += 1;
+    }
+
+    keys
+}
+
+/// Indexed by `color * 6 * 64 + piece_type * 64 + square`
+pub const PIECES: [u64; 2 * 6 * 64] = gen_keys(0);
+
+/// white kingside, white queenside, black kingside, black queenside
+pub const CASTLING: [u64; 4] = gen_keys(1);
+
+/// Indexed by the file of the en passant target
+pub const EN_PASSANT: [u64; 8] = gen_keys(2);
+
+/// Xored in when black is to move
+pub const BLACK_TO_MOVE: u64 = gen_keys::<1>(3)[0];
+
+pub const fn piece(color: usize, piece_type: usize, square: usize) -> u64 {
+    PIECES[color * 6 * 64 + piece_type * 64 + square]
+}
@@ -0,0 +1,183 @@
+// Builds a Polyglot book out of the main lines of a PGN file
+//
+// Every move played in the first plies of a game is counted, weighted by how
+// the game went for the side that played it: 2 for a win, 1 for a draw.
+// Moves that lost are left out.
+use std::{collections::HashMap, fs};
+
+use super::{encode_move, polyglot_key, Book, BookEntry};
+use crate::prelude::*;
+
+const DEFAULT_MAX_PLIES: usize = 30;
+
+/// Tokens of a PGN file, without tags, comments, variations and move numbers
+fn tokens(pgn: &str) -> Vec<&str> {
+    let mut tokens = vec![];
+    let mut variation_depth = 0;
+
+    for line in pgn.lines() {
+        let line = line.trim();
+
+        // `%` escapes and tag pairs take up the whole line
+        if line.starts_with('%') || line.starts_with('[') {
+            continue;
+        }
+
+        let mut rest = line;
+
+        while !rest.is_empty() {
+            rest = rest.trim_start();
+
+            let Some(c) = rest.chars().next() else {
+                break;
+            };
+
+            match c {
+                ';' => break,
+                '{' => match rest.find('}') {
+                    Some(end) => rest = &rest[end + 1..],
+                    // comments can span lines, but nobody does that
+                    None => break,
+                },
+                '(' => {
+                    variation_depth += 1;
+                    rest = &rest[1..];
+                }
+                ')' => {
+                    variation_depth -= 1;
+                    rest = &rest[1..];
+                }
+                _ => {
+                    let end = rest
+                        .find(|c: char| c.is_whitespace() || "{;()".contains(c))
+                        .unwrap_or(rest.len());
+
+                    let token = &rest[..end];
+                    rest = &rest[end..];
+
+                    if variation_depth > 0 || token.starts_with('$') {
+                        continue;
+                    }
+
+                    // `12.`, `12...` or `12.e4`, but not `1-0`
+                    let token = match token.rfind('.') {
+                        Some(dot) => &token[dot + 1..],
+                        None => token,
+                    };
+
+                    if !token.is_empty() {
+                        tokens.push(token);
+                    }
+                }
+            }
+        }
+    }
+
+    tokens
+}
+
+/// The result of a game, from white's point of view
+fn parse_result(token: &str) -> Option<f64> {
+    match token {
+        "1-0" => Some(1.0),
+        "0-1" => Some(0.0),
+        "1/2-1/2" => Some(0.5),
+        "*" => Some(f64::NAN),
+        _ => None,
+    }
+}
+
+/// `(polyglot key, polyglot move)` of each move in a game, with the side that played it
+type Game = Vec<(u64, u16, Color)>;
+
+fn games(pgn: &str, max_plies: usize) -> Vec<(Game, f64)> {
+    let mut games = vec![];
+    let mut game = vec![];
+    let mut position = Position::new();
+    let mut broken = false;
+
+    for token in tokens(pgn) {
+        if let Some(result) = parse_result(token) {
+            if !result.is_nan() {
+                games.push((std::mem::take(&mut game), result));
+            }
+
+            game.clear();
+            position = Position::new();
+            broken = false;
+            continue;
+        }
+
+        if broken || game.len() >= max_plies {
+            continue;
+        }
+
+        let Some(mv) = position.parse_san(token) else {
+            eprintln!("skipping rest of game at illegal move {token}");
+            broken = true;
+            continue;
+        };
+
+        game.push((polyglot_key(&position), encode_move(mv), position.turn));
+        position.play(mv);
+    }
+
+    games
+}
+
+/// Turn every move of every game into a book entry
+pub fn build(pgn: &str, max_plies: usize) -> Book {
+    let mut weights = HashMap::<(u64, u16), u64>::new();
+
+    for (game, result) in games(pgn, max_plies) {
+        for (key, mv, color) in game {
+            let score = match color {
+                Color::White => result,
+                Color::Black => 1.0 - result,
+            };
+
+            let weight = (score * 2.0) as u64;
+
+            if weight > 0 {
+                *weights.entry((key, mv)).or_default() += weight;
+            }
+        }
+    }
+
+    // weights are only 16 bits, so scale them down if needed
+    let max = weights.values().copied().max().unwrap_or(0);
+    let scale = (max as f64 / u16::MAX as f64).max(1.0);
+
+    let entries = weights
+        .into_iter()
+        .map(|((key, mv), weight)| BookEntry {
+            key,
+            mv,
+            weight: ((weight as f64 / scale) as u16).max(1),
+            learn: 0,
+        })
+        .collect();
+
+    Book::new(entries)
+}
+
+/// `book <pgn> <output> [max plies]`
+pub fn run(args: &[String]) {
+    let (Some(input), Some(output)) = (args.first(), args.get(1)) else {
+        eprintln!("usage: chess book <pgn> <output> [max plies]");
+        return;
+    };
+
+    let max_plies = args
+        .get(2)
+        .and_then(|n| n.parse().ok())
+        .unwrap_or(DEFAULT_MAX_PLIES);
+
+    let pgn = fs::read_to_string(input).unwrap_or_else(|e| panic!("could not read {input}: {e}"));
+    let book = build(&pgn, max_plies);
+
+    book.save(output)
+        .unwrap_or_else(|e| panic!("could not write {output}: {e}"));
+
+    println!("wrote {} entries to {output}", book.len());
+}
@@ -0,0 +1,343 @@
+// Polyglot opening books, see http://hgm.nubati.net/book_format.html
+use std::{fs, io};
+
+use crate::{
+    movegen,
+    prelude::{gui::PieceType, *},
+};
+
+/// Polyglot's random numbers, separate from our own zobrist keys
+pub mod random;
+
+/// Building books from PGN
+pub mod builder;
+
+pub const DEFAULT_DEPTH: usize = 20;
+
+const ENTRY_LEN: usize = 16;
+
+/// One book move. Stored big-endian, sorted by key
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BookEntry {
+    pub key: u64,
+    pub mv: u16,
+    pub weight: u16,
+    pub learn: u32,
+}
+
+impl BookEntry {
+    fn from_bytes(bytes: &[u8]) -> Self {
+        BookEntry {
+            key: u64::from_be_bytes(bytes[0..8].try_into().unwrap()),
+            mv: u16::from_be_bytes(bytes[8..10].try_into().unwrap()),
+            weight: u16::from_be_bytes(bytes[10..12].try_into().unwrap()),
+            learn: u32::from_be_bytes(bytes[12..16].try_into().unwrap()),
+        }
+    }
+
+    fn to_bytes(self) -> [u8; ENTRY_LEN] {
+        let mut bytes = [0; ENTRY_LEN];
+
+        bytes[0..8].copy_from_slice(&self.key.to_be_bytes());
+        bytes[8..10].copy_from_slice(&self.mv.to_be_bytes());
+        bytes[10..12].copy_from_slice(&self.weight.to_be_bytes());
+        bytes[12..16].copy_from_slice(&self.learn.to_be_bytes());
+
+        bytes
+    }
+}
+
+/// The Polyglot hash of a position
+pub fn polyglot_key(position: &Position) -> u64 {
+    let mut key = 0;
+
+    for square in position.occupied().bit_pos_iter() {
+        let piece = position.full_piece_at(square).unwrap();
+        let kind = 2 * piece.kind as usize + (piece.color == Color::White) as usize;
+
+        key ^= random::RANDOM64[64 * kind + square.index()];
+    }
+
+    for (i, flag) in position.castling_rights.flags().into_iter().enumerate() {
+        if flag {
+            key ^= random::RANDOM64[768 + i];
+        }
+    }
+
+    // only hashed if a pawn could actually take en passant
+    if let Some(target) = position.ep_target {
+        let our_pawns = position.pawns & position.pieces_of_turn();
+        let attackers = movegen::pawn_attacks(target.to_bitboard(), position.turn.other());
+
+        if attackers & our_pawns != Bitboard::EMPTY {
+            key ^= random::RANDOM64[772 + target.file().index() as usize];
+        }
+    }
+
+    if position.turn == Color::White {
+        key ^= random::RANDOM64[780];
+    }
+
+    key
+}
+
+/// Polyglot moves castle as "king takes rook", same as we do
+pub fn encode_move(mv: Move) -> u16 {
+    let promotion = match mv.promotion {
+        Some(PieceType::Knight) => 1,
+        Some(PieceType::Bishop) => 2,
+        Some(PieceType::Rook) => 3,
+        Some(PieceType::Queen) => 4,
+        _ => 0,
+    };
+
+    // squares are numbered the same way, rank * 8 + file
+    (mv.to.index() as u16) | (mv.from.index() as u16) << 6 | promotion << 12
+}
+
+pub fn decode_move(mv: u16) -> Move {
+    let to = Square::from_index((mv & 63) as u8);
+    let from = Square::from_index((mv >> 6 & 63) as u8);
+
+    let promotion = match mv >> 12 & 7 {
+        1 => Some(PieceType::Knight),
+        2 => Some(PieceType::Bishop),
+        3 => Some(PieceType::Rook),
+        4 => Some(PieceType::Queen),
+        _ => None,
+    };
+
+    Move {
+        from,
+        to,
+        promotion,
+    }
+}
+
+pub struct Book {
+    entries: Vec<BookEntry>,
+
+    /// Stop using the book after this many plies
+    pub depth: usize,
+}
+
+impl Book {
+    pub fn new(mut entries: Vec<BookEntry>) -> Self {
+        entries.sort_by_key(|e| (e.key, std::cmp::Reverse(e.weight)));
+
+        Self {
+            entries,
+            depth: DEFAULT_DEPTH,
+        }
+    }
+
+    pub fn load(path: &str) -> io::Result<Self> {
+        let bytes = fs::read(path)?;
+
+        if bytes.len() % ENTRY_LEN != 0 {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "not a polyglot book",
+            ));
+        }
+
+        let entries = bytes
+            .chunks_exact(ENTRY_LEN)
+            .map(BookEntry::from_bytes)
+            .collect();
+
+        Ok(Self::new(entries))
+    }
+
+    pub fn save(&self, path: &str) -> io::Result<()> {
+        let bytes = self
+            .entries
+            .iter()
+            .flat_map(|e| e.to_bytes())
+            .collect_vec();
+
+        fs::write(path, bytes)
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Every legal book move in a position, with its weight
+    pub fn moves(&self, position: &Position) -> Vec<(Move, u16)> {
+        let key = polyglot_key(position);
+        let start = self.entries.partition_point(|e| e.key < key);
+        let legal = position.legal_moves();
+
+        self.entries[start..]
+            .iter()
+            .take_while(|e| e.key == key)
+            .map(|e| (decode_move(e.mv), e.weight))
+            .filter(|(mv, _)| legal.contains(mv))
+            .collect()
+    }
+
+    /// Pick a book move at random, weighted by how good the book says it is
+    ///
+    /// * `ply` - Plies played since the start of the game
+    pub fn probe(&self, position: &Position, ply: usize) -> Option<Move> {
+        if ply >= self.depth {
+            return None;
+        }
+
+        let moves = self.moves(position);
+        let total = moves.iter().map(|(_, w)| *w as u64).sum::<u64>();
+
+        if total == 0 {
+            return None;
+        }
+
+        let mut pick = rng::below(total);
+
+        for (mv, weight) in moves {
+            if pick < weight as u64 {
+                return Some(mv);
+            }
+
+            pick -= weight as u64;
+        }
+
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn after(line: &str) -> Position {
+        let mut position = Position::new();
+
+        for mv in line.split_whitespace() {
+            position.play(position.parse_uci(mv, false).unwrap());
+        }
+
+        position
+    }
+
+    fn entry(position: &Position, mv: &str, weight: u16) -> BookEntry {
+        BookEntry {
+            key: polyglot_key(position),
+            mv: encode_move(position.parse_uci(mv, false).unwrap()),
+            weight,
+            learn: 0,
+        }
+    }
+
+    #[test]
+    fn reference_keys() {
+        // the examples from the book format's description
+        let keys = [
+            ("", 0x463b96181691fc9c),
+            ("e2e4", 0x823c9b50fd114196),
+            ("e2e4 d7d5", 0x0756b94461c50fb0),
+            ("e2e4 d7d5 e4e5", 0x662fafb965db29d4),
+            ("e2e4 d7d5 e4e5 f7f5", 0x22a48b5a8e47ff78),
+            ("e2e4 d7d5 e4e5 f7f5 e1e2", 0x652a607ca3f242c1),
+            ("e2e4 d7d5 e4e5 f7f5 e1e2 e8f7", 0x00fdd303c946bdd9),
+            ("a2a4 b7b5 h2h4 b5b4 c2c4", 0x3c8123ea7b067637),
+            ("a2a4 b7b5 h2h4 b5b4 c2c4 b4c3 a1a3", 0x5c3f9b829b279560),
+        ];
+
+        for (line, key) in keys {
+            assert_eq!(polyglot_key(&after(line)), key, "{line}");
+        }
+    }
+
+    #[test]
+    fn castling_is_king_takes_rook() {
+        let position = Position::from_fen("r3k2r/8/8/8/8/8/8/R3K2R w KQkq - 0 1").unwrap();
+        let e1h1 = 4 << 6 | 7;
+        let e1a1 = 4 << 6;
+
+        assert_eq!(
+            encode_move(position.parse_uci("e1g1", false).unwrap()),
+            e1h1
+        );
+        assert_eq!(
+            encode_move(position.parse_uci("e1c1", false).unwrap()),
+            e1a1
+        );
+
+        let book = Book::new(vec![BookEntry {
+            key: polyglot_key(&position),
+            mv: e1h1,
+            weight: 1,
+            learn: 0,
+        }]);
+
+        let (mv, _) = book.moves(&position)[0];
+        assert_eq!(position.to_uci(mv, false), "e1g1");
+    }
+
+    #[test]
+    fn moves_round_trip() {
+        let position = Position::from_fen("4k3/1P6/8/8/8/8/8/4K3 w - - 0 1").unwrap();
+
+        for mv in position.legal_moves() {
+            assert_eq!(decode_move(encode_move(mv)), mv);
+        }
+
+        // a queen promotion, from b7 to b8
+        assert_eq!(
+            encode_move(position.parse_uci("b7b8q", false).unwrap()),
+            4 << 12 | 49 << 6 | 57
+        );
+    }
+
+    #[test]
+    fn probes_by_weight() {
+        let start = Position::new();
+        let mut book = Book::new(vec![
+            entry(&start, "e2e4", 3),
+            entry(&start, "d2d4", 1),
+            entry(&start, "g1f3", 0),
+            // a move from another position
+            entry(&after("e2e4"), "e7e5", 100),
+        ]);
+
+        let e4 = start.parse_uci("e2e4", false).unwrap();
+        let d4 = start.parse_uci("d2d4", false).unwrap();
+
+        let mut counts = [0; 2];
+
+        for _ in 0..4000 {
+            match book.probe(&start, 0) {
+                Some(mv) if mv == e4 => counts[0] += 1,
+                Some(mv) if mv == d4 => counts[1] += 1,
+                mv => panic!("{mv:?}"),
+            }
+        }
+
+        assert!((2700..3300).contains(&counts[0]), "{counts:?}");
+
+        book.depth = 1;
+        assert_eq!(book.probe(&start, 1), None);
+        assert_eq!(book.probe(&after("d2d4"), 0), None);
+    }
+
+    #[test]
+    fn skips_illegal_and_weightless_moves() {
+        let start = Position::new();
+
+        // the king jumping from e1 to e5
+        let illegal = BookEntry {
+            mv: 4 << 6 | 36,
+            ..entry(&start, "e2e4", 1)
+        };
+
+        let book = Book::new(vec![illegal, entry(&start, "g1f3", 0)]);
+
+        assert_eq!(book.moves(&start).len(), 1);
+        assert_eq!(book.probe(&start, 0), None);
+    }
+}
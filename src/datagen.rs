@@ -0,0 +1,217 @@
+// Self-play training data for tuning and network training
+//
+// Every game starts with a few random plies, then the engine plays itself
+// with a fixed node budget per move. Every quiet position is written with
+// its search score and the final result of the game, in two formats:
+//
+// `<prefix>.txt`, one position per line, readable by `chess tune`:
+//     <fen> <white-relative score> [<result>]
+// where the result is 1.0, 0.5 or 0.0 from white's point of view
+//
+// `<prefix>.bin`, 28 bytes per position, every number little-endian:
+//     u64      occupied squares
+//     [u8; 16] one nibble per occupied square, in ascending square order,
+//              low nibble first: color (0 white, 1 black) << 3 | piece type
+//              (0 pawn, 1 knight, 2 bishop, 3 rook, 4 queen, 5 king)
+//     i16      white-relative score in centipawns
+//     u8       result: 0 black won, 1 draw, 2 white won
+//     u8       flags: bit 0 set if black is to move
+use std::{
+    fs::File,
+    io::{self, BufWriter, Write},
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Mutex, PoisonError,
+    },
+};
+
+use crate::{
+    prelude::*,
+    rng::Rng,
+    search::{SearchLimits, Searcher},
+};
+
+const DEFAULT_NODES: u64 = 5_000;
+const RANDOM_PLIES: usize = 8;
+const MAX_PLIES: usize = 400;
+
+pub const RECORD_LEN: usize = 28;
+
+struct DataPoint {
+    position: Position,
+
+    /// White-relative
+    score: Score,
+}
+
+pub(crate) fn random_opening(rng: &mut Rng) -> Position {
+    'retry: loop {
+        let mut position = Position::new();
+
+        for _ in 0..RANDOM_PLIES {
+            let moves = position.legal_moves();
+
+            if moves.is_empty() {
+                continue 'retry;
+            }
+
+            position.play(moves[rng.below(moves.len() as u64) as usize]);
+        }
+
+        if !position.legal_moves().is_empty() {
+            return position;
+        }
+    }
+}
+
+/// Play one game, returning its quiet positions and the result for white
+fn play_game(nodes: u64, rng: &mut Rng) -> (Vec<DataPoint>, f64) {
+    let mut position = random_opening(rng);
+    let mut history = vec![position.hash_key()];
+    let mut points = vec![];
+
+    let limits = SearchLimits {
+        nodes: Some(nodes),
+        ..Default::default()
+    };
+
+    // one table for the whole game, games already run in parallel
+    let mut searcher = Searcher::new(limits);
+
+    let result = loop {
+        if position.legal_moves().is_empty() {
+            if !position.in_check(position.turn) {
+                break 0.5;
+            }
+
+            break match position.turn {
+                Color::White => 0.0,
+                Color::Black => 1.0,
+            };
+        }
+
+        let key = position.hash_key();
+        let repeated = history.iter().filter(|&&k| k == key).count() >= 3;
+        let bare_kings = position.occupied() == position.kings;
+
+        if repeated || bare_kings || history.len() >= MAX_PLIES {
+            break 0.5;
+        }
+
+        let search = searcher.search(&position, |_| {});
+
+        let Some(mv) = search.best_move else {
+            break 0.5;
+        };
+
+        let quiet = !position.in_check(position.turn)
+            && !position.is_capture(mv)
+            && mv.promotion.is_none()
+            && !search.score.is_mate();
+
+        if quiet {
+            points.push(DataPoint {
+                position,
+                score: search.score.relative(position.turn),
+            });
+        }
+
+        position.play(mv);
+        history.push(position.hash_key());
+    };
+
+    (points, result)
+}
+
+fn encode(point: &DataPoint, result: f64) -> [u8; RECORD_LEN] {
+    let position = &point.position;
+    let mut record = [0u8; RECORD_LEN];
+
+    record[0..8].copy_from_slice(&position.occupied().0.to_le_bytes());
+
+    for (i, square) in position.occupied().bit_pos_iter().enumerate() {
+        let piece = position.full_piece_at(square).unwrap();
+        let nibble = ((piece.color as u8) << 3) | piece.kind as u8;
+
+        record[8 + i / 2] |= nibble << (4 * (i % 2));
+    }
+
+    let score = point.score.0.clamp(i16::MIN as i32, i16::MAX as i32) as i16;
+    record[24..26].copy_from_slice(&score.to_le_bytes());
+    record[26] = (result * 2.0) as u8;
+    record[27] = (position.turn == Color::Black) as u8;
+
+    record
+}
+
+/// `datagen <games> <output prefix> [nodes per move] [seed]`
+pub fn run(args: &[String]) {
+    let (Some(games), Some(prefix)) = (args.first().and_then(|g| g.parse::<usize>().ok()), args.get(1))
+    else {
+        eprintln!("usage: chess datagen <games> <output prefix> [nodes per move] [seed]");
+        return;
+    };
+
+    let nodes = args
+        .get(2)
+        .and_then(|n| n.parse().ok())
+        .unwrap_or(DEFAULT_NODES);
+
+    // every game has its own generator, so a seed gives the same games
+    // however they are spread over threads
+    let seed = args
+        .get(3)
+        .and_then(|s| s.parse().ok())
+        .unwrap_or_else(rng::dense_random);
+
+    let open = |ext: &str| {
+        let path = format!("{prefix}.{ext}");
+
+        File::create(&path)
+            .map(BufWriter::new)
+            .map_err(|e| eprintln!("could not create {path}: {e}"))
+    };
+
+    let (Ok(bin), Ok(txt)) = (open("bin"), open("txt")) else {
+        return;
+    };
+
+    let writers = Mutex::new((bin, txt));
+
+    let finished = AtomicUsize::new(0);
+    let positions = AtomicUsize::new(0);
+
+    // the first error stops the games that haven't started yet
+    let written = (0..games).into_par_iter().try_for_each(|game| {
+        let mut rng = Rng::derive(seed, game as u64);
+        let (points, result) = play_game(nodes, &mut rng);
+
+        {
+            let mut writers = writers.lock().unwrap_or_else(PoisonError::into_inner);
+            let (bin, txt) = &mut *writers;
+
+            for point in &points {
+                bin.write_all(&encode(point, result))?;
+                let fen = point.position.fen();
+                writeln!(txt, "{fen} {} [{result:.1}]", point.score.0)?;
+            }
+        }
+
+        let positions = positions.fetch_add(points.len(), Ordering::Relaxed) + points.len();
+        let finished = finished.fetch_add(1, Ordering::Relaxed) + 1;
+
+        println!("{finished}/{games} games, {positions} positions");
+
+        io::Result::Ok(())
+    });
+
+    let flushed = written.and_then(|()| {
+        let (mut bin, mut txt) = writers.into_inner().unwrap_or_else(PoisonError::into_inner);
+        bin.flush()?;
+        txt.flush()
+    });
+
+    if let Err(e) = flushed {
+        eprintln!("could not write {prefix}: {e}");
+    }
+}
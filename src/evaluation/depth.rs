@@ -1,105 +1,377 @@
-use std::sync::Arc;
-
-use atomicfloat::AtomicF64;
+// The game tree: every node in one arena, with a cap on its memory
+//
+// Nodes don't keep their position, only the move that led to them, so they
+// stay small. Positions are played out from the root on the way down. The
+// children of a node are next to each other in the arena, and the node only
+// keeps where they start and how many there are.
+use std::{mem, ops::Range};
 
 use crate::prelude::*;
 
-#[derive(PartialEq)]
+/// The index of a node in its tree's arena
+pub type NodeId = u32;
+
+/// The root is always the first node
+pub const ROOT: NodeId = 0;
+
+pub const DEFAULT_TREE_MB: usize = 256;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
 pub struct GameTreeNode {
-    pub position: Position,
-    pub children: Vec<GameTreeNode>,
+    /// The move that led here, `None` at the root
+    pub mv: Option<Move>,
+    first_child: NodeId,
+    child_count: u16,
+
+    /// Visits on their way down that haven't been backed up yet, counted
+    /// as losses so parallel selections spread out
+    pub virtual_loss: u16,
+    /// Monte Carlo tree search statistics, see [`crate::search::mcts`]
+    pub visits: u32,
+    /// Values backed up through this node, from the point of view of the
+    /// side that played `mv`, in -1..=1 each
+    pub value_sum: f32,
+    /// How likely the policy thinks `mv` is best, among its siblings
+    pub prior: f32,
 }
 
 impl GameTreeNode {
-    pub fn new(position: Position) -> Self {
+    fn new(mv: Option<Move>, prior: f32) -> Self {
+        Self {
+            mv,
+            first_child: 0,
+            child_count: 0,
+            virtual_loss: 0,
+            visits: 0,
+            value_sum: 0.0,
+            prior,
+        }
+    }
+
+    pub fn is_leaf(&self) -> bool {
+        self.child_count == 0
+    }
+
+    pub fn children(&self) -> Range<NodeId> {
+        self.first_child..self.first_child + self.child_count as NodeId
+    }
+
+    /// Mean value from the point of view of the side that played `mv`,
+    /// pending visits counted as losses
+    pub fn value(&self) -> f64 {
+        let visits = self.visits + self.virtual_loss as u32;
+
+        if visits == 0 {
+            return 0.0;
+        }
+
+        (self.value_sum as f64 - self.virtual_loss as f64) / visits as f64
+    }
+}
+
+pub struct GameTree {
+    /// The position at the root
+    position: Position,
+    nodes: Vec<GameTreeNode>,
+    max_nodes: usize,
+}
+
+impl GameTree {
+    /// An unexpanded root, with at most `memory_mb` for the nodes
+    pub fn new(position: Position, memory_mb: usize) -> Self {
+        let max_nodes = (memory_mb << 20) / mem::size_of::<GameTreeNode>();
+
         Self {
             position,
-            children: vec![],
+            nodes: vec![GameTreeNode::new(None, 1.0)],
+            max_nodes: max_nodes.clamp(1, NodeId::MAX as usize),
         }
     }
 
-    pub fn populate(&mut self, depth: usize) {
-        let position = *&self.position;
+    pub fn position(&self) -> &Position {
+        &self.position
+    }
+
+    pub fn root(&self) -> &GameTreeNode {
+        &self.nodes[ROOT as usize]
+    }
 
+    pub fn node(&self, id: NodeId) -> &GameTreeNode {
+        &self.nodes[id as usize]
+    }
+
+    pub fn node_mut(&mut self, id: NodeId) -> &mut GameTreeNode {
+        &mut self.nodes[id as usize]
+    }
+
+    pub fn len(&self) -> usize {
+        self.nodes.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.nodes.is_empty()
+    }
+
+    /// Whether `count` more nodes fit under the memory cap
+    pub fn has_room(&self, count: usize) -> bool {
+        self.nodes.len() + count <= self.max_nodes
+    }
+
+    pub fn leaf_count(&self) -> usize {
+        self.nodes.iter().filter(|node| node.is_leaf()).count()
+    }
+
+    /// Give the leaf `id` its children, false if they don't fit
+    pub fn expand(
+        &mut self,
+        id: NodeId,
+        children: impl ExactSizeIterator<Item = (Move, f32)>,
+    ) -> bool {
+        let count = children.len();
+
+        if !self.has_room(count) {
+            return false;
+        }
+
+        // grow no further than the cap, where doubling would overshoot it
+        if self.nodes.capacity() < self.nodes.len() + count {
+            let target =
+                (self.nodes.capacity() * 2).clamp(self.nodes.len() + count, self.max_nodes);
+            self.nodes.reserve_exact(target - self.nodes.len());
+        }
+
+        let first = self.nodes.len() as NodeId;
+        self.nodes
+            .extend(children.map(|(mv, prior)| GameTreeNode::new(Some(mv), prior)));
+
+        let node = self.node_mut(id);
+        node.first_child = first;
+        node.child_count = count as u16;
+
+        true
+    }
+
+    /// Expand every node down to `depth` plies below the root, false if
+    /// the tree got full first
+    pub fn populate(&mut self, depth: usize) -> bool {
+        let position = self.position;
+        self.populate_from(ROOT, &position, depth)
+    }
+
+    fn populate_from(&mut self, id: NodeId, position: &Position, depth: usize) -> bool {
         if depth == 0 {
-            return;
+            return true;
+        }
+
+        if self.node(id).is_leaf() {
+            let moves = position.legal_moves();
+            let prior = 1.0 / moves.len() as f32;
+
+            if !self.expand(id, moves.into_iter().map(|mv| (mv, prior))) {
+                return false;
+            }
         }
 
-        if self.children.is_empty() {
-            self.children = self
-                .position
-                .pieces_of_turn()
-                .bit_pos_iter()
-                .flat_map(|from| {
-                    self.position.moves_of(from).bit_pos_iter().map(move |to| {
-                        let mut child = position;
-                        child.make_move(from, to);
-
-                        child
-                    })
-                })
-                .map(|pos| GameTreeNode::new(pos))
-                .collect();
+        self.node(id).children().all(|child| {
+            let mut next = *position;
+            next.play(self.node(child).mv.expect("only the root has no move"));
+
+            self.populate_from(child, &next, depth - 1)
+        })
+    }
+
+    /// Make `mv` the root, keeping what was searched below it and freeing
+    /// the rest. `mv` has to be legal
+    pub fn move_into(&mut self, mv: Move) {
+        let child = self
+            .root()
+            .children()
+            .find(|&child| self.node(child).mv == Some(mv));
+
+        self.position.play(mv);
+
+        let Some(child) = child else {
+            self.nodes = vec![GameTreeNode::new(None, 1.0)];
+            return;
+        };
+
+        // copied breadth first, so that siblings stay next to each other
+        let mut nodes = vec![GameTreeNode {
+            mv: None,
+            ..*self.node(child)
+        }];
+
+        let mut i = 0;
+
+        while i < nodes.len() {
+            let children = nodes[i].children();
+
+            if !children.is_empty() {
+                nodes[i].first_child = nodes.len() as NodeId;
+                nodes
+                    .extend_from_slice(&self.nodes[children.start as usize..children.end as usize]);
+            }
+
+            i += 1;
         }
 
-        self.children
-            .iter_mut()
-            .par_bridge()
-            .for_each(|n| n.populate(depth - 1))
+        self.nodes = nodes;
     }
 
-    pub fn leaves<'a>(&'a mut self, collect: &mut Vec<&'a mut GameTreeNode>) {
-        if self.children.is_empty() {
-            collect.push(self);
-        } else {
-            for child in &mut self.children {
-                child.leaves(collect);
+    /// Move the root to `position` if it is at most `plies` moves below,
+    /// keeping the tree under it. False if it isn't found
+    pub fn advance_to(&mut self, position: &Position, plies: usize) -> bool {
+        match self.line_to(&self.position, ROOT, position, plies) {
+            Some(line) => {
+                for mv in line {
+                    self.move_into(mv);
+                }
+
+                true
             }
+            None => false,
         }
     }
 
-    fn replace(&mut self, idx: usize) {
-        *self = self.children.swap_remove(idx);
+    fn line_to(
+        &self,
+        from: &Position,
+        id: NodeId,
+        target: &Position,
+        plies: usize,
+    ) -> Option<Vec<Move>> {
+        if from == target {
+            return Some(vec![]);
+        }
+
+        if plies == 0 {
+            return None;
+        }
+
+        self.node(id).children().find_map(|child| {
+            let mv = self.node(child).mv?;
+            let mut next = *from;
+            next.play(mv);
+
+            let mut line = self.line_to(&next, child, target, plies - 1)?;
+            line.insert(0, mv);
+
+            Some(line)
+        })
     }
 }
 
-pub struct GameTreeRoot {
-    pub actual_root: GameTreeNode,
-    pub eval: Arc<AtomicF64>,
-}
+#[cfg(test)]
+mod tests {
+    use super::*;
 
-impl GameTreeRoot {
-    pub fn new(position: Position, eval_link: Arc<AtomicF64>) -> Self {
-        Self {
-            actual_root: GameTreeNode::new(position),
-            eval: eval_link,
+    #[derive(Debug, PartialEq)]
+    struct Subtree {
+        mv: Option<Move>,
+        visits: u32,
+        children: Vec<Subtree>,
+    }
+
+    impl Subtree {
+        fn size(&self) -> usize {
+            1 + self.children.iter().map(Subtree::size).sum::<usize>()
         }
     }
 
-    pub fn populate(&mut self, depth: usize) {
-        self.actual_root.populate(depth)
+    fn subtree(tree: &GameTree, id: NodeId) -> Subtree {
+        let node = tree.node(id);
+
+        Subtree {
+            mv: node.mv,
+            visits: node.visits,
+            children: node.children().map(|child| subtree(tree, child)).collect(),
+        }
     }
 
-    pub fn move_into(&mut self, from: Square, to: Square) {
-        let move_made = {
-            let mut p = *&self.actual_root.position;
-            p.make_move(from, to);
+    fn san(position: &Position, san: &str) -> Move {
+        position.parse_san(san).unwrap()
+    }
 
-            p
-        };
+    fn after(line: &[&str]) -> Position {
+        let mut position = Position::new();
 
-        for i in 0..self.actual_root.children.len() {
-            let position = self.actual_root.children[i].position;
+        for mv in line {
+            position.play(san(&position, mv));
+        }
 
-            if position == move_made {
-                self.actual_root.replace(i);
-            }
+        position
+    }
+
+    #[test]
+    fn move_into_keeps_the_subtree() {
+        let mut tree = GameTree::new(Position::new(), 16);
+        assert!(tree.populate(3));
+
+        for id in 0..tree.len() as NodeId {
+            tree.node_mut(id).visits = id;
         }
 
-        panic!("move not found in children");
+        let e4 = san(tree.position(), "e4");
+        let child = tree
+            .root()
+            .children()
+            .find(|&child| tree.node(child).mv == Some(e4))
+            .unwrap();
+
+        let kept = Subtree {
+            mv: None,
+            ..subtree(&tree, child)
+        };
+
+        tree.move_into(e4);
+
+        assert_eq!(subtree(&tree, ROOT), kept);
+        assert_eq!(tree.len(), kept.size());
+        assert!(*tree.position() == after(&["e4"]));
     }
 
-    pub fn leaves<'a>(&'a mut self, collect: &mut Vec<&'a mut GameTreeNode>) {
-        self.actual_root.leaves(collect);
+    #[test]
+    fn move_into_an_unsearched_move_starts_over() {
+        let mut tree = GameTree::new(Position::new(), 16);
+        let e4 = san(tree.position(), "e4");
+
+        tree.move_into(e4);
+
+        assert_eq!(tree.len(), 1);
+        assert!(tree.root().is_leaf());
+        assert!(*tree.position() == after(&["e4"]));
+    }
+
+    #[test]
+    fn advances_to_positions_in_the_tree() {
+        let mut tree = GameTree::new(Position::new(), 16);
+        assert!(tree.populate(2));
+
+        let position = after(&["e4", "e5"]);
+
+        assert!(!tree.advance_to(&position, 1));
+        assert!(tree.advance_to(&position, 2));
+        assert!(*tree.position() == position);
+        assert_eq!(tree.len(), 1);
+
+        // nothing below the new root leads back
+        assert!(!tree.advance_to(&Position::new(), 4));
+    }
+
+    #[test]
+    fn expand_stops_at_the_cap() {
+        let mut tree = GameTree::new(Position::new(), 0);
+        let moves = Position::new().legal_moves();
+
+        assert!(!tree.expand(ROOT, moves.into_iter().map(|mv| (mv, 0.05))));
+        assert!(tree.root().is_leaf());
+        assert_eq!(tree.len(), 1);
+
+        let mut tree = GameTree::new(Position::new(), 1);
+        let max_nodes = (1 << 20) / mem::size_of::<GameTreeNode>();
+
+        assert!(!tree.populate(4));
+        assert!(tree.len() <= max_nodes);
+        assert!(!tree.has_room(max_nodes - tree.len() + 1));
     }
 }
@@ -0,0 +1,283 @@
+// King and pawn versus king bitbase
+//
+// Generated at startup by retrograde analysis: positions that are
+// immediately won or drawn are marked first, then every other position is
+// resolved from its successors until nothing changes. The strong side is
+// always white here, with the pawn on files a to d.
+use std::sync::OnceLock;
+
+use crate::{movegen, prelude::*};
+
+/// Pawn files a-d and ranks 2-7, both kings, side to move
+const SIZE: usize = 24 * 64 * 64 * 2;
+
+/// One square towards the eighth rank
+const UP: Offset = Offset { rank: 1, file: 0 };
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+#[repr(u8)]
+enum Outcome {
+    Invalid = 0,
+    Unknown = 1,
+    Draw = 2,
+    Win = 4,
+}
+
+fn index(turn: Color, white_king: Square, black_king: Square, pawn: Square) -> usize {
+    let pawn = pawn.file().index() as usize * 6 + pawn.rank().index() as usize - 1;
+
+    ((pawn * 64 + white_king.index()) * 64 + black_king.index()) * 2 + turn as usize
+}
+
+struct Entry {
+    turn: Color,
+    white_king: Square,
+    black_king: Square,
+    pawn: Square,
+    result: Outcome,
+}
+
+impl Entry {
+    fn new(idx: usize) -> Self {
+        let turn = if idx & 1 == 0 {
+            Color::White
+        } else {
+            Color::Black
+        };
+
+        let black_king = Square::from_index((idx >> 1 & 63) as u8);
+        let white_king = Square::from_index((idx >> 7 & 63) as u8);
+        let pawn_idx = idx >> 13;
+        let pawn = Square::new(
+            Rank::from_index((pawn_idx % 6) as u8 + 1),
+            File::from_index((pawn_idx / 6) as u8),
+        );
+
+        let mut entry = Entry {
+            turn,
+            white_king,
+            black_king,
+            pawn,
+            result: Outcome::Unknown,
+        };
+
+        entry.result = entry.initial();
+        entry
+    }
+
+    /// Classify positions that don't depend on any other
+    fn initial(&self) -> Outcome {
+        let pawn_attacks = movegen::pawn_attacks(self.pawn.to_bitboard(), Color::White);
+        let white_attacks = movegen::king_attacks(self.white_king);
+        let black_attacks = movegen::king_attacks(self.black_king);
+
+        if self.white_king.distance_to(self.black_king) <= 1
+            || self.white_king == self.pawn
+            || self.black_king == self.pawn
+            || (self.turn == Color::White && pawn_attacks.at(self.black_king))
+        {
+            return Outcome::Invalid;
+        }
+
+        let Some(promotion) = self.pawn.try_add(UP) else {
+            return Outcome::Invalid;
+        };
+
+        // promotes without the queen being taken
+        if self.turn == Color::White
+            && self.pawn.rank() == Rank::Seventh
+            && self.white_king != promotion
+            && (self.black_king.distance_to(promotion) > 1
+                || self.white_king.distance_to(promotion) == 1)
+        {
+            return Outcome::Win;
+        }
+
+        if self.turn == Color::Black {
+            let stalemate = black_attacks & !(white_attacks | pawn_attacks) == Bitboard::EMPTY;
+            let takes_pawn = (black_attacks & !white_attacks).at(self.pawn);
+
+            if stalemate || takes_pawn {
+                return Outcome::Draw;
+            }
+        }
+
+        Outcome::Unknown
+    }
+
+    /// Resolve from the results of every move
+    fn classify(&self, db: &[Entry]) -> Outcome {
+        let mut results = 0;
+
+        match self.turn {
+            Color::White => {
+                for square in movegen::king_attacks(self.white_king).bit_pos_iter() {
+                    results |= db[index(Color::Black, square, self.black_king, self.pawn)].result as u8;
+                }
+
+                // pawns are never on the eighth rank
+                let push = self.pawn.try_add(UP).unwrap();
+
+                if self.pawn.rank() < Rank::Seventh {
+                    results |= db[index(Color::Black, self.white_king, self.black_king, push)].result as u8;
+                }
+
+                let blocked = push == self.white_king || push == self.black_king;
+
+                if self.pawn.rank() == Rank::Second && !blocked {
+                    let double = push.try_add(UP).unwrap();
+                    results |= db[index(Color::Black, self.white_king, self.black_king, double)].result as u8;
+                }
+            }
+            Color::Black => {
+                for square in movegen::king_attacks(self.black_king).bit_pos_iter() {
+                    results |= db[index(Color::White, self.white_king, square, self.pawn)].result as u8;
+                }
+            }
+        }
+
+        // white needs one winning move, black one drawing move
+        let (good, bad) = match self.turn {
+            Color::White => (Outcome::Win, Outcome::Draw),
+            Color::Black => (Outcome::Draw, Outcome::Win),
+        };
+
+        if results & good as u8 != 0 {
+            good
+        } else if results & Outcome::Unknown as u8 != 0 {
+            Outcome::Unknown
+        } else {
+            bad
+        }
+    }
+}
+
+/// One bit per position, set if white wins
+fn generate() -> Vec<u64> {
+    let mut db = (0..SIZE).map(Entry::new).collect_vec();
+
+    loop {
+        let mut changed = false;
+
+        for idx in 0..SIZE {
+            if db[idx].result != Outcome::Unknown {
+                continue;
+            }
+
+            let result = db[idx].classify(&db);
+
+            if result != Outcome::Unknown {
+                db[idx].result = result;
+                changed = true;
+            }
+        }
+
+        if !changed {
+            break;
+        }
+    }
+
+    let mut bits = vec![0u64; SIZE / 64];
+
+    for (idx, entry) in db.iter().enumerate() {
+        if entry.result == Outcome::Win {
+            bits[idx / 64] |= 1 << (idx % 64);
+        }
+    }
+
+    bits
+}
+
+fn bitbase() -> &'static [u64] {
+    static BITBASE: OnceLock<Vec<u64>> = OnceLock::new();
+    BITBASE.get_or_init(generate)
+}
+
+/// Whether the side with the pawn wins
+///
+/// * `strong_color` - The color of the side with the pawn
+/// * `strong_king` - The king of the side with the pawn
+/// * `weak_king` - The lone king
+/// * `strong_to_move` - Whether the side with the pawn is to move
+pub fn probe(
+    strong_color: Color,
+    strong_king: Square,
+    pawn: Square,
+    weak_king: Square,
+    strong_to_move: bool,
+) -> bool {
+    // seen from the strong side, with the pawn on the queenside
+    let flip_file = pawn.file() > File::D;
+
+    let normalize = |square: Square| {
+        let square = square.relative(strong_color);
+
+        if flip_file {
+            square.flip_file()
+        } else {
+            square
+        }
+    };
+    let turn = if strong_to_move {
+        Color::White
+    } else {
+        Color::Black
+    };
+
+    let idx = index(turn, normalize(strong_king), normalize(weak_king), normalize(pawn));
+
+    bitbase()[idx / 64] >> (idx % 64) & 1 != 0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Probe with squares written out, `strong_king`, `pawn`, `weak_king`
+    fn wins(strong_color: Color, squares: [&str; 3], strong_to_move: bool) -> bool {
+        let [strong_king, pawn, weak_king] = squares.map(|square| square.parse().unwrap());
+
+        probe(strong_color, strong_king, pawn, weak_king, strong_to_move)
+    }
+
+    #[test]
+    fn rook_pawns_draw_in_the_corner() {
+        for strong_to_move in [true, false] {
+            assert!(!wins(Color::White, ["b5", "a5", "a8"], strong_to_move));
+        }
+    }
+
+    #[test]
+    fn key_squares_win() {
+        for strong_to_move in [true, false] {
+            assert!(wins(Color::White, ["d6", "d4", "d8"], strong_to_move));
+        }
+
+        // a rank lower, it's down to who has the opposition
+        assert!(!wins(Color::White, ["d5", "d4", "d7"], true));
+        assert!(wins(Color::White, ["d5", "d4", "d7"], false));
+    }
+
+    #[test]
+    fn unprotected_pawns_are_taken() {
+        assert!(!wins(Color::White, ["a1", "e4", "d5"], false));
+
+        // outside the square of the pawn, the king is too late
+        assert!(wins(Color::White, ["a1", "e5", "a8"], true));
+
+        // a rank back, it's in time
+        assert!(!wins(Color::White, ["a1", "e4", "a8"], true));
+    }
+
+    #[test]
+    fn black_pawns_on_the_kingside() {
+        // the same positions as above, flipped both ways
+        for strong_to_move in [true, false] {
+            assert!(!wins(Color::Black, ["g4", "h4", "h1"], strong_to_move));
+            assert!(wins(Color::Black, ["e3", "e5", "e1"], strong_to_move));
+        }
+
+        assert!(!wins(Color::Black, ["e4", "e5", "e2"], true));
+        assert!(wins(Color::Black, ["e4", "e5", "e2"], false));
+    }
+}
@@ -0,0 +1,219 @@
+// Knowledge about specific endgames, looked up by the material on the board
+//
+// Some endgames get a dedicated evaluation that replaces the generic terms,
+// because they know the result and how to make progress. Others only scale
+// the generic evaluation towards a draw.
+use std::{collections::HashMap, sync::OnceLock};
+
+use super::{BISHOP_VALUE, KNIGHT_VALUE, PAWN_VALUE, QUEEN_VALUE, ROOK_VALUE};
+use crate::prelude::{gui::PieceType, *};
+
+pub mod kpk;
+
+/// Clearly winning, but below any tablebase win or mate
+pub const KNOWN_WIN: i32 = 10_000;
+
+/// Scale factors are out of this
+pub const SCALE_NORMAL: i32 = 64;
+
+/// Opposite-colored bishops and pawns only
+const SCALE_OCB: i32 = 22;
+
+/// Opposite-colored bishops with other pieces
+const SCALE_OCB_PIECES: i32 = 46;
+
+/// What the lookup knows about a position
+pub enum Endgame {
+    /// Replaces the generic evaluation, white-relative
+    Exact(Score),
+
+    /// Multiplies the generic evaluation by this out of [`SCALE_NORMAL`]
+    Scale(i32),
+}
+
+/// Evaluator for one endgame, given the side with the extra material
+type EndgameFn = fn(&Position, Color) -> Score;
+
+/// Pieces of each color, packed four bits per piece type: pawns, knights,
+/// bishops, rooks, queens
+fn signature(position: &Position, color: Color) -> u32 {
+    let pieces = position.pieces_of_col(color);
+
+    [
+        PieceType::Pawn,
+        PieceType::Knight,
+        PieceType::Bishop,
+        PieceType::Rook,
+        PieceType::Queen,
+    ]
+    .into_iter()
+    .enumerate()
+    .map(|(i, piece_type)| {
+        let count = (position.pieces_of_type(piece_type) & pieces).count_ones().min(15);
+        count << (4 * i)
+    })
+    .sum()
+}
+
+/// Signature of a side written like `KBN`
+fn parse_signature(pieces: &str) -> u32 {
+    pieces
+        .chars()
+        .filter_map(|c| "PNBRQ".find(c))
+        .map(|i| 1 << (4 * i))
+        .sum()
+}
+
+/// Whole-board key, with the stronger side's material first
+fn key(strong: u32, weak: u32) -> u64 {
+    (strong as u64) << 32 | weak as u64
+}
+
+fn endgames() -> &'static HashMap<u64, EndgameFn> {
+    static ENDGAMES: OnceLock<HashMap<u64, EndgameFn>> = OnceLock::new();
+
+    ENDGAMES.get_or_init(|| {
+        let endgames: [(&str, EndgameFn); 4] = [
+            ("KPvK", kpk_eval),
+            ("KBNvK", kbnk),
+            ("KRvK", kxk),
+            ("KQvK", kxk),
+        ];
+
+        endgames
+            .into_iter()
+            .map(|(name, eval)| {
+                let (strong, weak) = name.split_once('v').unwrap();
+                (key(parse_signature(strong), parse_signature(weak)), eval)
+            })
+            .collect()
+    })
+}
+
+/// Find what is known about the position's material
+pub fn probe(position: &Position) -> Option<Endgame> {
+    let white = signature(position, Color::White);
+    let black = signature(position, Color::Black);
+
+    if let Some(eval) = endgames().get(&key(white, black)) {
+        return Some(Endgame::Exact(eval(position, Color::White)));
+    }
+
+    if let Some(eval) = endgames().get(&key(black, white)) {
+        return Some(Endgame::Exact(eval(position, Color::Black)));
+    }
+
+    opposite_bishops(position, white, black).map(Endgame::Scale)
+}
+
+/// Bonus for pushing a king to the edge of the board
+fn edge_bonus(square: Square) -> i32 {
+    let file = square.file().min(square.file().flip()) as i32;
+    let rank = square.rank().min(square.rank().flip()) as i32;
+
+    90 - 10 * (file + rank) - 5 * file.min(rank)
+}
+
+/// Bonus for bringing the kings together
+fn close_bonus(a: Square, b: Square) -> i32 {
+    70 - 10 * a.distance_to(b) as i32
+}
+
+fn king_of(position: &Position, color: Color) -> Square {
+    (position.kings & position.pieces_of_col(color)).last_bit()
+}
+
+fn non_pawn_material(position: &Position, color: Color) -> i32 {
+    let pieces = position.pieces_of_col(color);
+
+    [
+        (position.knights, KNIGHT_VALUE),
+        (position.bishops, BISHOP_VALUE),
+        (position.rooks, ROOK_VALUE),
+        (position.queens, QUEEN_VALUE),
+    ]
+    .into_iter()
+    .map(|(bb, value)| (bb & pieces).count_ones() as i32 * value)
+    .sum()
+}
+
+/// Queen or rook against a bare king: drive the king to the edge
+fn kxk(position: &Position, strong: Color) -> Score {
+    let strong_king = king_of(position, strong);
+    let weak_king = king_of(position, strong.other());
+
+    let score = KNOWN_WIN
+        + non_pawn_material(position, strong)
+        + edge_bonus(weak_king)
+        + close_bonus(strong_king, weak_king);
+
+    Score(score).relative(strong)
+}
+
+/// Bishop and knight against a bare king: only the corners of the bishop's
+/// color can be mated in
+fn kbnk(position: &Position, strong: Color) -> Score {
+    let strong_king = king_of(position, strong);
+    let weak_king = king_of(position, strong.other());
+    let bishop = (position.bishops & position.pieces_of_col(strong)).last_bit();
+
+    let corners = if bishop.is_dark() {
+        [(Rank::First, File::A), (Rank::Eighth, File::H)]
+    } else {
+        [(Rank::First, File::H), (Rank::Eighth, File::A)]
+    };
+
+    let corner_distance = corners
+        .iter()
+        .map(|&(rank, file)| weak_king.distance_to(Square::new(rank, file)))
+        .min()
+        .unwrap() as i32;
+
+    let score = KNOWN_WIN
+        + KNIGHT_VALUE
+        + BISHOP_VALUE
+        + 20 * (7 - corner_distance)
+        + close_bonus(strong_king, weak_king);
+
+    Score(score).relative(strong)
+}
+
+/// King and pawn against king, straight from the bitbase
+fn kpk_eval(position: &Position, strong: Color) -> Score {
+    let strong_king = king_of(position, strong);
+    let weak_king = king_of(position, strong.other());
+    let pawn = position.pawns.last_bit();
+
+    if !kpk::probe(strong, strong_king, pawn, weak_king, position.turn == strong) {
+        return Score::DRAW;
+    }
+
+    let rank = pawn.rank().relative(strong) as i32;
+
+    Score(KNOWN_WIN + PAWN_VALUE + 10 * rank).relative(strong)
+}
+
+/// One bishop each on opposite colors makes most endings drawish
+fn opposite_bishops(position: &Position, white: u32, black: u32) -> Option<i32> {
+    const PAWNS: u32 = 0xf;
+    const MINORS: u32 = 0xff0;
+
+    let one_bishop = parse_signature("B");
+
+    if white & MINORS != one_bishop || black & MINORS != one_bishop {
+        return None;
+    }
+
+    let white_bishop = (position.bishops & position.n_white).last_bit();
+    let black_bishop = (position.bishops & position.n_black).last_bit();
+
+    if white_bishop.is_dark() == black_bishop.is_dark() {
+        return None;
+    }
+
+    if white & !PAWNS == one_bishop && black & !PAWNS == one_bishop {
+        Some(SCALE_OCB)
+    } else {
+        Some(SCALE_OCB_PIECES)
+    }
+}
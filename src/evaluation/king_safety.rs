@@ -0,0 +1,135 @@
+use super::{
+    pawns::{adjacent_files, ranks_ahead},
+    EvalParams, DEFAULT_PARAMS,
+};
+use crate::{movegen, prelude::*};
+
+/// Bonus per friendly pawn one and two ranks in front of the king
+pub const SHIELD: [i32; 2] = [15, 8];
+
+/// Penalty per enemy pawn on the king's files, indexed by ranks away from the king
+pub const STORM: [i32; 4] = [0, -5, -15, -10];
+
+/// Penalty per file next to the king without friendly pawns
+pub const SEMI_OPEN_FILE: i32 = -12;
+
+/// Penalty per file next to the king without any pawns, instead of [`SEMI_OPEN_FILE`]
+pub const OPEN_FILE: i32 = -20;
+
+/// Knight, bishop, rook, queen: weight per attacked square in the king zone
+pub const ATTACK_WEIGHT: [i32; 4] = [6, 5, 8, 12];
+
+/// Percentage of the attack weight that counts, indexed by number of attackers
+pub const ATTACKER_SCALE: [i32; 8] = [0, 0, 50, 75, 88, 94, 97, 99];
+
+/// Scores pawn shield, pawn storms, open files around the king
+/// and enemy pieces attacking the squares around it
+///
+/// Switched off in the endgame, where the king should be active instead
+#[derive(Clone, Copy)]
+pub struct KingSafetyEvaluator;
+
+impl KingSafetyEvaluator {
+    fn eval_side(position: &Position, color: Color, params: &EvalParams) -> i32 {
+        let kings = position.kings & position.pieces_of_col(color);
+
+        if kings == Bitboard::EMPTY {
+            return 0;
+        }
+
+        let king_at = kings.last_bit();
+        let theirs = position.pieces_of_col(color.other());
+        let our_pawns = position.pawns & position.pieces_of_col(color);
+        let their_pawns = position.pawns & theirs;
+
+        let files = Bitboard::file(king_at.file()) | adjacent_files(king_at.file());
+        let ahead = ranks_ahead(color, king_at.rank());
+
+        let mut score = 0;
+
+        for (i, bonus) in params.shield.into_iter().enumerate() {
+            let forward = match color {
+                Color::White => 1 + i as i8,
+                Color::Black => -1 - i as i8,
+            };
+
+            let Some(rank) = king_at.rank().offset(forward) else {
+                break;
+            };
+
+            let shield = our_pawns & files & Bitboard::rank(rank);
+            score += shield.count_bits() as i32 * bonus;
+        }
+
+        for pawn in (their_pawns & files & ahead).bit_pos_iter() {
+            let distance = (pawn.rank() as i8 - king_at.rank() as i8).unsigned_abs() as usize;
+
+            if distance < params.storm.len() {
+                score += params.storm[distance];
+            }
+        }
+
+        for file in (-1..=1).filter_map(|delta| king_at.file().offset(delta)) {
+            let file = Bitboard::file(file);
+
+            if position.pawns & file == Bitboard::EMPTY {
+                score += params.open_file;
+            } else if our_pawns & file == Bitboard::EMPTY {
+                score += params.semi_open_file;
+            }
+        }
+
+        let zone = movegen::king_attacks(king_at) | king_at.to_bitboard();
+        let occupied = position.occupied();
+
+        let pieces = [
+            position.knights,
+            position.bishops,
+            position.rooks,
+            position.queens,
+        ];
+
+        let mut attackers = 0;
+        let mut weight = 0;
+
+        for (i, pieces) in pieces.into_iter().enumerate() {
+            for square in (pieces & theirs).bit_pos_iter() {
+                let attacks = match i {
+                    0 => movegen::knight(square),
+                    1 => movegen::bishop(square, occupied),
+                    2 => movegen::rook(square, occupied),
+                    _ => movegen::queen(square, occupied),
+                } & zone;
+
+                if attacks != Bitboard::EMPTY {
+                    attackers += 1;
+                    weight += attacks.count_bits() as i32 * params.attack_weight[i];
+                }
+            }
+        }
+
+        let scale = params.attacker_scale[attackers.min(params.attacker_scale.len() - 1)];
+        score -= weight * scale / 100;
+
+        score
+    }
+
+    pub fn eval_with(position: &Position, params: &EvalParams) -> Score {
+        let endgame = position.pieces().count_ones() <= 7;
+
+        if endgame {
+            return Score::ZERO;
+        }
+
+        let white = Self::eval_side(position, Color::White, params);
+        let black = Self::eval_side(position, Color::Black, params);
+
+        Score::cp(white - black)
+    }
+}
+
+impl StaticEvaluator for KingSafetyEvaluator {
+    fn eval(&self, position: &Position) -> Score {
+        Self::eval_with(position, &DEFAULT_PARAMS)
+    }
+}
@@ -1,25 +1,38 @@
+use super::{EvalParams, DEFAULT_PARAMS};
 use crate::prelude::*;
 
+pub const PAWN_VALUE: i32 = 100;
+pub const KNIGHT_VALUE: i32 = 300;
+pub const BISHOP_VALUE: i32 = 300;
+pub const ROOK_VALUE: i32 = 500;
+pub const QUEEN_VALUE: i32 = 900;
+
 #[derive(Clone, Copy)]
 pub struct MaterialEvaluator;
 
+impl MaterialEvaluator {
+    fn eval_color(position: &Position, color: Bitboard, params: &EvalParams) -> i32 {
+        let pawns = (position.pawns & color).count_ones() as i32;
+        let knights = (position.knights & color).count_ones() as i32;
+        let bishops = (position.bishops & color).count_ones() as i32;
+        let rooks = (position.rooks & color).count_ones() as i32;
+        let queens = (position.queens & color).count_ones() as i32;
+
+        let [pawn, knight, bishop, rook, queen] = params.material;
+
+        (pawn * pawns) + (knight * knights) + (bishop * bishops) + (rook * rooks) + (queen * queens)
+    }
+
+    pub fn eval_with(position: &Position, params: &EvalParams) -> Score {
+        let wscore = Self::eval_color(position, position.n_white, params);
+        let bscore = Self::eval_color(position, position.n_black, params);
+
+        Score::cp(wscore - bscore)
+    }
+}
+
 impl StaticEvaluator for MaterialEvaluator {
-    fn eval(&self, position: &Position) -> f64 {
-        let wpawns = (position.pawns & position.n_white).count_ones() as f64;
-        let wknights = (position.knights & position.n_white).count_ones() as f64;
-        let wbishops = (position.bishops & position.n_white).count_ones() as f64;
-        let wrooks = (position.rooks & position.n_white).count_ones() as f64;
-        let wqueens = (position.queens & position.n_white).count_ones() as f64;
-
-        let bpawns = (position.pawns & position.n_black).count_ones() as f64;
-        let bknights = (position.knights & position.n_black).count_ones() as f64;
-        let bbishops = (position.bishops & position.n_black).count_ones() as f64;
-        let brooks = (position.rooks & position.n_black).count_ones() as f64;
-        let bqueens = (position.queens & position.n_black).count_ones() as f64;
-
-        let wscore = wpawns + (3. * wknights) + (3. * wbishops) + (5. * wrooks) + (9. * wqueens);
-        let bscore = bpawns + (3. * bknights) + (3. * bbishops) + (5. * brooks) + (9. * bqueens);
-
-        wscore - bscore
+    fn eval(&self, position: &Position) -> Score {
+        Self::eval_with(position, &DEFAULT_PARAMS)
     }
 }
@@ -0,0 +1,61 @@
+use super::{EvalParams, DEFAULT_PARAMS};
+use crate::{movegen, prelude::*};
+
+/// Knight, bishop, rook, queen
+pub const MOBILITY_WEIGHT: [i32; 4] = [4, 4, 2, 1];
+
+/// Number of safe squares a piece is expected to have, below which it is penalised
+pub const MOBILITY_OFFSET: [i32; 4] = [4, 6, 7, 13];
+
+/// Scores the number of squares each piece can reach that are not
+/// attacked by enemy pawns
+#[derive(Clone, Copy)]
+pub struct MobilityEvaluator;
+
+impl MobilityEvaluator {
+    fn eval_side(position: &Position, color: Color, params: &EvalParams) -> i32 {
+        let mut score = 0;
+
+        let ours = position.pieces_of_col(color);
+        let their_pawns = position.pawns & position.pieces_of_col(color.other());
+        let occupied = position.occupied();
+        let safe = !ours & !movegen::pawn_attacks(their_pawns, color.other());
+
+        let pieces = [
+            position.knights,
+            position.bishops,
+            position.rooks,
+            position.queens,
+        ];
+
+        for (i, pieces) in pieces.into_iter().enumerate() {
+            for square in (pieces & ours).bit_pos_iter() {
+                let attacks = match i {
+                    0 => movegen::knight(square),
+                    1 => movegen::bishop(square, occupied),
+                    2 => movegen::rook(square, occupied),
+                    _ => movegen::queen(square, occupied),
+                };
+
+                let mobility = (attacks & safe).count_bits() as i32;
+
+                score += (mobility - params.mobility_offset[i]) * params.mobility_weight[i];
+            }
+        }
+
+        score
+    }
+
+    pub fn eval_with(position: &Position, params: &EvalParams) -> Score {
+        let white = Self::eval_side(position, Color::White, params);
+        let black = Self::eval_side(position, Color::Black, params);
+
+        Score::cp(white - black)
+    }
+}
+
+impl StaticEvaluator for MobilityEvaluator {
+    fn eval(&self, position: &Position) -> Score {
+        Self::eval_with(position, &DEFAULT_PARAMS)
+    }
+}
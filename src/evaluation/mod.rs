@@ -1,24 +1,120 @@
+use std::{
+    io,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, RwLock,
+    },
+};
+
 use crate::prelude::*;
 
 pub mod depth;
+pub mod endgame;
+pub mod king_safety;
 pub mod material;
+pub mod mobility;
+pub mod nnue;
+pub mod params;
+pub mod pawns;
+pub mod score;
 pub mod tables;
+pub mod tuned;
+pub mod tuning;
 
-pub trait StaticEvaluator: Copy + Send + Sync + 'static {
-    fn eval(&self, position: &Position) -> f64;
+/// Evaluates a position without searching, from white's point of view
+pub trait StaticEvaluator: Clone + Send + Sync + 'static {
+    fn eval(&self, position: &Position) -> Score;
 }
 
+pub use king_safety::KingSafetyEvaluator;
 pub use material::*;
+pub use mobility::MobilityEvaluator;
+pub use nnue::{AccumulatorStack, NnueEvaluator};
+pub use params::{EvalParams, DEFAULT_PARAMS};
+pub use pawns::PawnStructureEvaluator;
+pub use score::*;
 pub use tables::*;
 
+static USE_NNUE: AtomicBool = AtomicBool::new(false);
+static NETWORK: RwLock<Option<Arc<nnue::Network>>> = RwLock::new(None);
+
 pub struct CompoundEvaluator;
 
 impl CompoundEvaluator {
-    pub fn eval(position: &Position) -> f64 {
-        let mut score = 0.;
+    /// Load the network used when NNUE is switched on (the `EvalFile` UCI option)
+    pub fn load_network(path: &str) -> io::Result<()> {
+        // searches still running keep the network they started with
+        let net = nnue::Network::load(path)?;
+        *NETWORK.write().unwrap() = Some(net);
+
+        Ok(())
+    }
+
+    /// Switch between the network and the hand-written terms (the `UseNNUE` UCI option)
+    pub fn set_use_nnue(enabled: bool) {
+        USE_NNUE.store(enabled, Ordering::Relaxed);
+    }
+
+    /// The network evaluator, if it is switched on and a network is loaded
+    pub fn nnue() -> Option<NnueEvaluator> {
+        if !USE_NNUE.load(Ordering::Relaxed) {
+            return None;
+        }
+
+        let net = NETWORK.read().unwrap().clone()?;
+
+        Some(NnueEvaluator { net })
+    }
+
+    pub fn eval(position: &Position) -> Score {
+        match Self::nnue() {
+            Some(nnue) => Self::scaled(position, |position| nnue.eval(position)),
+            None => Self::scaled(position, Self::eval_generic),
+        }
+    }
+
+    /// [`CompoundEvaluator::eval`] with the network, from the accumulator
+    /// the search keeps in step with `position`
+    pub fn eval_incremental(position: &Position, accumulators: &AccumulatorStack) -> Score {
+        Self::scaled(position, |position| accumulators.eval(position.turn))
+    }
+
+    /// Known endgames are scored exactly or scaled towards a draw
+    fn scaled(position: &Position, eval: impl FnOnce(&Position) -> Score) -> Score {
+        let scale = match endgame::probe(position) {
+            Some(endgame::Endgame::Exact(score)) => return score,
+            Some(endgame::Endgame::Scale(scale)) => scale,
+            None => endgame::SCALE_NORMAL,
+        };
+
+        Score(eval(position).0 * scale / endgame::SCALE_NORMAL)
+    }
+
+    fn eval_generic(position: &Position) -> Score {
+        let mut score = Score::ZERO;
+
+        score += MaterialEvaluator.eval(position);
+        score += PieceTableEvaluator.eval(position);
+        score += PawnStructureEvaluator.eval(position);
+        score += MobilityEvaluator.eval(position);
+        score += KingSafetyEvaluator.eval(position);
+
+        score
+    }
+
+    /// Evaluate with arbitrary weights, bypassing the pawn hash table
+    pub fn eval_with(position: &Position, params: &EvalParams) -> Score {
+        let mut score = Score::ZERO;
 
-        score += MaterialEvaluator.eval(position) * 2.0;
-        score += PieceTableEvaluator.eval(position) * 1.0;
+        score += MaterialEvaluator::eval_with(position, params);
+        score += PieceTableEvaluator::eval_with(position, params);
+        score += PawnStructureEvaluator::eval_uncached(
+            position.pawns & position.n_white,
+            position.pawns & position.n_black,
+            params,
+        );
+        score += MobilityEvaluator::eval_with(position, params);
+        score += KingSafetyEvaluator::eval_with(position, params);
 
         score
     }
@@ -0,0 +1,482 @@
+//! Efficiently updatable neural network evaluation
+//!
+//! A single hidden layer network over 768 inputs (color x piece type x square)
+//! seen from both sides, with a clipped ReLU and one output neuron.
+//!
+//! # Weight file format
+//!
+//! Every number is little-endian.
+//!
+//! | field             | type  | count              |
+//! |-------------------|-------|--------------------|
+//! | magic `b"CNNU"`   | u8    | 4                  |
+//! | version (1)       | u32   | 1                  |
+//! | hidden size (256) | u32   | 1                  |
+//! | feature weights   | i16   | 768 * hidden size  |
+//! | feature biases    | i16   | hidden size        |
+//! | output weights    | i16   | 2 * hidden size    |
+//! | output bias       | i32   | 1                  |
+//!
+//! Feature weights are stored feature-major: the `hidden size` weights of
+//! feature 0 come first. A feature index, from the point of view of
+//! `perspective`, is `side * 384 + piece_type * 64 + square`, where `side`
+//! is 0 for the perspective's own pieces and 1 for the opponent's, piece
+//! types are ordered pawn, knight, bishop, rook, queen, king, and squares are
+//! little-endian rank-file, flipped vertically (`square ^ 56`) for black.
+//!
+//! The first half of the output weights applies to the side to move, the
+//! second half to the other side. Feature weights are quantised by [`QA`],
+//! output weights by [`QB`], and the output is scaled by [`SCALE`] to give
+//! centipawns.
+use std::{alloc, fs, io, sync::Arc};
+
+use super::MAX_PLY;
+use crate::prelude::{gui::PieceType, *};
+
+pub const INPUTS: usize = 768;
+pub const HIDDEN: usize = 256;
+
+pub const QA: i32 = 255;
+pub const QB: i32 = 64;
+pub const SCALE: i32 = 400;
+
+const MAGIC: &[u8; 4] = b"CNNU";
+const VERSION: u32 = 1;
+const HEADER_LEN: usize = 12;
+const FILE_LEN: usize = HEADER_LEN + 2 * (INPUTS * HIDDEN + HIDDEN + 2 * HIDDEN) + 4;
+
+/// The output stays below tablebase wins and mates, which the search tells
+/// apart from evaluations by their value
+const MAX_EVAL: i32 = Score::TB_WIN.0 - MAX_PLY - 1;
+
+/// A piece on a square, one input of the network
+pub type Feature = (Color, PieceType, Square);
+
+#[repr(C, align(64))]
+pub struct Network {
+    feature_weights: [[i16; HIDDEN]; INPUTS],
+    feature_bias: [i16; HIDDEN],
+    output_weights: [[i16; HIDDEN]; 2],
+    output_bias: i32,
+}
+
+fn invalid(msg: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg.to_string())
+}
+
+/// Index of a piece in the input layer, as seen by `perspective`
+pub fn feature(perspective: Color, color: Color, piece_type: PieceType, square: Square) -> usize {
+    let side = if color == perspective { 0 } else { 1 };
+    side * 384 + piece_type as usize * 64 + square.relative(perspective).index()
+}
+
+fn crelu(x: i32) -> i32 {
+    x.clamp(0, QA)
+}
+
+impl Network {
+    /// A network with every weight set to zero
+    fn zeroed() -> Box<Self> {
+        // too big for the stack, and all zeroes is a valid network
+        let layout = alloc::Layout::new::<Network>();
+
+        unsafe {
+            let ptr = alloc::alloc_zeroed(layout) as *mut Network;
+
+            if ptr.is_null() {
+                alloc::handle_alloc_error(layout);
+            }
+
+            Box::from_raw(ptr)
+        }
+    }
+
+    /// Load a network in the format described in the [module docs](self)
+    pub fn load(path: &str) -> io::Result<Arc<Self>> {
+        let bytes = fs::read(path)?;
+
+        if bytes.len() != FILE_LEN {
+            return Err(invalid("wrong file size"));
+        }
+
+        if &bytes[0..4] != MAGIC {
+            return Err(invalid("not a network file"));
+        }
+
+        let version = u32::from_le_bytes(bytes[4..8].try_into().unwrap());
+        let hidden = u32::from_le_bytes(bytes[8..12].try_into().unwrap());
+
+        if version != VERSION || hidden as usize != HIDDEN {
+            return Err(invalid("unsupported network version or size"));
+        }
+
+        let mut net = Self::zeroed();
+        let mut values = bytes[HEADER_LEN..]
+            .chunks_exact(2)
+            .map(|b| i16::from_le_bytes([b[0], b[1]]));
+
+        let weights = net
+            .feature_weights
+            .iter_mut()
+            .flatten()
+            .chain(net.feature_bias.iter_mut())
+            .chain(net.output_weights.iter_mut().flatten());
+
+        for weight in weights {
+            *weight = values.next().unwrap();
+        }
+
+        net.output_bias = i32::from_le_bytes(bytes[FILE_LEN - 4..].try_into().unwrap());
+
+        Ok(Arc::from(net))
+    }
+
+    /// Side-to-move relative evaluation of an up to date accumulator
+    pub fn evaluate(&self, acc: &Accumulator, turn: Color) -> Score {
+        let (us, them) = match turn {
+            Color::White => (&acc.white, &acc.black),
+            Color::Black => (&acc.black, &acc.white),
+        };
+
+        // fixed length loops so the compiler can vectorise them, in i64
+        // because nothing stops a file from having weights that overflow i32
+        let weighted = |acc: &[i32; HIDDEN], weights: &[i16; HIDDEN]| {
+            acc.iter()
+                .zip(weights)
+                .map(|(&x, &w)| (crelu(x) * w as i32) as i64)
+                .sum::<i64>()
+        };
+
+        let sum = weighted(us, &self.output_weights[0]) + weighted(them, &self.output_weights[1]);
+        let out = (sum + self.output_bias as i64) * SCALE as i64 / (QA * QB) as i64;
+
+        Score::cp(out.clamp(-MAX_EVAL as i64, MAX_EVAL as i64) as i32)
+    }
+}
+
+/// The hidden layer before activation, from both sides' point of view.
+/// Kept in i32, the i16 weights of 32 pieces can add up past `i16::MAX`
+#[derive(Clone, Copy)]
+#[repr(C, align(64))]
+pub struct Accumulator {
+    pub white: [i32; HIDDEN],
+    pub black: [i32; HIDDEN],
+}
+
+impl Accumulator {
+    /// Build the accumulator from scratch
+    pub fn new(net: &Network, position: &Position) -> Self {
+        let mut acc = Accumulator {
+            white: net.feature_bias.map(i32::from),
+            black: net.feature_bias.map(i32::from),
+        };
+
+        for color in [Color::White, Color::Black] {
+            for piece_type in PieceType::every() {
+                let pieces = position.pieces_of_type(piece_type) & position.pieces_of_col(color);
+
+                for square in pieces.bit_pos_iter() {
+                    acc.add(net, (color, piece_type, square));
+                }
+            }
+        }
+
+        acc
+    }
+
+    pub fn add(&mut self, net: &Network, (color, piece_type, square): Feature) {
+        let white = &net.feature_weights[feature(Color::White, color, piece_type, square)];
+        let black = &net.feature_weights[feature(Color::Black, color, piece_type, square)];
+
+        for (acc, &weight) in self.white.iter_mut().zip(white) {
+            *acc += weight as i32;
+        }
+
+        for (acc, &weight) in self.black.iter_mut().zip(black) {
+            *acc += weight as i32;
+        }
+    }
+
+    pub fn remove(&mut self, net: &Network, (color, piece_type, square): Feature) {
+        let white = &net.feature_weights[feature(Color::White, color, piece_type, square)];
+        let black = &net.feature_weights[feature(Color::Black, color, piece_type, square)];
+
+        for (acc, &weight) in self.white.iter_mut().zip(white) {
+            *acc -= weight as i32;
+        }
+
+        for (acc, &weight) in self.black.iter_mut().zip(black) {
+            *acc -= weight as i32;
+        }
+    }
+
+    /// Update the accumulator of `position` to the one after `mv`, which
+    /// must be legal
+    pub fn update(&mut self, net: &Network, position: &Position, mv: Move) {
+        let (removed, added) = changes(position, mv);
+
+        for piece in removed.into_iter().flatten() {
+            self.remove(net, piece);
+        }
+
+        for piece in added.into_iter().flatten() {
+            self.add(net, piece);
+        }
+    }
+}
+
+/// The pieces a move takes off the board and the ones it puts on: the
+/// mover and what it captures, or the king and the rook when castling
+fn changes(position: &Position, mv: Move) -> ([Option<Feature>; 2], [Option<Feature>; 2]) {
+    let us = position.turn;
+    let piece = position.piece_at(mv.from).expect("a piece to move");
+
+    if position.is_castle(mv) {
+        // the king takes its own rook, then both go to their usual squares
+        let (king_file, rook_file) = if mv.to.file() > mv.from.file() {
+            (File::G, File::F)
+        } else {
+            (File::C, File::D)
+        };
+
+        let rank = mv.from.rank();
+
+        return (
+            [
+                Some((us, PieceType::King, mv.from)),
+                Some((us, PieceType::Rook, mv.to)),
+            ],
+            [
+                Some((us, PieceType::King, Square::new(rank, king_file))),
+                Some((us, PieceType::Rook, Square::new(rank, rook_file))),
+            ],
+        );
+    }
+
+    let en_passant = piece == PieceType::Pawn && position.ep_target == Some(mv.to);
+
+    let captured = if en_passant {
+        // the pawn taken is beside the one taking it
+        let square = Square::new(mv.from.rank(), mv.to.file());
+        Some((us.other(), PieceType::Pawn, square))
+    } else {
+        position
+            .piece_at(mv.to)
+            .map(|victim| (us.other(), victim, mv.to))
+    };
+
+    let arrives = match mv.to.rank() {
+        Rank::First | Rank::Eighth if piece == PieceType::Pawn => {
+            mv.promotion.unwrap_or(PieceType::Queen)
+        }
+        _ => piece,
+    };
+
+    (
+        [Some((us, piece, mv.from)), captured],
+        [Some((us, arrives, mv.to)), None],
+    )
+}
+
+/// Accumulators for every position on the current line of play
+///
+/// Positions are copied rather than unmade, so the search pushes an
+/// accumulator updated with the move's pieces for every move it makes, and
+/// pops it when it goes back up
+pub struct AccumulatorStack {
+    net: Arc<Network>,
+    stack: Vec<Accumulator>,
+}
+
+impl AccumulatorStack {
+    pub fn new(net: Arc<Network>, root: &Position) -> Self {
+        let mut stack = Vec::with_capacity(MAX_PLY as usize + 1);
+        stack.push(Accumulator::new(&net, root));
+
+        Self { net, stack }
+    }
+
+    /// Push the accumulator of `position` after `mv`
+    pub fn make_move(&mut self, position: &Position, mv: Move) {
+        let mut acc = *self.current();
+        acc.update(&self.net, position, mv);
+
+        self.stack.push(acc);
+    }
+
+    pub fn unmake_move(&mut self) {
+        if self.stack.len() > 1 {
+            self.stack.pop();
+        }
+    }
+
+    pub fn current(&self) -> &Accumulator {
+        self.stack.last().unwrap()
+    }
+
+    /// White-relative evaluation of the position on top of the stack
+    pub fn eval(&self, turn: Color) -> Score {
+        self.net.evaluate(self.current(), turn).relative(turn)
+    }
+}
+
+/// Evaluates with a loaded [`Network`], refreshing the accumulator every time
+///
+/// The search keeps an [`AccumulatorStack`] to evaluate incrementally
+#[derive(Clone)]
+pub struct NnueEvaluator {
+    pub net: Arc<Network>,
+}
+
+impl NnueEvaluator {
+    /// An accumulator stack for a search from `root`
+    pub fn stack(&self, root: &Position) -> AccumulatorStack {
+        AccumulatorStack::new(Arc::clone(&self.net), root)
+    }
+}
+
+impl StaticEvaluator for NnueEvaluator {
+    fn eval(&self, position: &Position) -> Score {
+        let acc = Accumulator::new(&self.net, position);
+
+        self.net
+            .evaluate(&acc, position.turn)
+            .relative(position.turn)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::rng::Rng;
+
+    fn random_network(rng: &mut Rng) -> Arc<Network> {
+        let mut net = Network::zeroed();
+        let weights = net
+            .feature_weights
+            .iter_mut()
+            .flatten()
+            .chain(net.feature_bias.iter_mut())
+            .chain(net.output_weights.iter_mut().flatten());
+
+        for weight in weights {
+            *weight = rng.next_u64() as i16;
+        }
+
+        Arc::from(net)
+    }
+
+    fn assert_in_step(stack: &AccumulatorStack, position: &Position) {
+        let fresh = Accumulator::new(&stack.net, position);
+
+        assert!(
+            stack.current().white == fresh.white && stack.current().black == fresh.black,
+            "{}",
+            position.fen()
+        );
+    }
+
+    #[test]
+    fn updates_match_a_refresh() {
+        let mut rng = Rng::new(7);
+        let net = random_network(&mut rng);
+
+        let starts = [
+            Position::new(),
+            // castling both ways
+            Position::from_fen(
+                "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1",
+            )
+            .unwrap(),
+            // promotions, with and without captures
+            Position::from_fen("n1n5/PPPk4/8/8/8/8/4Kppp/5N1N b - - 0 1").unwrap(),
+            // en passant
+            Position::from_fen("rnbqkbnr/ppp1p1pp/8/3pPp2/8/8/PPPP1PPP/RNBQKBNR w KQkq f6 0 3")
+                .unwrap(),
+            // castling with the king next to its rook
+            Position::from_960_index(0).unwrap(),
+        ];
+
+        for start in starts {
+            for _ in 0..20 {
+                let mut position = start;
+                let mut stack = AccumulatorStack::new(Arc::clone(&net), &position);
+
+                for _ in 0..60 {
+                    let moves = position.legal_moves();
+
+                    if moves.is_empty() {
+                        break;
+                    }
+
+                    let mv = moves[rng.below(moves.len() as u64) as usize];
+                    stack.make_move(&position, mv);
+                    position.play(mv);
+
+                    assert_in_step(&stack, &position);
+                }
+
+                while stack.stack.len() > 1 {
+                    stack.unmake_move();
+                }
+
+                assert_in_step(&stack, &start);
+            }
+        }
+    }
+
+    #[test]
+    fn static_eval_matches_the_stack() {
+        let mut rng = Rng::new(30);
+        let evaluator = NnueEvaluator {
+            net: random_network(&mut rng),
+        };
+
+        let mut position = Position::new();
+        let mut stack = evaluator.stack(&position);
+
+        for _ in 0..40 {
+            let moves = position.legal_moves();
+
+            if moves.is_empty() {
+                break;
+            }
+
+            let mv = moves[rng.below(moves.len() as u64) as usize];
+            stack.make_move(&position, mv);
+            position.play(mv);
+
+            let incremental = stack.eval(position.turn);
+            assert_eq!(evaluator.eval(&position), incremental, "{}", position.fen());
+        }
+    }
+
+    #[test]
+    fn large_weights_dont_overflow() {
+        let mut net = Network::zeroed();
+        net.feature_weights.iter_mut().for_each(|row| row.fill(i16::MAX));
+        net.feature_bias.fill(i16::MAX);
+        net.output_weights.iter_mut().for_each(|row| row.fill(i16::MAX));
+        net.output_bias = i32::MAX;
+
+        let acc = Accumulator::new(&net, &Position::new());
+        let score = net.evaluate(&acc, Color::White);
+
+        assert_eq!(score, Score::cp(MAX_EVAL));
+    }
+
+    #[test]
+    fn output_stays_below_mates() {
+        let mut net = Network::zeroed();
+
+        for bias in [i32::MAX, i32::MIN] {
+            net.output_bias = bias;
+
+            let acc = Accumulator::new(&net, &Position::new());
+            let score = net.evaluate(&acc, Color::White);
+
+            assert!(score.0.abs() < Score::TB_WIN.0 - MAX_PLY, "{score}");
+            assert!(!score.is_mate());
+        }
+    }
+}
@@ -0,0 +1,236 @@
+use super::{king_safety, material, mobility, pawns, tables, tuned};
+use crate::prelude::*;
+
+/// Every weight used by the evaluation, in centipawns
+///
+/// Flattened into a single parameter vector with [`EvalParams::to_vec`]
+/// for tuning
+#[derive(Clone, Debug, PartialEq)]
+pub struct EvalParams {
+    /// pawn, knight, bishop, rook, queen
+    pub material: [i32; 5],
+
+    pub pawn: [i32; 64],
+    pub pawn_endgame: [i32; 64],
+    pub knight: [i32; 64],
+    pub bishop: [i32; 64],
+    pub rook: [i32; 64],
+    pub queen: [i32; 64],
+    pub king: [i32; 64],
+    pub king_endgame: [i32; 64],
+
+    pub passed: [i32; 8],
+    pub connected: [i32; 8],
+    pub phalanx: i32,
+    pub doubled: i32,
+    pub isolated: i32,
+    pub backward: i32,
+
+    pub mobility_weight: [i32; 4],
+    pub mobility_offset: [i32; 4],
+
+    pub shield: [i32; 2],
+    pub storm: [i32; 4],
+    pub semi_open_file: i32,
+    pub open_file: i32,
+    pub attack_weight: [i32; 4],
+    pub attacker_scale: [i32; 8],
+}
+
+/// The weights the engine plays with, the last ones `chess tune` wrote
+pub const DEFAULT_PARAMS: EvalParams = tuned::TUNED_PARAMS;
+
+/// The weights as written by hand, where tuning started
+pub const HAND_WRITTEN_PARAMS: EvalParams = EvalParams {
+    material: [
+        material::PAWN_VALUE,
+        material::KNIGHT_VALUE,
+        material::BISHOP_VALUE,
+        material::ROOK_VALUE,
+        material::QUEEN_VALUE,
+    ],
+
+    pawn: tables::PAWN,
+    pawn_endgame: tables::PAWN_ENDGAME,
+    knight: tables::KNIGHT,
+    bishop: tables::BISHOP,
+    rook: tables::ROOK,
+    queen: tables::QUEEN,
+    king: tables::KING,
+    king_endgame: tables::KING_ENDGAME,
+
+    passed: pawns::PASSED,
+    connected: pawns::CONNECTED,
+    phalanx: pawns::PHALANX,
+    doubled: pawns::DOUBLED,
+    isolated: pawns::ISOLATED,
+    backward: pawns::BACKWARD,
+
+    mobility_weight: mobility::MOBILITY_WEIGHT,
+    mobility_offset: mobility::MOBILITY_OFFSET,
+
+    shield: king_safety::SHIELD,
+    storm: king_safety::STORM,
+    semi_open_file: king_safety::SEMI_OPEN_FILE,
+    open_file: king_safety::OPEN_FILE,
+    attack_weight: king_safety::ATTACK_WEIGHT,
+    attacker_scale: king_safety::ATTACKER_SCALE,
+};
+
+/// A single weight or a table of weights
+trait Param {
+    fn values(&self) -> &[i32];
+    fn values_mut(&mut self) -> &mut [i32];
+    fn to_rust(&self) -> String;
+}
+
+impl Param for i32 {
+    fn values(&self) -> &[i32] {
+        std::slice::from_ref(self)
+    }
+
+    fn values_mut(&mut self) -> &mut [i32] {
+        std::slice::from_mut(self)
+    }
+
+    fn to_rust(&self) -> String {
+        self.to_string()
+    }
+}
+
+impl<const N: usize> Param for [i32; N] {
+    fn values(&self) -> &[i32] {
+        self
+    }
+
+    fn values_mut(&mut self) -> &mut [i32] {
+        self
+    }
+
+    fn to_rust(&self) -> String {
+        if N != 64 {
+            return format!("[{}]", self.iter().join(", "));
+        }
+
+        // square tables are laid out as a board, like in `tables.rs`
+        let body = self
+            .chunks(8)
+            .map(|rank| format!("\t{}\n", rank.iter().map(|v| format!("{v:5},")).join("")))
+            .collect::<String>();
+
+        format!("[\n{body}    ]")
+    }
+}
+
+// lists every field once, so the vector layout and the generated source
+// can't get out of sync with the struct
+macro_rules! params {
+    ($($field:ident),* $(,)?) => {
+        impl EvalParams {
+            fn fields(&self) -> Vec<(&'static str, &dyn Param)> {
+                vec![$((stringify!($field), &self.$field as &dyn Param)),*]
+            }
+
+            fn fields_mut(&mut self) -> Vec<&mut dyn Param> {
+                vec![$(&mut self.$field as &mut dyn Param),*]
+            }
+        }
+    };
+}
+
+params!(
+    material,
+    pawn,
+    pawn_endgame,
+    knight,
+    bishop,
+    rook,
+    queen,
+    king,
+    king_endgame,
+    passed,
+    connected,
+    phalanx,
+    doubled,
+    isolated,
+    backward,
+    mobility_weight,
+    mobility_offset,
+    shield,
+    storm,
+    semi_open_file,
+    open_file,
+    attack_weight,
+    attacker_scale,
+);
+
+impl EvalParams {
+    /// Every weight as one flat parameter vector
+    pub fn to_vec(&self) -> Vec<i32> {
+        self.fields()
+            .into_iter()
+            .flat_map(|(_, param)| param.values().to_vec())
+            .collect()
+    }
+
+    /// Inverse of [`EvalParams::to_vec`]
+    ///
+    /// Panics if `values` is not exactly as long as the parameter vector
+    pub fn from_slice(values: &[i32]) -> Self {
+        let mut params = DEFAULT_PARAMS;
+        let mut values = values.iter();
+
+        for param in params.fields_mut() {
+            for value in param.values_mut() {
+                *value = *values.next().expect("parameter vector too short");
+            }
+        }
+
+        assert!(values.next().is_none(), "parameter vector too long");
+
+        params
+    }
+
+    /// Write the weights as a Rust constant named `name`
+    pub fn to_rust(&self, name: &str) -> String {
+        let fields = self
+            .fields()
+            .into_iter()
+            .map(|(field, param)| format!("    {field}: {},\n", param.to_rust()))
+            .collect::<String>();
+
+        format!(
+            "// THIS FILE IS AUTOGENERATED. DO NOT EDIT.\n\
+             use crate::evaluation::params::EvalParams;\n\n\
+             #[rustfmt::skip]\n\
+             pub const {name}: EvalParams = EvalParams {{\n{fields}}};\n"
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn round_trips_through_the_vector() {
+        let mut values = HAND_WRITTEN_PARAMS.to_vec();
+        values[0] += 1;
+
+        let params = EvalParams::from_slice(&values);
+
+        assert_eq!(params.material[0], HAND_WRITTEN_PARAMS.material[0] + 1);
+        assert_eq!(params.to_vec(), values);
+    }
+
+    #[test]
+    fn writes_every_field() {
+        let source = DEFAULT_PARAMS.to_rust("TUNED_PARAMS");
+
+        assert!(source.contains("pub const TUNED_PARAMS: EvalParams"));
+
+        for (field, _) in DEFAULT_PARAMS.fields() {
+            assert!(source.contains(&format!("    {field}: ")), "{field}");
+        }
+    }
+}
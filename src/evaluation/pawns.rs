@@ -0,0 +1,183 @@
+use std::sync::{
+    atomic::{AtomicU64, Ordering},
+    OnceLock,
+};
+
+use super::{EvalParams, DEFAULT_PARAMS};
+use crate::{movegen, prelude::*};
+
+/// Bonus for a passed pawn, indexed by rank from the pawn's own side
+pub const PASSED: [i32; 8] = [0, 5, 10, 20, 35, 60, 100, 0];
+
+/// Bonus for a pawn defended by another pawn, indexed by relative rank
+pub const CONNECTED: [i32; 8] = [0, 0, 5, 8, 12, 20, 30, 0];
+
+pub const PHALANX: i32 = 6;
+pub const DOUBLED: i32 = -15;
+pub const ISOLATED: i32 = -15;
+pub const BACKWARD: i32 = -10;
+
+/// Number of entries in the pawn hash table, must be a power of two
+const PAWN_TABLE_SIZE: usize = 1 << 14;
+
+static PAWN_TABLE: OnceLock<PawnHashTable> = OnceLock::new();
+
+#[derive(Default)]
+struct PawnHashEntry {
+    /// pawn key xored with data, so torn writes are detected on probe
+    key: AtomicU64,
+    data: AtomicU64,
+}
+
+/// Lockless cache of pawn structure scores, keyed by [`Position::pawn_key`]
+///
+/// See [Hyatt's lockless hashing](https://www.chessprogramming.org/Shared_Hash_Table#Lockless)
+pub struct PawnHashTable {
+    entries: Box<[PawnHashEntry]>,
+}
+
+impl PawnHashTable {
+    pub fn new(size: usize) -> Self {
+        Self {
+            entries: (0..size).map(|_| PawnHashEntry::default()).collect(),
+        }
+    }
+
+    /// The table shared by every thread
+    pub fn global() -> &'static PawnHashTable {
+        PAWN_TABLE.get_or_init(|| PawnHashTable::new(PAWN_TABLE_SIZE))
+    }
+
+    fn entry(&self, key: u64) -> &PawnHashEntry {
+        &self.entries[key as usize & (self.entries.len() - 1)]
+    }
+
+    pub fn probe(&self, key: u64) -> Option<Score> {
+        let entry = self.entry(key);
+        let data = entry.data.load(Ordering::Relaxed);
+
+        if entry.key.load(Ordering::Relaxed) ^ data != key {
+            return None;
+        }
+
+        Some(Score::cp(data as u32 as i32))
+    }
+
+    pub fn store(&self, key: u64, score: Score) {
+        let entry = self.entry(key);
+        let data = score.0 as u32 as u64;
+
+        entry.key.store(key ^ data, Ordering::Relaxed);
+        entry.data.store(data, Ordering::Relaxed);
+    }
+}
+
+/// Every rank in front of `rank`, from `color`'s point of view
+pub fn ranks_ahead(color: Color, rank: Rank) -> Bitboard {
+    Rank::ALL
+        .into_iter()
+        .filter(|&other| match color {
+            Color::White => other > rank,
+            Color::Black => other < rank,
+        })
+        .fold(Bitboard::EMPTY, |ahead, other| ahead | Bitboard::rank(other))
+}
+
+pub fn adjacent_files(file: File) -> Bitboard {
+    [file.offset(-1), file.offset(1)]
+        .into_iter()
+        .flatten()
+        .fold(Bitboard::EMPTY, |files, file| files | Bitboard::file(file))
+}
+
+/// Scores passed, doubled, isolated, backward, connected and phalanx pawns
+///
+/// Only looks at pawns, so results are cached in the [`PawnHashTable`]
+#[derive(Clone, Copy)]
+pub struct PawnStructureEvaluator;
+
+impl PawnStructureEvaluator {
+    fn eval_side(ours: Bitboard, theirs: Bitboard, color: Color, params: &EvalParams) -> i32 {
+        let mut score = 0;
+
+        let our_attacks = movegen::pawn_attacks(ours, color);
+        let their_attacks = movegen::pawn_attacks(theirs, color.other());
+
+        for square in ours.bit_pos_iter() {
+            let file = Bitboard::file(square.file());
+            let adjacent = adjacent_files(square.file());
+            let ahead = ranks_ahead(color, square.rank());
+            let relative_rank = square.rank().relative(color) as usize;
+
+            let doubled = ours & file & ahead != Bitboard::EMPTY;
+            let isolated = ours & adjacent == Bitboard::EMPTY;
+            let passed = !doubled && theirs & (file | adjacent) & ahead == Bitboard::EMPTY;
+
+            if doubled {
+                score += params.doubled;
+            }
+
+            if isolated {
+                score += params.isolated;
+            }
+
+            if passed {
+                score += params.passed[relative_rank];
+            }
+
+            if our_attacks.at(square) {
+                score += params.connected[relative_rank];
+            }
+
+            if ours & adjacent & Bitboard::rank(square.rank()) != Bitboard::EMPTY {
+                score += params.phalanx;
+            }
+
+            // no friendly pawn level with or behind it can ever defend it,
+            // and it can't advance without being taken
+            let forward = match color {
+                Color::White => 1,
+                Color::Black => -1,
+            };
+            let stop = square.try_add(Offset { rank: forward, file: 0 });
+
+            let unsupportable = ours & adjacent & !ahead == Bitboard::EMPTY;
+            let stop_attacked = stop.is_some_and(|stop| their_attacks.at(stop));
+
+            if !isolated && !passed && unsupportable && stop_attacked {
+                score += params.backward;
+            }
+        }
+
+        score
+    }
+
+    /// Evaluate without touching the pawn hash table
+    pub fn eval_uncached(white_pawns: Bitboard, black_pawns: Bitboard, params: &EvalParams) -> Score {
+        let white = Self::eval_side(white_pawns, black_pawns, Color::White, params);
+        let black = Self::eval_side(black_pawns, white_pawns, Color::Black, params);
+
+        Score::cp(white - black)
+    }
+}
+
+impl StaticEvaluator for PawnStructureEvaluator {
+    fn eval(&self, position: &Position) -> Score {
+        let table = PawnHashTable::global();
+        let key = position.pawn_key();
+
+        if let Some(score) = table.probe(key) {
+            return score;
+        }
+
+        let score = Self::eval_uncached(
+            position.pawns & position.n_white,
+            position.pawns & position.n_black,
+            &DEFAULT_PARAMS,
+        );
+
+        table.store(key, score);
+
+        score
+    }
+}
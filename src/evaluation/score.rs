@@ -0,0 +1,215 @@
+use core::fmt;
+use std::ops::{Add, AddAssign, Mul, Neg, Sub, SubAssign};
+
+use crate::prelude::*;
+
+/// Deepest ply a mate score can be encoded for
+pub const MAX_PLY: i32 = 256;
+
+/// An evaluation in centipawns.
+///
+/// Static evaluators return scores from white's point of view; use
+/// [`Score::relative`] to convert to the side to move for negamax.
+///
+/// Mates are encoded as `MATE - plies` (the side to move mates) or
+/// `-MATE + plies` (the side to move gets mated), so shorter mates always
+/// compare better than longer ones.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Default)]
+pub struct Score(pub i32);
+
+impl Score {
+    pub const ZERO: Score = Score(0);
+    pub const DRAW: Score = Score(0);
+    pub const MATE: Score = Score(32_000);
+    pub const INFINITE: Score = Score(32_001);
+
+    /// Any score at or above this (in absolute value) is a mate score
+    pub const MATE_BOUND: Score = Score(Self::MATE.0 - MAX_PLY);
+
+    /// A tablebase win, below every mate score
+    pub const TB_WIN: Score = Score(Self::MATE_BOUND.0 - MAX_PLY);
+
+    pub const fn cp(centipawns: i32) -> Self {
+        Score(centipawns)
+    }
+
+    /// The side to move delivers mate in `ply` plies
+    pub const fn mate_in(ply: i32) -> Self {
+        Score(Self::MATE.0 - ply)
+    }
+
+    /// The side to move is mated in `ply` plies
+    pub const fn mated_in(ply: i32) -> Self {
+        Score(-Self::MATE.0 + ply)
+    }
+
+    pub fn is_mate(&self) -> bool {
+        self.0.abs() >= Self::MATE_BOUND.0 && self.0.abs() <= Self::MATE.0
+    }
+
+    /// Plies until mate, negative if the side this score belongs to is getting mated
+    pub fn mate_plies(&self) -> Option<i32> {
+        if !self.is_mate() {
+            return None;
+        }
+
+        if self.0 > 0 {
+            Some(Self::MATE.0 - self.0)
+        } else {
+            Some(-(Self::MATE.0 + self.0))
+        }
+    }
+
+    /// Full moves until mate, as reported by UCI `score mate`
+    pub fn mate_moves(&self) -> Option<i32> {
+        let plies = self.mate_plies()?;
+
+        if plies > 0 {
+            Some((plies + 1) / 2)
+        } else {
+            Some(plies / 2)
+        }
+    }
+
+    /// Convert a white-relative score to be relative to `color`
+    ///
+    /// Also converts back, as the conversion is its own inverse
+    pub fn relative(&self, color: Color) -> Score {
+        match color {
+            Color::White => *self,
+            Color::Black => -*self,
+        }
+    }
+
+    /// Convert a score found `ply` plies from the root into one that is
+    /// independent of where in the tree it was found, for storing in a
+    /// transposition table
+    pub fn to_tt(&self, ply: i32) -> Score {
+        if self.0 >= Self::MATE_BOUND.0 {
+            Score(self.0 + ply)
+        } else if self.0 <= -Self::MATE_BOUND.0 {
+            Score(self.0 - ply)
+        } else {
+            *self
+        }
+    }
+
+    /// Inverse of [`Score::to_tt`]
+    pub fn from_tt(&self, ply: i32) -> Score {
+        if self.0 >= Self::MATE_BOUND.0 {
+            Score(self.0 - ply)
+        } else if self.0 <= -Self::MATE_BOUND.0 {
+            Score(self.0 + ply)
+        } else {
+            *self
+        }
+    }
+}
+
+impl Add for Score {
+    type Output = Score;
+
+    fn add(self, rhs: Score) -> Score {
+        Score(self.0 + rhs.0)
+    }
+}
+
+impl AddAssign for Score {
+    fn add_assign(&mut self, rhs: Score) {
+        self.0 += rhs.0;
+    }
+}
+
+impl Sub for Score {
+    type Output = Score;
+
+    fn sub(self, rhs: Score) -> Score {
+        Score(self.0 - rhs.0)
+    }
+}
+
+impl SubAssign for Score {
+    fn sub_assign(&mut self, rhs: Score) {
+        self.0 -= rhs.0;
+    }
+}
+
+impl Neg for Score {
+    type Output = Score;
+
+    fn neg(self) -> Score {
+        Score(-self.0)
+    }
+}
+
+impl Mul<i32> for Score {
+    type Output = Score;
+
+    fn mul(self, rhs: i32) -> Score {
+        Score(self.0 * rhs)
+    }
+}
+
+/// Formats the score the way UCI `info score` expects it: `cp <x>` or `mate <y>`
+impl fmt::Display for Score {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.mate_moves() {
+            Some(moves) => write!(f, "mate {moves}"),
+            None => write!(f, "cp {}", self.0),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn mates_round_trip() {
+        for ply in 0..MAX_PLY {
+            assert!(Score::mate_in(ply).is_mate() && Score::mated_in(ply).is_mate());
+            assert_eq!(Score::mate_in(ply).mate_plies(), Some(ply));
+            assert_eq!(Score::mated_in(ply).mate_plies(), Some(-ply));
+            assert_eq!(-Score::mate_in(ply), Score::mated_in(ply));
+        }
+
+        assert!(!Score::TB_WIN.is_mate());
+        assert!(!(Score::MATE_BOUND - Score::cp(1)).is_mate());
+        assert_eq!(Score::cp(500).mate_plies(), None);
+    }
+
+    #[test]
+    fn shorter_mates_are_better() {
+        assert!(Score::mate_in(1) > Score::mate_in(3));
+        assert!(Score::mated_in(3) > Score::mated_in(1));
+        assert!(Score::mate_in(MAX_PLY - 1) > Score::TB_WIN);
+    }
+
+    #[test]
+    fn relative_is_its_own_inverse() {
+        let score = Score::cp(50);
+
+        assert_eq!(score.relative(Color::White), score);
+        assert_eq!(score.relative(Color::Black), Score::cp(-50));
+        assert_eq!(score.relative(Color::Black).relative(Color::Black), score);
+    }
+
+    #[test]
+    fn mates_are_stored_from_the_node() {
+        // mate in 5 from the root is mate in 2 from a node 3 plies down
+        assert_eq!(Score::mate_in(5).to_tt(3), Score::mate_in(2));
+        assert_eq!(Score::mated_in(5).to_tt(3), Score::mated_in(2));
+        assert_eq!(Score::mate_in(2).from_tt(3), Score::mate_in(5));
+        assert_eq!(Score::cp(-40).to_tt(3), Score::cp(-40));
+    }
+
+    #[test]
+    fn formats_for_uci() {
+        assert_eq!(Score::cp(35).to_string(), "cp 35");
+        assert_eq!(Score::cp(-120).to_string(), "cp -120");
+        assert_eq!(Score::mate_in(1).to_string(), "mate 1");
+        assert_eq!(Score::mate_in(3).to_string(), "mate 2");
+        assert_eq!(Score::mated_in(2).to_string(), "mate -1");
+        assert_eq!(Score::mated_in(4).to_string(), "mate -2");
+    }
+}
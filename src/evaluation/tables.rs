@@ -1,7 +1,8 @@
+use super::{EvalParams, DEFAULT_PARAMS};
 use crate::prelude::{gui::PieceType, *};
 
 #[rustfmt::skip]
-pub const PAWN: [i8; 64] = [
+pub const PAWN: [i32; 64] = [
 	 0,   0,   0,   0,   0,   0,   0,   0,
 	50,  50,  50,  50,  50,  50,  50,  50,
 	10,  10,  20,  30,  30,  20,  10,  10,
@@ -13,7 +14,7 @@ pub const PAWN: [i8; 64] = [
 ];
 
 #[rustfmt::skip]
-pub const PAWN_ENDGAME: [i8; 64] = [
+pub const PAWN_ENDGAME: [i32; 64] = [
 	 0,   0,   0,   0,   0,   0,   0,   0,
 	80,  80,  80,  80,  80,  80,  80,  80,
 	50,  50,  50,  50,  50,  50,  50,  50,
@@ -25,7 +26,7 @@ pub const PAWN_ENDGAME: [i8; 64] = [
 ];
 
 #[rustfmt::skip]
-pub const ROOK: [i8; 64] = [
+pub const ROOK: [i32; 64] = [
 	 0,  0,   0,   0,   0,   0,   0,   0,
 	 5, 10,  10,  10,  10,  10,  10,   5,
 	-5,  0,   0,   0,   0,   0,   0,  -5,
@@ -37,7 +38,7 @@ pub const ROOK: [i8; 64] = [
 ];
 
 #[rustfmt::skip]
-pub const BISHOP: [i8; 64] = [
+pub const BISHOP: [i32; 64] = [
 	-20, -10, -10, -10, -10, -10, -10, -20,
 	-10,   0,   0,   0,   0,   0,   0, -10,
 	-10,   0,   5,  10,  10,   5,   0, -10,
@@ -49,7 +50,7 @@ pub const BISHOP: [i8; 64] = [
 ];
 
 #[rustfmt::skip]
-pub const KNIGHT: [i8; 64] = [
+pub const KNIGHT: [i32; 64] = [
 	-50, -40, -30, -30, -30, -30, -40, -50,
 	-40, -20,   0,   0,   0,   0, -20, -40,
 	-30,   0,  10,  15,  15,  10,   0, -30,
@@ -61,7 +62,7 @@ pub const KNIGHT: [i8; 64] = [
 ];
 
 #[rustfmt::skip]
-pub const KING: [i8; 64] = [
+pub const KING: [i32; 64] = [
 	-30, -40, -40, -50, -50, -40, -40, -30,
 	-30, -40, -40, -50, -50, -40, -40, -30,
 	-30, -40, -40, -50, -50, -40, -40, -30,
@@ -73,7 +74,7 @@ pub const KING: [i8; 64] = [
 ];
 
 #[rustfmt::skip]
-pub const KING_ENDGAME: [i8; 64] = [
+pub const KING_ENDGAME: [i32; 64] = [
 	-50, -40, -30, -20, -20, -30, -40, -50,
 	-30, -20, -10,   0,   0, -10, -20, -30,
 	-30, -10,  20,  30,  30,  20, -10, -30,
@@ -85,7 +86,7 @@ pub const KING_ENDGAME: [i8; 64] = [
 ];
 
 #[rustfmt::skip]
-pub const QUEEN: [i8; 64] = [
+pub const QUEEN: [i32; 64] = [
 	-20, -10, -10,  -5,  -5, -10, -10, -20,
 	-10,   0,   0,   0,   0,   0,   0, -10,
 	-10,   0,   5,   5,   5,   5,   0, -10,
@@ -100,57 +101,65 @@ pub const QUEEN: [i8; 64] = [
 pub struct PieceTableEvaluator;
 
 impl PieceTableEvaluator {
-    pub fn eval_white(position: &Position) -> f64 {
-        let mut score = 0.0;
+    pub fn eval_white(position: &Position, params: &EvalParams) -> Score {
+        let mut score = 0;
 
         for square in position.n_white.bit_pos_iter() {
             let piece_at = position.piece_at(square);
-            let ev_square = square as usize;
+            // the tables are written with the eighth rank first
+            let ev_square = square.flip_rank().index();
             let endgame = position.pieces().count_ones() <= 7;
 
             score += match piece_at {
-                Some(PieceType::King) if endgame => KING_ENDGAME[ev_square],
-                Some(PieceType::Pawn) if endgame => PAWN_ENDGAME[ev_square],
-                Some(PieceType::Pawn) => PAWN[ev_square],
-                Some(PieceType::Rook) => ROOK[ev_square],
-                Some(PieceType::Knight) => KNIGHT[ev_square],
-                Some(PieceType::Bishop) => BISHOP[ev_square],
-                Some(PieceType::Queen) => QUEEN[ev_square],
-                Some(PieceType::King) => KING[ev_square],
+                Some(PieceType::King) if endgame => params.king_endgame[ev_square],
+                Some(PieceType::Pawn) if endgame => params.pawn_endgame[ev_square],
+                Some(PieceType::Pawn) => params.pawn[ev_square],
+                Some(PieceType::Rook) => params.rook[ev_square],
+                Some(PieceType::Knight) => params.knight[ev_square],
+                Some(PieceType::Bishop) => params.bishop[ev_square],
+                Some(PieceType::Queen) => params.queen[ev_square],
+                Some(PieceType::King) => params.king[ev_square],
                 _ => 0,
-            } as f64;
+            };
         }
 
-        score * 0.1
+        Score::cp(score)
     }
 
-    pub fn eval_black(position: &Position) -> f64 {
-        let mut score = 0.0;
+    pub fn eval_black(position: &Position, params: &EvalParams) -> Score {
+        let mut score = 0;
 
         for square in position.n_black.bit_pos_iter() {
             let piece_at = position.piece_at(square);
-            let ev_square = Square::new(square.file(), 7 - square.rank()) as usize;
+            // which is black's side of the board as it is
+            let ev_square = square.index();
             let endgame = position.pieces().count_ones() <= 7;
 
             score += match piece_at {
-                Some(PieceType::King) if endgame => KING_ENDGAME[ev_square],
-                Some(PieceType::Pawn) if endgame => PAWN_ENDGAME[ev_square],
-                Some(PieceType::Pawn) => PAWN[ev_square],
-                Some(PieceType::Rook) => ROOK[ev_square],
-                Some(PieceType::Knight) => KNIGHT[ev_square],
-                Some(PieceType::Bishop) => BISHOP[ev_square],
-                Some(PieceType::Queen) => QUEEN[ev_square],
-                Some(PieceType::King) => KING[ev_square],
+                Some(PieceType::King) if endgame => params.king_endgame[ev_square],
+                Some(PieceType::Pawn) if endgame => params.pawn_endgame[ev_square],
+                Some(PieceType::Pawn) => params.pawn[ev_square],
+                Some(PieceType::Rook) => params.rook[ev_square],
+                Some(PieceType::Knight) => params.knight[ev_square],
+                Some(PieceType::Bishop) => params.bishop[ev_square],
+                Some(PieceType::Queen) => params.queen[ev_square],
+                Some(PieceType::King) => params.king[ev_square],
                 _ => 0,
-            } as f64;
+            };
         }
 
-        score * 0.1
+        Score::cp(score)
+    }
+}
+
+impl PieceTableEvaluator {
+    pub fn eval_with(position: &Position, params: &EvalParams) -> Score {
+        Self::eval_white(position, params) - Self::eval_black(position, params)
     }
 }
 
 impl StaticEvaluator for PieceTableEvaluator {
-    fn eval(&self, position: &Position) -> f64 {
-        Self::eval_white(position) - Self::eval_black(position)
+    fn eval(&self, position: &Position) -> Score {
+        Self::eval_with(position, &DEFAULT_PARAMS)
     }
 }
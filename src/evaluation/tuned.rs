@@ -0,0 +1,5 @@
+// The weights `chess tune` found, which it writes over this file. Until it
+// has been run, the hand-written ones
+use crate::evaluation::params::{EvalParams, HAND_WRITTEN_PARAMS};
+
+pub const TUNED_PARAMS: EvalParams = HAND_WRITTEN_PARAMS;
@@ -0,0 +1,211 @@
+// Texel's tuning method, see https://www.chessprogramming.org/Texel%27s_Tuning_Method
+use std::{fs, time::Instant};
+
+use super::{CompoundEvaluator, EvalParams, DEFAULT_PARAMS};
+use crate::prelude::*;
+
+const DEFAULT_OUTPUT: &str = "src/evaluation/tuned.rs";
+
+/// A quiet position and the result of the game it was taken from
+pub struct TuningEntry {
+    pub position: Position,
+
+    /// 1.0 for a white win, 0.5 for a draw, 0.0 for a black win
+    pub result: f64,
+}
+
+fn parse_result(token: &str) -> Option<f64> {
+    let token = token.trim_matches(|c| matches!(c, '[' | ']' | '"' | ';' | '(' | ')'));
+
+    match token {
+        "1-0" => Some(1.0),
+        "0-1" => Some(0.0),
+        "1/2-1/2" | "1/2" => Some(0.5),
+        _ => token.parse().ok().filter(|r| (0.0..=1.0).contains(r)),
+    }
+}
+
+/// Parse a `<fen> <result>` line
+///
+/// The result may be `1-0`, `0-1`, `1/2-1/2` or a number between 0 and 1,
+/// optionally wrapped in brackets or quotes and preceded by an EPD `c9` opcode
+pub fn parse_entry(line: &str) -> Option<TuningEntry> {
+    let (fen, result) = line.trim().rsplit_once(char::is_whitespace)?;
+    let fen = fen.trim().trim_end_matches("c9").trim();
+
+    Some(TuningEntry {
+        position: Position::from_fen(fen).ok()?,
+        result: parse_result(result)?,
+    })
+}
+
+pub fn load(path: &str) -> std::io::Result<Vec<TuningEntry>> {
+    let contents = fs::read_to_string(path)?;
+    let mut entries = vec![];
+
+    for (i, line) in contents.lines().enumerate() {
+        if line.trim().is_empty() {
+            continue;
+        }
+
+        match parse_entry(line) {
+            Some(entry) => entries.push(entry),
+            None => eprintln!("skipping line {}: {line}", i + 1),
+        }
+    }
+
+    Ok(entries)
+}
+
+/// Expected score for white given a white-relative evaluation
+pub fn sigmoid(score: Score, k: f64) -> f64 {
+    1.0 / (1.0 + 10f64.powf(-k * score.0 as f64 / 400.0))
+}
+
+/// Mean squared error between the game results and the sigmoid of the evaluation
+pub fn error(entries: &[TuningEntry], params: &EvalParams, k: f64) -> f64 {
+    let total: f64 = entries
+        .par_iter()
+        .map(|entry| {
+            let eval = CompoundEvaluator::eval_with(&entry.position, params);
+            let diff = entry.result - sigmoid(eval, k);
+
+            diff * diff
+        })
+        .sum();
+
+    total / entries.len() as f64
+}
+
+/// Find the scaling constant that best fits the current evaluation to the results
+pub fn find_k(entries: &[TuningEntry], params: &EvalParams) -> f64 {
+    let mut best_k = 1.0;
+    let mut step = 0.1;
+
+    // an increasingly fine grid search around the best value so far
+    for _ in 0..3 {
+        let center = best_k;
+        let mut best_error = f64::MAX;
+
+        for i in -10..=10 {
+            let k = center + i as f64 * step;
+
+            if k <= 0.0 {
+                continue;
+            }
+
+            let err = error(entries, params, k);
+
+            if err < best_error {
+                best_error = err;
+                best_k = k;
+            }
+        }
+
+        step /= 10.0;
+    }
+
+    best_k
+}
+
+/// Local search over every parameter, one step at a time, until no change improves the error
+///
+/// `on_pass` is called with the best parameters found after every pass
+pub fn tune(
+    entries: &[TuningEntry],
+    params: &EvalParams,
+    k: f64,
+    mut on_pass: impl FnMut(&EvalParams, f64),
+) -> EvalParams {
+    let mut best = params.to_vec();
+    let mut best_error = error(entries, params, k);
+
+    loop {
+        let mut improved = false;
+
+        for i in 0..best.len() {
+            for delta in [1, -1] {
+                best[i] += delta;
+
+                let err = error(entries, &EvalParams::from_slice(&best), k);
+
+                if err < best_error {
+                    best_error = err;
+                    improved = true;
+                    break;
+                }
+
+                best[i] -= delta;
+            }
+        }
+
+        let params = EvalParams::from_slice(&best);
+        on_pass(&params, best_error);
+
+        if !improved {
+            return params;
+        }
+    }
+}
+
+/// `tune <positions> [output]`
+pub fn run(args: &[String]) {
+    let Some(input) = args.first() else {
+        eprintln!("usage: chess tune <positions> [output]");
+        return;
+    };
+
+    let output = args.get(1).map(|s| s.as_str()).unwrap_or(DEFAULT_OUTPUT);
+
+    let entries = match load(input) {
+        Ok(entries) => entries,
+        Err(e) => {
+            eprintln!("could not read {input}: {e}");
+            return;
+        }
+    };
+
+    if entries.is_empty() {
+        eprintln!("no positions in {input}");
+        return;
+    }
+
+    println!("loaded {} positions", entries.len());
+
+    let k = find_k(&entries, &DEFAULT_PARAMS);
+    println!("k = {k:.4}, initial error = {:.6}", error(&entries, &DEFAULT_PARAMS, k));
+
+    // find out that the output can't be written before spending hours on it
+    if let Err(e) = fs::write(output, DEFAULT_PARAMS.to_rust("TUNED_PARAMS")) {
+        eprintln!("could not write {output}: {e}");
+        return;
+    }
+
+    let timer = Instant::now();
+    let mut pass = 0;
+    let mut written = false;
+
+    // starting from the weights the engine plays with, so tuning again
+    // goes on where the last run stopped
+    tune(&entries, &DEFAULT_PARAMS, k, |params, err| {
+        pass += 1;
+
+        println!(
+            "pass {pass}: error = {err:.6} ({} secs)",
+            timer.elapsed().as_secs()
+        );
+
+        // written every pass so an interrupted run isn't wasted
+        written = match fs::write(output, params.to_rust("TUNED_PARAMS")) {
+            Ok(()) => true,
+            Err(e) => {
+                eprintln!("could not write {output}: {e}");
+                false
+            }
+        };
+    });
+
+    if written {
+        println!("wrote {output}, rebuild to play with the new weights");
+    }
+}
@@ -1,13 +1,29 @@
-use std::collections::HashSet;
+use std::{
+    sync::{Arc, Mutex},
+    thread,
+    time::Duration,
+};
 
-use crate::{evaluation::depth::ThreadedEvaluator, prelude::*};
+use crate::{
+    book::Book,
+    prelude::*,
+    search::{SearchLimits, SearchResult, Searcher},
+};
 use eframe::{
     egui,
     epaint::{Color32, Rounding, Stroke},
 };
 
+use super::editor::{Editor, Start};
+
 pub const LEN_SQ: f32 = 75.0;
 
+/// Width of the controls next to the board
+pub const PANEL_WIDTH: f32 = 260.0;
+
+/// How long "Analyze" searches for
+const ANALYSIS_TIME: Duration = Duration::from_secs(5);
+
 #[derive(Clone, Copy)]
 pub struct HighlightList {
     selected: Option<Square>,
@@ -32,7 +48,7 @@ impl HighlightList {
         self.selected = None;
     }
 
-    pub fn from_to(&mut self, from: Square, to: Square) {
+    pub fn set_move(&mut self, from: Square, to: Square) {
         self.from.replace(from);
         self.to.replace(to);
         self.selected = None;
@@ -51,171 +67,326 @@ impl Default for HighlightList {
     }
 }
 
-pub struct SquareData<'a> {
-    pub button: egui::Button<'a>,
-    pub has_piece: bool,
-    pub movable: bool,
-    pub sq_idx: usize,
-    pub on_click: fn(&mut ChessApp, bool, bool, Square),
+/// Where `square` is drawn on a board in `board`, white at the bottom
+fn square_rect(board: egui::Rect, square: Square) -> egui::Rect {
+    let x = board.left() + square.file().index() as f32 * LEN_SQ;
+    let y = board.top() + square.rank().flip().index() as f32 * LEN_SQ;
+
+    egui::Rect::from_min_size(egui::pos2(x, y), egui::vec2(LEN_SQ, LEN_SQ))
 }
 
-impl<'a> Default for SquareData<'a> {
-    fn default() -> Self {
-        Self {
-            button: egui::Button::new(""),
-            has_piece: false,
-            movable: false,
-            sq_idx: 0,
-            on_click: |_, _, _, _| {},
-        }
+/// The square under `pos` on a board drawn in `board`
+pub fn square_at(board: egui::Rect, pos: egui::Pos2) -> Option<Square> {
+    if !board.contains(pos) {
+        return None;
     }
-}
 
-fn collect_data<'a>(app: &ChessApp) -> Vec<SquareData<'a>> {
-    let position = app.eval.position();
-    let selected = app.selected_piece;
-    let highlight = &app.highlight;
+    let file = ((pos.x - board.left()) / LEN_SQ) as u8;
+    let row = ((pos.y - board.top()) / LEN_SQ) as u8;
 
-    let mut movable_sqs = HashSet::new();
-    let unordered = position.collect().into_iter().enumerate().collect_vec();
-    let ordered = unordered.chunks(8).rev().flatten().copied().collect_vec();
+    Some(Square::new(
+        Rank::from_index(row.min(7)).flip(),
+        File::from_index(file.min(7)),
+    ))
+}
 
-    let mut data = vec![];
+/// Only the corners of the board are rounded
+fn corner_rounding(square: Square) -> Rounding {
+    let round = |rank, file| {
+        if square == Square::new(rank, file) {
+            15.0
+        } else {
+            0.0
+        }
+    };
 
-    if let Some(selected) = selected {
-        movable_sqs = position.moves_of(selected).bit_pos_iter().collect();
+    Rounding {
+        nw: round(Rank::Eighth, File::A),
+        ne: round(Rank::Eighth, File::H),
+        sw: round(Rank::First, File::A),
+        se: round(Rank::First, File::H),
     }
+}
 
-    for i in 0..64 {
-        let (ipiece, piece) = ordered[i];
-        let movable = movable_sqs.contains(&(ipiece as u8));
-        let color = match (i % 2 == (i / 8) % 2, highlight.highlighted(ipiece as u8)) {
-            (true, true) => Color32::from_rgb(244, 246, 128),
-            (true, false) => Color32::from_rgb(233, 237, 204),
-            (false, true) => Color32::from_rgb(187, 204, 68),
-            (false, false) => Color32::from_rgb(119, 153, 84),
+/// Draw the board and its pieces, and sense clicks and drags on it
+///
+/// * `highlighted` - Squares drawn in the brighter colors
+/// * `targets` - Squares marked as where the selected piece can go
+pub fn draw_board(
+    ui: &mut egui::Ui,
+    position: &Position,
+    highlighted: impl Fn(Square) -> bool,
+    targets: Bitboard,
+) -> egui::Response {
+    let size = egui::vec2(8.0 * LEN_SQ, 8.0 * LEN_SQ);
+    let (response, painter) = ui.allocate_painter(size, egui::Sense::click_and_drag());
+
+    for square in Square::every() {
+        let rect = square_rect(response.rect, square);
+        let color = match (square.is_dark(), highlighted(square)) {
+            (false, true) => Color32::from_rgb(244, 246, 128),
+            (false, false) => Color32::from_rgb(233, 237, 204),
+            (true, true) => Color32::from_rgb(187, 204, 68),
+            (true, false) => Color32::from_rgb(119, 153, 84),
         };
 
-        let mut button = if let Some(piece) = piece {
-            let image = egui::Image::new(piece.image())
-                .max_height(LEN_SQ)
-                .max_width(LEN_SQ);
+        painter.rect_filled(rect, corner_rounding(square), color);
 
-            egui::Button::image(image)
-        } else {
-            egui::Button::new("")
-        };
+        if let Some(piece) = position.full_piece_at(square) {
+            egui::Image::new(piece.image()).paint_at(ui, rect);
+        }
 
-        button = button
-            .fill(color)
-            .min_size(egui::vec2(LEN_SQ, LEN_SQ))
-            .frame(false);
+        if !targets.at(square) {
+            continue;
+        }
 
-        fn round(a: bool) -> f32 {
-            if a {
-                15.0
-            } else {
-                0.0
-            }
+        if position.occupied().at(square) {
+            painter.circle_stroke(
+                rect.center(),
+                (LEN_SQ / 2.0) - (LEN_SQ / 20.0),
+                Stroke::new(
+                    LEN_SQ / 10.0,
+                    Color32::from_rgba_premultiplied(128, 0, 0, 200),
+                ),
+            );
+        } else {
+            painter.circle_filled(
+                rect.center(),
+                LEN_SQ / 6.0,
+                Color32::from_rgba_premultiplied(0, 0, 0, 25),
+            );
         }
+    }
 
-        button = button.rounding(egui::Rounding {
-            nw: round(i == 0),
-            ne: round(i == 7),
-            sw: round(i == 56),
-            se: round(i == 63),
-            ..Rounding::ZERO
+    response
+}
+
+/// A search on another thread, so the window keeps responding
+#[derive(Default)]
+struct Analysis {
+    /// The position searched, to stop showing the result once it changes
+    position: Option<Position>,
+    /// The last finished iteration
+    result: Arc<Mutex<Option<SearchResult>>>,
+}
+
+impl Analysis {
+    fn start(&mut self, position: Position, ctx: &egui::Context) {
+        // a search that is still running keeps writing into the old result
+        let result = Arc::new(Mutex::new(None));
+        self.result = Arc::clone(&result);
+        self.position = Some(position);
+
+        let ctx = ctx.clone();
+
+        thread::spawn(move || {
+            let limits = SearchLimits {
+                time: Some(ANALYSIS_TIME),
+                ..Default::default()
+            };
+
+            Searcher::new(limits).search(&position, |iteration| {
+                *result.lock().unwrap() = Some(iteration.clone());
+                ctx.request_repaint();
+            });
         });
+    }
 
-        data.push(SquareData {
-            button,
-            movable,
-            has_piece: piece.is_some(),
-            sq_idx: ipiece,
-            on_click: |app, has_piece, movable, sq| {
-                if movable && let Some(piece) = app.selected_piece {
-                    app.eval.move_into({
-                        let mut position = app.eval.position();
-
-                        position.make_move(piece, sq);
-                        position
-                    });
-
-                    app.highlight.from_to(piece, sq);
-                } else if has_piece
-                    && (app.selected_piece.is_some_and(|s| s != sq) || app.selected_piece.is_none())
-                {
-                    app.selected_piece = Some(sq);
-                    app.highlight.select(sq);
+    fn show(&self, ui: &mut egui::Ui, position: &Position) {
+        if self.position != Some(*position) {
+            return;
+        }
+
+        match &*self.result.lock().unwrap() {
+            Some(result) => {
+                ui.label(format!("depth {}, {}", result.depth, result.score));
+
+                if result.pv.is_empty() {
+                    ui.label("no moves");
                 } else {
-                    app.selected_piece = None;
-                    app.highlight.deselect();
+                    let line = position.line_to_uci(&result.pv, false);
+                    ui.add(egui::Label::new(line).wrap(true));
                 }
-            },
-        });
+            }
+            None => {
+                ui.label("searching...");
+            }
+        }
     }
+}
+
+/// A Polyglot book, loaded from a path typed into the panel
+#[derive(Default)]
+struct OpeningBook {
+    path: String,
+    book: Option<Book>,
+    error: Option<String>,
+}
+
+impl OpeningBook {
+    /// The book's moves in `position`, and the one picked when "Book move"
+    /// is clicked
+    ///
+    /// * `ply` - Plies played since the game started, for the book depth
+    fn show(&mut self, ui: &mut egui::Ui, position: &Position, ply: usize) -> Option<Move> {
+        ui.label("Opening book");
+        ui.text_edit_singleline(&mut self.path);
+
+        if ui.button("Load book").clicked() {
+            match Book::load(self.path.trim()) {
+                Ok(book) => {
+                    self.book = Some(book);
+                    self.error = None;
+                }
+                Err(e) => self.error = Some(e.to_string()),
+            }
+        }
+
+        if let Some(error) = &self.error {
+            ui.colored_label(Color32::RED, error);
+        }
+
+        let book = self.book.as_ref()?;
+        let moves = book.moves(position);
+
+        if moves.is_empty() || ply >= book.depth {
+            ui.label("out of book");
+            return None;
+        }
+
+        for (mv, weight) in moves {
+            ui.label(format!("{} ({weight})", position.to_uci(mv, false)));
+        }
 
-    data
+        ui.button("Book move")
+            .clicked()
+            .then(|| book.probe(position, ply))
+            .flatten()
+    }
 }
 
 #[derive(Default)]
 pub struct ChessApp {
-    eval: ThreadedEvaluator,
+    position: Position,
+    /// Plies played since the game or the setup started
+    ply: usize,
     selected_piece: Option<Square>,
     highlight: HighlightList,
+    /// `Some` while setting up a position
+    editor: Option<Editor>,
+    analysis: Analysis,
+    book: OpeningBook,
+}
+
+impl ChessApp {
+    fn play(&mut self, mv: Move) {
+        self.position.play(mv);
+        self.ply += 1;
+        self.highlight.set_move(mv.from, mv.to);
+        self.selected_piece = None;
+    }
+
+    /// Select one of our pieces, or move the selected one
+    fn click(&mut self, square: Square) {
+        match self.selected_piece {
+            Some(from) if self.position.moves_of(from).at(square) => {
+                self.play(Move::new(from, square));
+            }
+            selected if selected != Some(square) && self.position.pieces_of_turn().at(square) => {
+                self.selected_piece = Some(square);
+                self.highlight.select(square);
+            }
+            _ => {
+                self.selected_piece = None;
+                self.highlight.deselect();
+            }
+        }
+    }
+
+    /// Start from a new position, which has been validated
+    fn start(&mut self, position: Position, start: Start, ctx: &egui::Context) {
+        self.position = position;
+        self.ply = 0;
+        self.selected_piece = None;
+        self.highlight = HighlightList::new();
+        self.editor = None;
+
+        if start == Start::Analyze {
+            self.analysis.start(position, ctx);
+        }
+    }
+
+    fn board(&mut self, ui: &mut egui::Ui) {
+        let targets = self
+            .selected_piece
+            .map_or(Bitboard::EMPTY, |from| self.position.moves_of(from));
+
+        let highlight = self.highlight;
+        let response = draw_board(ui, &self.position, |sq| highlight.highlighted(sq), targets);
+
+        let clicked = response
+            .interact_pointer_pos()
+            .filter(|_| response.clicked())
+            .and_then(|pos| square_at(response.rect, pos));
+
+        if let Some(square) = clicked {
+            self.click(square);
+        }
+    }
+
+    fn controls(&mut self, ui: &mut egui::Ui) {
+        let moves = self.position.legal_moves();
+        let turn = self.position.turn;
+
+        let status = match (moves.is_empty(), self.position.in_check(turn)) {
+            (true, true) => format!("checkmate, {} wins", turn.other()),
+            (true, false) => String::from("stalemate"),
+            (false, _) => format!("{turn} to move"),
+        };
+
+        ui.heading(status);
+        ui.add(egui::Label::new(self.position.fen()).wrap(true));
+        ui.separator();
+
+        if ui.button("Analyze").clicked() {
+            self.analysis.start(self.position, ui.ctx());
+        }
+
+        self.analysis.show(ui, &self.position);
+        ui.separator();
+
+        if let Some(mv) = self.book.show(ui, &self.position, self.ply) {
+            self.play(mv);
+        }
+
+        ui.separator();
+
+        if ui.button("New game").clicked() {
+            self.start(Position::new(), Start::Play, ui.ctx());
+        }
+
+        if ui.button("Set up position").clicked() {
+            self.editor = Some(Editor::new(self.position));
+        }
+    }
 }
 
 impl eframe::App for ChessApp {
     fn update(&mut self, ctx: &egui::Context, _frame: &mut eframe::Frame) {
-        egui::CentralPanel::default().show(ctx, |ui| {
-            egui::Grid::new("chess_grid")
-                .spacing(egui::vec2(0.0, 0.0))
-                .min_col_width(50.0)
-                .min_row_height(50.0)
-                .show(ui, |ui| {
-                    let mut data = collect_data(self).into_iter();
-
-                    for i in 0..64 {
-                        ui.allocate_ui(egui::vec2(LEN_SQ, LEN_SQ), |ui| {
-                            let square_data = data.next().unwrap();
-                            let circle_pos = ui.min_rect().center() + egui::vec2(LEN_SQ / 2.0, 0.0);
-
-                            if ui.add(square_data.button).clicked() {
-                                (square_data.on_click)(
-                                    self,
-                                    square_data.has_piece,
-                                    square_data.movable,
-                                    square_data.sq_idx as u8,
-                                );
-
-                                ctx.request_repaint();
-                            }
-
-                            if square_data.movable {
-                                if square_data.has_piece {
-                                    ui.painter().circle_stroke(
-                                        circle_pos,
-                                        (LEN_SQ / 2.0) - (LEN_SQ / 20.0),
-                                        Stroke::new(
-                                            LEN_SQ / 10.0,
-                                            Color32::from_rgba_premultiplied(128, 0, 0, 200),
-                                        ),
-                                    );
-                                } else {
-                                    ui.painter().circle_filled(
-                                        circle_pos,
-                                        LEN_SQ / 6.0,
-                                        Color32::from_rgba_premultiplied(0, 0, 0, 25),
-                                    );
-                                }
-                            }
-                        });
-
-                        if i % 8 == 7 {
-                            ui.end_row();
-                        }
+        egui::SidePanel::right("controls")
+            .exact_width(PANEL_WIDTH)
+            .show(ctx, |ui| match &mut self.editor {
+                Some(editor) => {
+                    if let Some((position, start)) = editor.controls(ui) {
+                        self.start(position, start, ctx);
                     }
-                })
+                }
+                None => self.controls(ui),
+            });
+
+        egui::CentralPanel::default().show(ctx, |ui| match &mut self.editor {
+            Some(editor) => editor.board(ui),
+            None => self.board(ui),
         });
     }
 }
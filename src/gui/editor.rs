@@ -0,0 +1,307 @@
+// Setting up a position by hand, for studies and puzzles
+//
+// Pick a piece from the palette and click squares to place it, or drag
+// pieces from the palette onto the board, around the board, or off it to
+// remove them. Nothing is checked while editing, only once play or analysis
+// starts, with `Position::validate`.
+use eframe::{egui, epaint::Color32};
+
+use super::app::{self, LEN_SQ};
+use crate::{
+    board::validate::PositionError,
+    prelude::{
+        gui::{Piece, PieceType},
+        *,
+    },
+};
+
+/// Size of the pieces in the palette
+const PALETTE_SQ: f32 = 36.0;
+
+/// What clicking a square does
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Brush {
+    Place(Piece),
+    Erase,
+}
+
+/// How to go on from the position once it is set up
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum Start {
+    Play,
+    Analyze,
+}
+
+pub struct Editor {
+    /// The position the editor was opened with, for "Reset"
+    original: Position,
+    /// Everything but the en passant square, which depends on the side to
+    /// move, so only its file is kept
+    position: Position,
+    ep_file: Option<File>,
+    brush: Brush,
+    /// A piece picked up from the board or the palette
+    dragging: Option<Piece>,
+    fen: String,
+    error: Option<String>,
+}
+
+impl Editor {
+    pub fn new(position: Position) -> Self {
+        let mut editor = Self {
+            original: position,
+            position,
+            ep_file: None,
+            brush: Brush::Erase,
+            dragging: None,
+            fen: String::new(),
+            error: None,
+        };
+
+        editor.load(position);
+        editor
+    }
+
+    fn load(&mut self, position: Position) {
+        self.position = position;
+        self.ep_file = position.ep_target.map(|target| target.file());
+        self.dragging = None;
+        self.changed();
+    }
+
+    /// The position as it is set up, not validated
+    fn setup(&self) -> Position {
+        let mut position = self.position;
+        let rank = Rank::Sixth.relative(position.turn);
+
+        position.ep_target = self.ep_file.map(|file| Square::new(rank, file));
+        position
+    }
+
+    /// Call after every edit, to keep the FEN up to date
+    fn changed(&mut self) {
+        self.fen = self.setup().fen();
+        self.error = None;
+    }
+
+    /// Place the brush's piece, or take it off again if it's already there
+    fn paint(&mut self, square: Square) {
+        match self.brush {
+            Brush::Place(piece) if self.position.full_piece_at(square) != Some(piece) => {
+                self.position.put(square, piece);
+            }
+            _ => self.position.clear(square),
+        }
+
+        self.changed();
+    }
+
+    /// Give or take away one of `KQkq`, with the outermost rook like X-FEN
+    fn set_castling(&mut self, c: char, color: Color, kingside: bool, allowed: bool) {
+        self.changed();
+
+        if !allowed {
+            self.position.castling_rights.set(color, kingside, None);
+        } else if self.position.parse_castling(c).is_none() {
+            self.error = Some(format!("no king and rook to castle '{c}' with"));
+        }
+    }
+
+    fn palette(&mut self, ui: &mut egui::Ui) {
+        for color in [Color::White, Color::Black] {
+            ui.horizontal(|ui| {
+                for kind in PieceType::every() {
+                    let piece = Piece { color, kind };
+                    let image = egui::Image::new(piece.image())
+                        .fit_to_exact_size(egui::vec2(PALETTE_SQ, PALETTE_SQ))
+                        .sense(egui::Sense::click_and_drag());
+
+                    let response = ui.add(image).on_hover_text(format!("{color} {kind}"));
+
+                    if self.brush == Brush::Place(piece) {
+                        ui.painter().rect_stroke(
+                            response.rect.expand(2.0),
+                            4.0,
+                            egui::Stroke::new(2.0, Color32::from_rgb(187, 204, 68)),
+                        );
+                    }
+
+                    if response.clicked() {
+                        self.brush = Brush::Place(piece);
+                    }
+
+                    if response.drag_started() {
+                        self.dragging = Some(piece);
+                    }
+                }
+            });
+        }
+
+        ui.selectable_value(&mut self.brush, Brush::Erase, "Eraser");
+    }
+
+    /// The controls next to the board, returning the position once play
+    /// or analysis starts from it
+    pub fn controls(&mut self, ui: &mut egui::Ui) -> Option<(Position, Start)> {
+        ui.heading("Set up position");
+        self.palette(ui);
+        ui.separator();
+
+        let turn = self.position.turn;
+
+        ui.horizontal(|ui| {
+            ui.radio_value(&mut self.position.turn, Color::White, "White to move");
+            ui.radio_value(&mut self.position.turn, Color::Black, "Black to move");
+        });
+
+        if self.position.turn != turn {
+            self.changed();
+        }
+
+        ui.horizontal(|ui| {
+            ui.label("Castling");
+
+            for (c, color, kingside) in [
+                ('K', Color::White, true),
+                ('Q', Color::White, false),
+                ('k', Color::Black, true),
+                ('q', Color::Black, false),
+            ] {
+                let rights = self.position.castling_rights;
+                let mut allowed = rights.rook_file(color, kingside).is_some();
+
+                if ui.checkbox(&mut allowed, c.to_string()).changed() {
+                    self.set_castling(c, color, kingside, allowed);
+                }
+            }
+        });
+
+        let ep_file = self.ep_file;
+
+        egui::ComboBox::from_label("En passant file")
+            .selected_text(
+                self.ep_file
+                    .map_or(String::from("-"), |file| file.to_string()),
+            )
+            .show_ui(ui, |ui| {
+                ui.selectable_value(&mut self.ep_file, None, "-");
+
+                for file in File::ALL {
+                    ui.selectable_value(&mut self.ep_file, Some(file), file.to_string());
+                }
+            });
+
+        if self.ep_file != ep_file {
+            self.changed();
+        }
+
+        ui.horizontal(|ui| {
+            if ui.button("Clear").clicked() {
+                self.load(Position::empty());
+            }
+
+            if ui.button("Reset").clicked() {
+                self.load(self.original);
+            }
+
+            if ui.button("Start position").clicked() {
+                self.load(Position::new());
+            }
+        });
+
+        ui.separator();
+        ui.label("FEN");
+        ui.add(egui::TextEdit::multiline(&mut self.fen).desired_rows(2));
+
+        if ui.button("Load FEN").clicked() {
+            // checked only once the setup is played or analyzed
+            match Position::from_fen_unchecked(self.fen.trim()) {
+                Ok(position) => self.load(position),
+                Err(e) => self.error = Some(e.to_string()),
+            }
+        }
+
+        ui.separator();
+
+        let mut start = None;
+
+        ui.horizontal(|ui| {
+            if ui.button("Play").clicked() {
+                start = Some(Start::Play);
+            }
+
+            if ui.button("Analyze").clicked() {
+                start = Some(Start::Analyze);
+            }
+        });
+
+        let started = start.and_then(|start| match self.finish() {
+            Ok(position) => Some((position, start)),
+            Err(e) => {
+                self.error = Some(e.to_string());
+                None
+            }
+        });
+
+        if let Some(error) = &self.error {
+            ui.colored_label(Color32::RED, error);
+        }
+
+        started
+    }
+
+    /// The position as set up, if a game could reach it
+    fn finish(&self) -> Result<Position, PositionError> {
+        let position = self.setup();
+        position.validate()?;
+
+        Ok(position)
+    }
+
+    pub fn board(&mut self, ui: &mut egui::Ui) {
+        let response = app::draw_board(ui, &self.position, |_| false, Bitboard::EMPTY);
+        let board = response.rect;
+        let pointer = ui.input(|i| i.pointer.interact_pos());
+
+        if response.drag_started() {
+            let origin = ui.input(|i| i.pointer.press_origin());
+
+            if let Some(square) = origin.and_then(|pos| app::square_at(board, pos)) {
+                self.dragging = self.position.full_piece_at(square);
+                self.position.clear(square);
+            }
+        } else if response.clicked() {
+            if let Some(square) = pointer.and_then(|pos| app::square_at(board, pos)) {
+                self.paint(square);
+            }
+        }
+
+        let Some(piece) = self.dragging else {
+            return;
+        };
+
+        if let Some(pos) = pointer {
+            // above both panels, so it follows the pointer from the palette
+            egui::Area::new(egui::Id::new("dragged piece"))
+                .order(egui::Order::Tooltip)
+                .interactable(false)
+                .fixed_pos(pos - egui::vec2(LEN_SQ, LEN_SQ) / 2.0)
+                .show(ui.ctx(), |ui| {
+                    ui.add(
+                        egui::Image::new(piece.image())
+                            .fit_to_exact_size(egui::vec2(LEN_SQ, LEN_SQ)),
+                    );
+                });
+        }
+
+        // dropped anywhere but the board, it's gone
+        if ui.input(|i| i.pointer.any_released()) {
+            if let Some(square) = pointer.and_then(|pos| app::square_at(board, pos)) {
+                self.position.put(square, piece);
+            }
+
+            self.dragging = None;
+            self.changed();
+        }
+    }
+}
@@ -1,24 +1,32 @@
+#[cfg(feature = "gui")]
 use eframe::egui;
 
-//mod app;
+#[cfg(feature = "gui")]
+mod app;
+#[cfg(feature = "gui")]
+mod editor;
 pub mod gui_piece;
 
-// pub fn run() -> Result<(), eframe::Error> {
-//     let vp_size = (8.0 * app::LEN_SQ) + 15.0;
+/// Open the board window, the `chess gui` subcommand. Run it from the
+/// repository, the piece images are loaded from `assets/`
+#[cfg(feature = "gui")]
+pub fn run() -> Result<(), eframe::Error> {
+    let options = eframe::NativeOptions {
+        viewport: egui::ViewportBuilder::default().with_inner_size([
+            8.0 * app::LEN_SQ + app::PANEL_WIDTH + 40.0,
+            8.0 * app::LEN_SQ + 30.0,
+        ]),
+        ..Default::default()
+    };
 
-//     let options = eframe::NativeOptions {
-//         viewport: egui::ViewportBuilder::default().with_inner_size([vp_size, vp_size]),
-//         ..Default::default()
-//     };
+    eframe::run_native(
+        "Chess",
+        options,
+        Box::new(|cc| {
+            let app = app::ChessApp::default();
+            egui_extras::install_image_loaders(&cc.egui_ctx);
 
-//     eframe::run_native(
-//         "Chess",
-//         options,
-//         Box::new(|cc| {
-//             let app = app::ChessApp::default();
-//             egui_extras::install_image_loaders(&cc.egui_ctx);
-
-//             Box::new(app)
-//         }),
-//     )
-// }
+            Box::new(app)
+        }),
+    )
+}
@@ -0,0 +1,111 @@
+//! A bitboard chess engine.
+//!
+//! The library has everything but the command line: the board and its
+//! rules, move generation, evaluation and search, and the UCI loop. The
+//! `chess` binary only picks a subcommand.
+//!
+//! A [`Square`] is one of a1, b1, ..., h8, built from a [`Rank`] and a
+//! [`File`], and a [`Bitboard`] is a set of squares with one bit each.
+//! Both are newtypes, so a square can't be used where a bitboard is meant.
+//!
+//! ```
+//! use chess::{movegen, Bitboard, File, Rank, Square};
+//!
+//! let e4: Square = "e4".parse().unwrap();
+//! assert_eq!(e4, Square::new(Rank::Fourth, File::E));
+//!
+//! let attacks = movegen::knight(e4);
+//! assert_eq!(attacks.count_ones(), 8);
+//! assert!(attacks.at("f6".parse().unwrap()));
+//! assert_eq!(attacks & Bitboard::file(File::E), Bitboard::EMPTY);
+//! ```
+//!
+//! Evaluators score a position from white's point of view, and the search
+//! turns that around for the side to move. FEN that doesn't describe a
+//! position that can come up in a game is refused.
+//!
+//! ```
+//! use chess::{evaluation::MaterialEvaluator, FenError, Position, Score, StaticEvaluator};
+//!
+//! let position = Position::from_fen("4k3/8/8/8/8/8/8/3QK3 b - - 0 1").unwrap();
+//! let score = MaterialEvaluator.eval(&position);
+//!
+//! assert!(score > Score::ZERO);
+//! assert!(score.relative(position.turn) < Score::ZERO);
+//!
+//! let error = Position::from_fen("4k3/8/4K3 w - - 0 1").err();
+//! assert_eq!(error, Some(FenError::RankCount(3)));
+//! ```
+//!
+//! A search from a position:
+//!
+//! ```
+//! use chess::{Position, SearchLimits, Searcher};
+//!
+//! let mut position = Position::new();
+//! position.play(position.parse_san("e4").unwrap());
+//!
+//! assert_eq!(position.legal_moves().len(), 20);
+//! assert_eq!(
+//!     position.fen(),
+//!     "rnbqkbnr/pppppppp/8/8/4P3/8/PPPP1PPP/RNBQKBNR b KQkq e3 0 1"
+//! );
+//!
+//! let limits = SearchLimits {
+//!     depth: Some(2),
+//!     ..Default::default()
+//! };
+//!
+//! let result = Searcher::new(limits).search(&position, |_| {});
+//! let reply = result.best_move.unwrap();
+//!
+//! println!("{}", position.to_uci(reply, false));
+//! ```
+
+/// The board, moves, and reading and writing FEN, SAN and UCI moves
+pub mod board;
+
+/// Polyglot opening books
+pub mod book;
+
+/// Self-play training data, the `chess datagen` subcommand
+pub mod datagen;
+
+/// Static evaluation, and the tuning of its parameters
+pub mod evaluation;
+
+/// The board GUI, the `chess gui` subcommand
+pub mod gui;
+
+/// Attacks and moves of every piece
+pub mod movegen;
+
+mod prelude;
+
+/// Seedable random numbers
+pub mod rng;
+
+/// Alpha-beta search, on one thread or many
+pub mod search;
+
+/// Syzygy endgame tablebases
+pub mod syzygy;
+
+/// The UCI protocol, the `chess uci` subcommand
+pub mod uci;
+
+pub use board::{
+    bitboard::Bitboard,
+    castling::CastlingRights,
+    color::Color,
+    fen::FenError,
+    moves::Move,
+    position::Position,
+    square::{File, Offset, ParseSquareError, Rank, Square},
+    validate::PositionError,
+};
+pub use evaluation::{Score, StaticEvaluator};
+pub use gui::gui_piece::{Piece, PieceType};
+pub use search::{
+    tt::TranspositionTable, GameHistory, SearchLimits, SearchOptions, SearchResult, Searcher,
+};
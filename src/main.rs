@@ -1,41 +1,39 @@
-#![allow(internal_features)]
-#![feature(
-    const_for,
-    const_trait_impl,
-    const_mut_refs,
-    const_option,
-    let_chains,
-    core_intrinsics
-)]
-
-use std::{sync::Arc, time::Instant};
-
-use atomicfloat::AtomicF64;
-use board::position::Position;
-
-mod board;
-mod evaluation;
-mod gui;
-mod movegen;
-mod prelude;
-mod rng;
+use std::time::Instant;
+
+use chess::{book, datagen, evaluation, movegen, rng, search, uci, Position};
 
 fn main() {
     rng::init();
-    //gui::run().unwrap();
 
+    let args = std::env::args().collect::<Vec<_>>();
+
+    match args.get(1).map(|s| s.as_str()) {
+        Some("book") => book::builder::run(&args[2..]),
+        Some("bench-sliders") => movegen::magic::bench::run(&args[2..]),
+        Some("bench-smp") => search::bench::run(&args[2..]),
+        Some("datagen") => datagen::run(&args[2..]),
+        #[cfg(feature = "gui")]
+        Some("gui") => chess::gui::run().unwrap(),
+        Some("magics") => movegen::magic::magic_numbers::run(&args[2..]),
+        Some("mate") => search::mate::run(&args[2..]),
+        Some("mcts-match") => search::mcts::run(&args[2..]),
+        Some("prove") => search::proof::run(&args[2..]),
+        Some("tune") => evaluation::tuning::run(&args[2..]),
+        Some("uci") => uci::run(),
+        _ => populate(),
+    }
+}
+
+fn populate() {
     let mut gametree =
-        evaluation::depth::GameTreeRoot::new(Position::new(), Arc::new(AtomicF64::new(0.)));
+        evaluation::depth::GameTree::new(Position::new(), evaluation::depth::DEFAULT_TREE_MB);
 
     let timer = Instant::now();
 
     gametree.populate(5);
 
     let time = timer.elapsed();
-
-    let mut leaves = vec![];
-    gametree.leaves(&mut leaves);
-    let count = leaves.len();
+    let count = gametree.len();
 
     println!("populated {count} nodes in {} secs", time.as_secs_f32());
     println!(
@@ -44,15 +42,14 @@ fn main() {
     );
     println!("attempting to populate remaining nodes");
 
-    let mut count = 0;
-    for l in &mut leaves {
-        l.populate(1);
-        count += 1;
+    let full = !gametree.populate(6);
 
-        if count % 10_000 == 0 {
-            println!("populated {} nodes", count);
-        }
-    }
+    println!(
+        "{} nodes, {} leaves{}",
+        gametree.len(),
+        gametree.leaf_count(),
+        if full { ", the tree is full" } else { "" }
+    );
 
     println!("done!")
 }
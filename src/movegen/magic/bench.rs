@@ -0,0 +1,116 @@
+// `chess bench-sliders`: time the magic and pext backends against each other
+//
+// That both give the same attacks is checked by the tests below
+use std::{hint::black_box, time::Instant};
+
+use super::lookup_tables::{Backend, SliderTables};
+use crate::{prelude::*, rng::Rng};
+
+const DEFAULT_LOOKUPS: usize = 10_000_000;
+
+/// Nanoseconds per rook and bishop lookup pair
+fn time(table: &SliderTables, occupancies: &[Bitboard]) -> f64 {
+    let start = Instant::now();
+    let mut acc = Bitboard::EMPTY;
+
+    for (i, &occupied) in occupancies.iter().enumerate() {
+        let sq = Square::from_index((i % 64) as u8);
+        acc ^= table.rook(sq, occupied) ^ table.bishop(sq, occupied);
+    }
+
+    black_box(acc);
+
+    start.elapsed().as_nanos() as f64 / occupancies.len() as f64
+}
+
+/// `bench-sliders [lookups]`
+pub fn run(args: &[String]) {
+    let lookups = args
+        .first()
+        .and_then(|n| n.parse().ok())
+        .unwrap_or(DEFAULT_LOOKUPS);
+
+    let mut rng = Rng::new(0);
+
+    let backends = [Backend::Magic, Backend::Pext]
+        .into_iter()
+        .filter_map(|backend| {
+            let table = SliderTables::new(backend);
+
+            if table.is_none() {
+                println!("{backend:?}: not supported by this CPU");
+            }
+
+            table
+        })
+        .collect_vec();
+
+    // about as dense as a middlegame
+    let occupancies = (0..lookups)
+        .map(|_| Bitboard(rng.next_u64() & rng.next_u64()))
+        .collect_vec();
+
+    for table in &backends {
+        let selected = if table.backend == Backend::SELECTED {
+            " (selected)"
+        } else {
+            ""
+        };
+
+        println!(
+            "{:?}{selected}: {:.2} ns per lookup pair",
+            table.backend,
+            time(table, &occupancies)
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::movegen::magic::helper;
+
+    type Lookup = fn(&SliderTables, Square, Bitboard) -> Bitboard;
+
+    /// Every blocker pattern of every square, each with some noise outside
+    /// the mask, which the backend shouldn't look at
+    fn check(table: &SliderTables) {
+        let mut rng = Rng::new(0);
+
+        let pieces: [(&str, _, Lookup); 2] = [
+            ("rook", helper::DIRS_ROOK, SliderTables::rook),
+            ("bishop", helper::DIRS_BISHOP, SliderTables::bishop),
+        ];
+
+        for (name, dirs, lookup) in pieces {
+            for sq in Square::every() {
+                let mask = helper::gen_movement_mask(sq, dirs);
+                let bits = mask.count_bits();
+
+                for i in 0..1 << bits {
+                    let occupied = mask.set_occupancy(i, bits) | Bitboard(rng.next_u64()) & !mask;
+
+                    assert_eq!(
+                        lookup(table, sq, occupied),
+                        helper::gen_attack_mask(sq, occupied, dirs),
+                        "{:?} {name} on {sq} with {occupied:#x}",
+                        table.backend
+                    );
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn magic_attacks_are_right() {
+        check(&SliderTables::new(Backend::Magic).unwrap());
+    }
+
+    #[test]
+    fn pext_attacks_are_right() {
+        match SliderTables::new(Backend::Pext) {
+            Some(table) => check(&table),
+            None => eprintln!("skipped, this CPU has no BMI2"),
+        }
+    }
+}
@@ -1,28 +1,46 @@
 use crate::prelude::*;
 
 /// up, down, left, right
-pub const DIRS_ROOK: [(i8, i8); 4] = [(0, 1), (0, -1), (-1, 0), (1, 0)];
+pub const DIRS_ROOK: [Offset; 4] = [
+    Offset { rank: 1, file: 0 },
+    Offset { rank: -1, file: 0 },
+    Offset { rank: 0, file: -1 },
+    Offset { rank: 0, file: 1 },
+];
 
 /// up-left, up-right, down-left, down-right
-pub const DIRS_BISHOP: [(i8, i8); 4] = [(-1, 1), (1, 1), (-1, -1), (1, -1)];
+pub const DIRS_BISHOP: [Offset; 4] = [
+    Offset { rank: 1, file: -1 },
+    Offset { rank: 1, file: 1 },
+    Offset { rank: -1, file: -1 },
+    Offset { rank: -1, file: 1 },
+];
+
+/// `steps` times `dir`
+fn scaled(dir: Offset, steps: i8) -> Offset {
+    Offset {
+        rank: dir.rank * steps,
+        file: dir.file * steps,
+    }
+}
 
 /// Generate the movement mask
 ///
 /// * `starting` - the square the piece is on
 /// * `directions` - the directions the piece can move in (use the DIRS constants)
 /// * returns - the movement mask
-pub fn gen_movement_mask(starting: Square, directions: [(i8, i8); 4]) -> Bitboard {
+pub fn gen_movement_mask(starting: Square, directions: [Offset; 4]) -> Bitboard {
     let mut bb = Bitboard::EMPTY;
 
-    for (file, rank) in directions {
+    for dir in directions {
         for dest in 1..7 {
             let next = dest + 1;
 
-            let true = starting.try_add(file * next, rank * next).is_some() else {
+            let true = starting.try_add(scaled(dir, next)).is_some() else {
                 break;
             };
 
-            let Some(current) = starting.try_add(file * dest, rank * dest) else {
+            let Some(current) = starting.try_add(scaled(dir, dest)) else {
                 break;
             };
 
@@ -42,13 +60,13 @@ pub fn gen_movement_mask(starting: Square, directions: [(i8, i8); 4]) -> Bitboar
 pub fn gen_attack_mask(
     starting: Square,
     blockers: Bitboard,
-    directions: [(i8, i8); 4],
+    directions: [Offset; 4],
 ) -> Bitboard {
     let mut bb = Bitboard::EMPTY;
 
-    for (file, rank) in directions {
+    for dir in directions {
         for dest in 1..8 {
-            let Some(current) = starting.try_add(file * dest, rank * dest) else {
+            let Some(current) = starting.try_add(scaled(dir, dest)) else {
                 break;
             };
 
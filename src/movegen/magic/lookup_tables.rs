@@ -1,77 +1,208 @@
-use super::generated_magics::*;
-use super::helper;
+// Slider attack tables, built the first time a slider moves
+//
+// Every square gets a slice of one flat array ("fancy magics"): its blockers
+// are turned into an index, and the offset says where the square's slice
+// starts. That keeps all attacks together in memory instead of in 128
+// separate allocations.
+//
+// The index comes from one of two backends, picked at build time. Magic
+// numbers hash the blockers with a multiplication and work anywhere. With
+// the `pext` feature, BMI2's `pext` instruction packs the blocker bits
+// directly, which is faster on CPUs where it isn't microcoded.
+use std::sync::OnceLock;
+
+use super::{generated_magics::*, helper};
 use crate::prelude::*;
 
-pub fn generate_movement_masks(dirs: [(i8, i8); 4]) -> String {
-    let arr_str = Square::every()
-        .map(|sq| format!("{:#x},", helper::gen_movement_mask(sq, dirs)))
-        .collect::<String>();
+#[cfg(all(
+    feature = "pext",
+    not(all(target_arch = "x86_64", target_feature = "bmi2"))
+))]
+compile_error!("the `pext` feature needs BMI2, build with RUSTFLAGS=\"-C target-feature=+bmi2\"");
 
-    format!("[{arr_str}]")
-}
+/// Rook attacks of every square, summed over all blocker patterns
+const ROOK_TABLE_SIZE: usize = 102_400;
 
-pub fn generate_attacks(rook: bool) -> String {
-    let arr_str = Square::every()
-        .map(|sq| create_table(sq, rook))
-        .collect::<String>();
+/// Bishop attacks of every square, summed over all blocker patterns
+const BISHOP_TABLE_SIZE: usize = 5_248;
 
-    format!("[{arr_str}]")
+/// How blockers are turned into an index
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Backend {
+    Magic,
+    Pext,
 }
 
-pub fn create_table(sq: Square, rook: bool) -> String {
-    let dirs = if rook {
-        helper::DIRS_ROOK
+impl Backend {
+    /// The backend `movegen` uses, picked by the `pext` feature
+    pub const SELECTED: Backend = if cfg!(feature = "pext") {
+        Backend::Pext
     } else {
-        helper::DIRS_BISHOP
+        Backend::Magic
     };
 
-    let movement_mask = helper::gen_movement_mask(sq, dirs);
+    /// Whether this CPU can run the backend
+    pub fn available(self) -> bool {
+        match self {
+            Backend::Magic => true,
+            #[cfg(target_arch = "x86_64")]
+            Backend::Pext => std::arch::is_x86_feature_detected!("bmi2"),
+            #[cfg(not(target_arch = "x86_64"))]
+            Backend::Pext => false,
+        }
+    }
+}
 
-    let bits_in = movement_mask.count_bits();
-    let num_blocker_pats = 1u16 << bits_in;
-    let magic = if rook {
-        MAGICS_ROOK[sq as usize]
-    } else {
-        MAGICS_BISHOP[sq as usize]
-    };
+/// Only call this if `Backend::Pext.available()`
+#[cfg(target_arch = "x86_64")]
+#[target_feature(enable = "bmi2")]
+unsafe fn pext(value: u64, mask: u64) -> u64 {
+    std::arch::x86_64::_pext_u64(value, mask)
+}
+
+#[cfg(not(target_arch = "x86_64"))]
+unsafe fn pext(_value: u64, _mask: u64) -> u64 {
+    unreachable!("pext is only available on x86_64")
+}
+
+/// Where to find the attacks of a slider on one square
+#[derive(Clone, Copy, Default)]
+pub struct Magic {
+    /// The squares whose pieces block the slider, without the edges
+    pub mask: Bitboard,
+    pub magic: u64,
+    pub shift: u8,
+    pub offset: usize,
+}
+
+impl Magic {
+    /// Index into the attacks of the selected backend
+    #[inline(always)]
+    pub fn index(&self, occupied: Bitboard) -> usize {
+        #[cfg(feature = "pext")]
+        {
+            // SAFETY: the build fails without BMI2
+            self.offset + unsafe { pext(occupied.0, self.mask.0) } as usize
+        }
+
+        #[cfg(not(feature = "pext"))]
+        {
+            self.magic_index(occupied)
+        }
+    }
 
-    let mut table = vec![0; num_blocker_pats as usize];
+    pub fn magic_index(&self, occupied: Bitboard) -> usize {
+        let blockers = occupied & self.mask;
 
-    for i in 0..num_blocker_pats {
-        let i = i as usize;
-        let pattern = movement_mask.set_occupancy(i, bits_in);
+        self.offset + (blockers.trimmed_mul(self.magic) >> self.shift) as usize
+    }
+
+    /// Index into the attacks of the pext backend
+    ///
+    /// # Safety
+    ///
+    /// The CPU must support BMI2, see `Backend::Pext.available()`
+    unsafe fn pext_index(&self, occupied: Bitboard) -> usize {
+        // SAFETY: up to the caller
+        self.offset + unsafe { pext(occupied.0, self.mask.0) } as usize
+    }
+
+    fn index_with(&self, backend: Backend, occupied: Bitboard) -> usize {
+        match backend {
+            Backend::Magic => self.magic_index(occupied),
+            // SAFETY: tables are only built for available backends
+            Backend::Pext => unsafe { self.pext_index(occupied) },
+        }
+    }
+}
 
-        let index = pattern.trimmed_mul(magic) >> (64 - bits_in);
-        let attack_mask = helper::gen_attack_mask(sq, pattern, dirs);
+pub struct SliderTables {
+    pub backend: Backend,
+    pub rook: [Magic; 64],
+    pub bishop: [Magic; 64],
+    pub attacks: Vec<Bitboard>,
+}
+
+impl SliderTables {
+    /// Build the tables for `backend`, `None` if this CPU can't run it
+    pub fn new(backend: Backend) -> Option<Self> {
+        if !backend.available() {
+            return None;
+        }
+
+        let mut attacks = Vec::with_capacity(ROOK_TABLE_SIZE + BISHOP_TABLE_SIZE);
+
+        let rook = fill(&mut attacks, backend, (&MAGICS_ROOK, &BITS_ROOK), helper::DIRS_ROOK);
+        let bishop = fill(
+            &mut attacks,
+            backend,
+            (&MAGICS_BISHOP, &BITS_BISHOP),
+            helper::DIRS_BISHOP,
+        );
+
+        Some(SliderTables {
+            backend,
+            rook,
+            bishop,
+            attacks,
+        })
+    }
 
-        table[index as usize] = attack_mask;
+    pub fn rook(&self, sq: Square, occupied: Bitboard) -> Bitboard {
+        let magic = &self.rook[sq.index()];
+        self.attacks[magic.index_with(self.backend, occupied)]
     }
 
-    let mut s = String::new();
+    pub fn bishop(&self, sq: Square, occupied: Bitboard) -> Bitboard {
+        let magic = &self.bishop[sq.index()];
+        self.attacks[magic.index_with(self.backend, occupied)]
+    }
+}
 
-    for attack in table.iter() {
-        s.push_str(&format!("{attack:#x},"));
+/// Fill in one piece's magics and attacks, starting at the end of `attacks`
+fn fill(
+    attacks: &mut Vec<Bitboard>,
+    backend: Backend,
+    (magics, magic_bits): (&[u64; 64], &[u8; 64]),
+    dirs: [Offset; 4],
+) -> [Magic; 64] {
+    let mut entries = [Magic::default(); 64];
+
+    for sq in Square::every() {
+        let mask = helper::gen_movement_mask(sq, dirs);
+        let mask_bits = mask.count_bits();
+
+        // pext packs every blocker, magics may share indices
+        let bits = match backend {
+            Backend::Magic => magic_bits[sq.index()],
+            Backend::Pext => mask_bits,
+        };
+
+        let magic = Magic {
+            mask,
+            magic: magics[sq.index()],
+            shift: 64 - magic_bits[sq.index()],
+            offset: attacks.len(),
+        };
+
+        attacks.resize(attacks.len() + (1 << bits), Bitboard::EMPTY);
+
+        for i in 0..1 << mask_bits {
+            let blockers = mask.set_occupancy(i, mask_bits);
+            attacks[magic.index_with(backend, blockers)] = helper::gen_attack_mask(sq, blockers, dirs);
+        }
+
+        entries[sq.index()] = magic;
     }
 
-    format!("&[{s}],")
+    entries
 }
 
-pub fn save() {
-    let mut file_str = format!(
-        "
-		// THIS FILE IS AUTOGENERATED. DO NOT EDIT.
-		use crate::prelude::*;
-		pub const MOVEMENTS_ROOK: [Bitboard; 64] = {};
-		pub const MOVEMENTS_BISHOP: [Bitboard; 64] = {};
-		pub const ATTACKS_ROOK: [&'static [Bitboard]; 64] = {};
-		pub const ATTACKS_BISHOP: [&'static [Bitboard]; 64] = {};
-	",
-        generate_movement_masks(helper::DIRS_ROOK),
-        generate_movement_masks(helper::DIRS_BISHOP),
-        generate_attacks(true),
-        generate_attacks(false),
-    );
-
-    // save to src/movegen/magic/generated_lookups.rs
-    let mut file = std::fs::write("src/movegen/magic/generated_lookups.rs", file_str).unwrap();
+/// The tables of the selected backend
+pub fn tables() -> &'static SliderTables {
+    static TABLES: OnceLock<SliderTables> = OnceLock::new();
+
+    TABLES.get_or_init(|| {
+        SliderTables::new(Backend::SELECTED).expect("the selected backend runs on this CPU")
+    })
 }
@@ -1,10 +1,17 @@
-use std::ffi::c_int;
-
-use super::helper;
-use crate::prelude::*;
+// `chess magics`: find and check the magic numbers in `generated_magics.rs`
+//
+// `chess magics search [seed] [bits to drop] [tries]` prints a replacement
+// for `generated_magics.rs`, the same one for the same seed. Dropping bits
+// looks for magics that map blocker patterns with the same attacks to the
+// same index, which shrinks the tables. Squares where none is found within
+// `tries` candidates get one more bit, up to the full `RELEVANT_BITS_*`.
+//
+// `chess magics verify` checks the stored magics against brute force.
+use super::{generated_magics::*, helper};
+use crate::{prelude::*, rng::Rng};
 
 #[rustfmt::skip]
-const RELEVANT_BITS_ROOK: [u8; 64] = [
+pub const RELEVANT_BITS_ROOK: [u8; 64] = [
     12, 11, 11, 11, 11, 11, 11, 12,
     11, 10, 10, 10, 10, 10, 10, 11,
     11, 10, 10, 10, 10, 10, 10, 11,
@@ -16,7 +23,7 @@ const RELEVANT_BITS_ROOK: [u8; 64] = [
 ];
 
 #[rustfmt::skip]
-const RELEVANT_BITS_BISHOP: [u8; 64] = [
+pub const RELEVANT_BITS_BISHOP: [u8; 64] = [
     6, 5, 5, 5, 5, 5, 5, 6,
     5, 5, 5, 5, 5, 5, 5, 5,
     5, 5, 7, 7, 7, 7, 5, 5,
@@ -27,88 +34,240 @@ const RELEVANT_BITS_BISHOP: [u8; 64] = [
     6, 5, 5, 5, 5, 5, 5, 6
 ];
 
-fn gen_candidate() -> u64 {
-    rng::dense_random() & rng::dense_random() & rng::dense_random()
+const DEFAULT_TRIES: u32 = 10_000_000;
+
+#[derive(Clone, Copy)]
+struct Slider {
+    index: u64,
+    name: &'static str,
+    dirs: [Offset; 4],
+    relevant_bits: &'static [u8; 64],
 }
 
-fn gen_magic_number(
-    sq: Square,
-    relevant_bits: u8,
-    moves_fn: fn(Square) -> Bitboard,
-    attacks_fn: fn(Square, Bitboard) -> Bitboard,
-) -> Option<u64> {
-    let movement_mask = moves_fn(sq);
-    let bits_in_mask = movement_mask.count_bits();
-    let num_occupancies = 1u16 << bits_in_mask;
+const ROOK: Slider = Slider {
+    index: 0,
+    name: "ROOK",
+    dirs: helper::DIRS_ROOK,
+    relevant_bits: &RELEVANT_BITS_ROOK,
+};
 
-    let mut used_attacks = [0; 4096];
+const BISHOP: Slider = Slider {
+    index: 1,
+    name: "BISHOP",
+    dirs: helper::DIRS_BISHOP,
+    relevant_bits: &RELEVANT_BITS_BISHOP,
+};
 
-    let (occupancies, attacks): (Vec<_>, Vec<_>) = (0..num_occupancies)
-        .into_par_iter()
-        .map(|i| {
-            let occupancy = movement_mask.set_occupancy(i as usize, bits_in_mask);
-            let attack = attacks_fn(sq, occupancy);
+/// Every blocker pattern of a square, and the attacks with it
+fn patterns(sq: Square, dirs: [Offset; 4]) -> Vec<(Bitboard, Bitboard)> {
+    let mask = helper::gen_movement_mask(sq, dirs);
+    let bits = mask.count_bits();
 
-            (occupancy, attack)
+    (0..1 << bits)
+        .map(|i| {
+            let blockers = mask.set_occupancy(i, bits);
+            (blockers, helper::gen_attack_mask(sq, blockers, dirs))
         })
-        .unzip();
+        .collect()
+}
 
-    for _ in 0..1_000_000_000 {
-        let candidate = gen_candidate();
-        let moves_magic = ((movement_mask as u128 * candidate as u128) & (0xff << 56)) as u64;
+/// Whether every pattern gets an index that holds its attacks, allowing
+/// patterns with the same attacks to share one
+///
+/// * `used` - Scratch space of `1 << bits` entries, holding which attempt
+///   last wrote each index so it doesn't need clearing between attempts
+fn fits(
+    patterns: &[(Bitboard, Bitboard)],
+    magic: u64,
+    bits: u8,
+    attempt: u32,
+    used: &mut [(u32, Bitboard)],
+) -> bool {
+    patterns.iter().all(|&(blockers, attacks)| {
+        let index = (blockers.trimmed_mul(magic) >> (64 - bits)) as usize;
 
-        if moves_magic.count_ones() < 6 {
-            continue;
+        if used[index].0 != attempt {
+            used[index] = (attempt, attacks);
+            true
+        } else {
+            used[index].1 == attacks
         }
+    })
+}
 
-        let mut ok = true;
+fn find_magic(
+    rng: &mut Rng,
+    sq: Square,
+    slider: Slider,
+    bits: u8,
+    tries: u32,
+) -> Option<u64> {
+    let mask = helper::gen_movement_mask(sq, slider.dirs);
+    let patterns = patterns(sq, slider.dirs);
+    let mut used = vec![(0, Bitboard::EMPTY); 1 << bits];
 
-        for i in 0..num_occupancies {
-            let i = i as usize;
+    // sparse candidates find ordinary magics quickly, but hardly ever ones
+    // where patterns share indices
+    let full = bits >= mask.count_bits();
 
-            let magic_idx = occupancies[i].trimmed_mul(candidate) >> (64 - relevant_bits);
-            let magic_idx = magic_idx as usize;
+    for attempt in 1..=tries {
+        let candidate = if full {
+            rng.sparse_u64()
+        } else {
+            rng.next_u64()
+        };
 
-            if used_attacks[magic_idx] == Bitboard::EMPTY {
-                used_attacks[magic_idx] = attacks[i];
-            } else if used_attacks[magic_idx] != attacks[i] {
-                ok = false;
-                break;
-            }
+        // bad magics don't spread the mask over the top byte
+        if full && (mask.trimmed_mul(candidate) >> 56).count_ones() < 6 {
+            continue;
         }
 
-        if ok {
+        if fits(&patterns, candidate, bits, attempt, &mut used) {
             return Some(candidate);
         }
-
-        used_attacks = [0; 4096];
     }
 
     None
 }
 
-pub fn gen_magic_numbers() {
-    println!("pub const MAGICS_ROOK: [u64; 64] = [");
-    for sq in Square::every() {
-        let magic_number = gen_magic_number(
-            sq,
-            RELEVANT_BITS_ROOK[sq as usize],
-            |sq| helper::gen_movement_mask(sq, helper::DIRS_ROOK),
-            |sq, filled| helper::gen_attack_mask(sq, filled, helper::DIRS_ROOK),
-        );
-        println!("    {:#x}u64,", magic_number.unwrap_or(0));
+/// The magic and index bits of every square, each searched with its own
+/// generator so the result doesn't depend on threads
+fn search(seed: u64, slider: Slider, drop_bits: u8, tries: u32) -> Option<Vec<(u64, u8)>> {
+    Square::every()
+        .collect_vec()
+        .into_par_iter()
+        .map(|sq| {
+            let mut rng = Rng::derive(seed, slider.index * 64 + sq.index() as u64);
+            let relevant = slider.relevant_bits[sq.index()];
+
+            let found = (relevant.saturating_sub(drop_bits).max(1)..=relevant)
+                .find_map(|bits| Some((find_magic(&mut rng, sq, slider, bits, tries)?, bits)));
+
+            if found.is_none() {
+                eprintln!("no {} magic for {}", slider.name.to_lowercase(), sq);
+            }
+
+            found
+        })
+        .collect()
+}
+
+/// Bytes of attack tables for these index bits
+fn table_bytes(bits: &[u8]) -> usize {
+    bits.iter()
+        .map(|&bits| (1 << bits) * std::mem::size_of::<Bitboard>())
+        .sum()
+}
+
+fn report(rook_bits: &[u8], bishop_bits: &[u8]) {
+    let full = table_bytes(&RELEVANT_BITS_ROOK) + table_bytes(&RELEVANT_BITS_BISHOP);
+    let rook = table_bytes(rook_bits);
+    let bishop = table_bytes(bishop_bits);
+
+    eprintln!("rook tables: {rook} bytes");
+    eprintln!("bishop tables: {bishop} bytes");
+    eprintln!(
+        "total: {} bytes, {:.1}% of {full} with every relevant bit",
+        rook + bishop,
+        100.0 * (rook + bishop) as f64 / full as f64
+    );
+}
+
+/// `per_row` values to a line, 8 lays the table out a rank per row like
+/// `RELEVANT_BITS_*`, which rustfmt would undo
+fn print_table<T: std::fmt::Display>(
+    name: &str,
+    ty: &str,
+    values: impl Iterator<Item = T>,
+    per_row: usize,
+) {
+    if per_row > 1 {
+        println!("#[rustfmt::skip]");
+    }
+
+    println!("pub const {name}: [{ty}; 64] = [");
+
+    for row in &values.chunks(per_row) {
+        println!("    {}", row.map(|value| format!("{value},")).join(" "));
     }
+
     println!("];");
+}
+
+fn run_search(args: &[String]) {
+    let arg = |i: usize| args.get(i).and_then(|a| a.parse::<u64>().ok());
 
-    println!("pub const MAGICS_BISHOP: [u64; 64] = [");
-    for sq in Square::every() {
-        let magic_number = gen_magic_number(
-            sq,
-            RELEVANT_BITS_BISHOP[sq as usize],
-            |sq| helper::gen_movement_mask(sq, helper::DIRS_BISHOP),
-            |sq, filled| helper::gen_attack_mask(sq, filled, helper::DIRS_BISHOP),
+    let seed = arg(0).unwrap_or_else(rng::dense_random);
+    let drop_bits = arg(1).unwrap_or(0) as u8;
+    let tries = arg(2).map_or(DEFAULT_TRIES, |t| t as u32);
+
+    eprintln!("searching with seed {seed}");
+
+    let (Some(rook), Some(bishop)) = (
+        search(seed, ROOK, drop_bits, tries),
+        search(seed, BISHOP, drop_bits, tries),
+    ) else {
+        eprintln!("try a different seed or more tries");
+        std::process::exit(1);
+    };
+
+    println!("// generated by `chess magics search {seed} {drop_bits} {tries}`");
+
+    for (slider, found) in [(ROOK, &rook), (BISHOP, &bishop)] {
+        println!();
+        print_table(
+            &format!("MAGICS_{}", slider.name),
+            "u64",
+            found.iter().map(|(magic, _)| format!("{magic:#x}u64")),
+            1,
+        );
+        println!();
+        print_table(
+            &format!("BITS_{}", slider.name),
+            "u8",
+            found.iter().map(|(_, bits)| bits),
+            8,
         );
-        println!("    {:#x}u64,", magic_number.unwrap_or(0));
     }
-    println!("];");
+
+    let bits = |found: &[(u64, u8)]| found.iter().map(|&(_, bits)| bits).collect_vec();
+    report(&bits(&rook), &bits(&bishop));
+}
+
+fn run_verify() {
+    let mut failed = 0;
+
+    for (slider, magics, bits) in [
+        (ROOK, &MAGICS_ROOK, &BITS_ROOK),
+        (BISHOP, &MAGICS_BISHOP, &BITS_BISHOP),
+    ] {
+        for sq in Square::every() {
+            let magic = magics[sq.index()];
+            let bits = bits[sq.index()];
+            let mut used = vec![(0, Bitboard::EMPTY); 1 << bits];
+
+            if !fits(&patterns(sq, slider.dirs), magic, bits, 1, &mut used) {
+                eprintln!("bad {} magic for {}: {magic:#x}", slider.name.to_lowercase(), sq);
+                failed += 1;
+            }
+        }
+    }
+
+    report(&BITS_ROOK, &BITS_BISHOP);
+
+    if failed > 0 {
+        eprintln!("{failed} magics failed");
+        std::process::exit(1);
+    }
+
+    eprintln!("all 128 magics verified");
+}
+
+/// `magics search [seed] [bits to drop] [tries]` or `magics verify`
+pub fn run(args: &[String]) {
+    match args.first().map(|s| s.as_str()) {
+        Some("search") => run_search(&args[1..]),
+        Some("verify") => run_verify(),
+        _ => eprintln!("usage: chess magics search [seed] [bits to drop] [tries] | chess magics verify"),
+    }
 }
@@ -1,5 +1,4 @@
-/// magic number generator
-#[allow(unused)]
+/// magic number search and verification
 pub mod magic_numbers;
 
 /// generated magic numbers
@@ -7,19 +6,12 @@ pub mod magic_numbers;
 #[allow(warnings)]
 pub mod generated_magics;
 
-/// generated lookup tables
-#[rustfmt::skip]
-#[allow(warnings)]
-#[cfg(not(feature = "gen-tables"))]
-pub mod generated_lookups {
-	// avoid rust-analyzer going bazonkers
-	include!("generated_lookups.rs");
-}
-
-/// lookup tables generation
-#[allow(unused)]
+/// attack lookup tables, built on first use
 pub mod lookup_tables;
 
+/// magic and pext backend comparison
+pub mod bench;
+
 /// common functions
 #[allow(unused)]
 pub mod helper;
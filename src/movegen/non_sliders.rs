@@ -1,25 +1,25 @@
 use crate::prelude::*;
 
-const KING_MOVEMENTS: [(i8, i8); 8] = [
-    (-1, -1),
-    (-1, 0),
-    (-1, 1),
-    (0, -1),
-    (0, 1),
-    (1, -1),
-    (1, 0),
-    (1, 1),
+const KING_MOVEMENTS: [Offset; 8] = [
+    Offset { rank: -1, file: -1 },
+    Offset { rank: -1, file: 0 },
+    Offset { rank: -1, file: 1 },
+    Offset { rank: 0, file: -1 },
+    Offset { rank: 0, file: 1 },
+    Offset { rank: 1, file: -1 },
+    Offset { rank: 1, file: 0 },
+    Offset { rank: 1, file: 1 },
 ];
 
-const KNIGHT_MOVEMENTS: [(i8, i8); 8] = [
-    (-2, -1),
-    (-2, 1),
-    (-1, -2),
-    (-1, 2),
-    (1, -2),
-    (1, 2),
-    (2, -1),
-    (2, 1),
+const KNIGHT_MOVEMENTS: [Offset; 8] = [
+    Offset { rank: -2, file: -1 },
+    Offset { rank: -2, file: 1 },
+    Offset { rank: -1, file: -2 },
+    Offset { rank: -1, file: 2 },
+    Offset { rank: 1, file: -2 },
+    Offset { rank: 1, file: 2 },
+    Offset { rank: 2, file: -1 },
+    Offset { rank: 2, file: 1 },
 ];
 
 /// Get the king moves in a position
@@ -27,62 +27,82 @@ const KNIGHT_MOVEMENTS: [(i8, i8); 8] = [
 /// * `king_at` - The square the king is at
 /// * `castling` - The castling rights of the position
 /// * `color` - The color of the king
-/// * returns - The bitboard of all squares the king can move to,
-/// assuming occupied squares are enemy pieces
+/// * returns - The bitboard of all squares the king can move to
 ///
-/// Make sure to bitwise-and the result with !friendly_pieces
+/// Unlike the other pieces, friendly pieces are already removed, because
+/// castling is given as the king moving onto its own rook. That is the only
+/// way to tell the moves apart in Chess960, where the king may not move at all
 pub fn king(
     king_at: Square,
     castling: CastlingRights,
     color: Color,
     position: Position,
 ) -> Bitboard {
-    let mut moves = Bitboard::EMPTY;
+    let friendly = position.pieces_of_col(color);
+    let mut moves = king_attacks(king_at) & !friendly;
 
-    for (file, rank) in KING_MOVEMENTS {
-        if let Some(square) = king_at.try_add(file, rank) {
-            moves |= square.to_bitboard();
+    let back_rank = Rank::First.relative(color);
+
+    if king_at.rank() != back_rank || position.in_check(color) {
+        return moves;
+    }
+
+    for kingside in [true, false] {
+        let Some(rook_file) = castling.rook_file(color, kingside) else {
+            continue;
+        };
+
+        let rook_at = Square::new(back_rank, rook_file);
+
+        if !(position.rooks & friendly).at(rook_at) {
+            continue;
+        }
+
+        // the king ends on g or c, the rook next to it on f or d
+        let (king_file, rook_to_file) = if kingside {
+            (File::G, File::F)
+        } else {
+            (File::C, File::D)
+        };
+        let king_to = Square::new(back_rank, king_file);
+        let rook_to = Square::new(back_rank, rook_to_file);
+
+        let king_path = rank_span(king_at, king_to);
+        let others = position.occupied() & !king_at.to_bitboard() & !rook_at.to_bitboard();
+
+        if (king_path | rank_span(rook_at, rook_to)) & others != Bitboard::EMPTY {
+            continue;
+        }
+
+        let attacked = (king_path & !king_at.to_bitboard())
+            .bit_pos_iter()
+            .any(|square| position.is_attacked(square, color.other()));
+
+        if !attacked {
+            moves |= rook_at.to_bitboard();
         }
     }
 
-    let in_check_ks = {
-        let mut position = *&position;
-        position.make_move(king_at, Square::new(king_at.rank(), 5));
-
-        position.in_check(color)
-    };
-
-    let in_check_qs = {
-        let mut position = *&position;
-        position.make_move(king_at, Square::new(king_at.file(), 3));
-
-        position.in_check(color)
-    };
-
-    let blocking_wks = 0x60 & position.occupied();
-    let blocking_wqs = 0xc & position.occupied();
-    let blocking_bks = 0x6000000000000000 & position.occupied();
-    let blocking_bqs = 0xc00000000000000 & position.occupied();
-
-    if !position.in_check(color) {
-        match color {
-            Color::White => {
-                if castling.kingside_white && !in_check_ks && blocking_wks == Bitboard::EMPTY {
-                    moves |= Square::new(0, 6).to_bitboard();
-                }
-
-                if castling.queenside_white && !in_check_qs && blocking_wqs == Bitboard::EMPTY {
-                    moves |= Square::new(0, 2).to_bitboard();
-                }
-            }
-            Color::Black => {
-                if castling.kingside_black && !in_check_ks && blocking_bks == Bitboard::EMPTY {
-                    moves |= Square::new(7, 6).to_bitboard();
-                }
-                if castling.queenside_black && !in_check_qs && blocking_bqs == Bitboard::EMPTY {
-                    moves |= Square::new(7, 2).to_bitboard();
-                }
-            }
+    moves
+}
+
+/// Every square between two squares on the same rank, both included
+fn rank_span(a: Square, b: Square) -> Bitboard {
+    (a.min(b).index()..=a.max(b).index())
+        .map(|square| Square::from_index(square as u8))
+        .collect()
+}
+
+/// Get the squares a king attacks, without castling
+///
+/// * `king_at` - The square the king is at
+/// * returns - The bitboard of all squares adjacent to the king
+pub fn king_attacks(king_at: Square) -> Bitboard {
+    let mut moves = Bitboard::EMPTY;
+
+    for offset in KING_MOVEMENTS {
+        if let Some(square) = king_at.try_add(offset) {
+            moves |= square.to_bitboard();
         }
     }
 
@@ -93,14 +113,14 @@ pub fn king(
 ///
 /// * `knight_at` - The square the knight is at
 /// * returns - The bitboard of all squares the knight can move to,
-/// assuming occupied squares are enemy pieces
+///   assuming occupied squares are enemy pieces
 ///
 /// Make sure to bitwise-and the result with !friendly_pieces
 pub fn knight(knight_at: Square) -> Bitboard {
     let mut moves = Bitboard::EMPTY;
 
-    for (file, rank) in KNIGHT_MOVEMENTS {
-        if let Some(square) = knight_at.try_add(file, rank) {
+    for offset in KNIGHT_MOVEMENTS {
+        if let Some(square) = knight_at.try_add(offset) {
             moves |= square.to_bitboard();
         }
     }
@@ -7,7 +7,7 @@ use crate::prelude::*;
 /// * `color` - The color of the pawn
 /// * `ep_target` - The square of the en passant target, if any
 /// * returns - The bitboard of all squares the pawn can move to,
-/// assuming occupied squares are enemy pieces
+///   assuming occupied squares are enemy pieces
 ///
 /// Make sure to bitwise-and the result with !friendly_pieces
 pub fn pawn(
@@ -22,39 +22,41 @@ pub fn pawn(
     let mut moves = Bitboard::EMPTY;
 
     if color == Color::White {
-        moves |= pawn_bb.safe_shl(8) & empty;
-        moves |= pawn_bb.safe_shl(16) & empty.safe_shl(8) & empty & Bitboard::rank(3);
-        moves |= pawn_bb.safe_shl(7) & occupied & Bitboard::rank(pawn_at.rank() + 1);
-        moves |= pawn_bb.safe_shl(9) & occupied & Bitboard::rank(pawn_at.rank() + 1);
-
-        if let Some(target) = ep_target
-            && target.rank() == pawn_at.rank() + 1
-        {
-            let target_bb = target.to_bitboard();
-
-            moves |= pawn_bb.safe_shl(7) & target_bb;
-            moves |= pawn_bb.safe_shl(9) & target_bb;
-        }
+        moves |= pawn_bb << 8 & empty;
+        moves |= pawn_bb << 16 & empty << 8 & empty & Bitboard::rank(Rank::Fourth);
     } else {
         moves |= pawn_bb >> 8 & empty;
-        moves |= pawn_bb >> 16 & empty >> 8 & empty & Bitboard::rank(4);
+        moves |= pawn_bb >> 16 & empty >> 8 & empty & Bitboard::rank(Rank::Fifth);
+    }
 
-        if pawn_at.rank() == 0 {
-            return moves;
-        }
+    let forward = if color == Color::White { 1 } else { -1 };
 
-        moves |= pawn_bb >> 7 & occupied & Bitboard::rank(pawn_at.rank() - 1);
-        moves |= pawn_bb >> 9 & occupied & Bitboard::rank(pawn_at.rank() - 1);
+    let Some(capture_rank) = pawn_at.rank().offset(forward) else {
+        return moves;
+    };
 
-        if let Some(target) = ep_target
-            && target.rank() == pawn_at.rank() - 1
-        {
-            let target_bb = target.to_bitboard();
+    let captures = pawn_attacks(pawn_bb, color) & Bitboard::rank(capture_rank);
+    moves |= captures & occupied;
 
-            moves |= pawn_bb >> 7 & target_bb;
-            moves |= pawn_bb >> 9 & target_bb;
-        }
+    if let Some(target) = ep_target.filter(|t| t.rank() == capture_rank) {
+        moves |= captures & target.to_bitboard();
     }
 
     moves
 }
+
+/// Get every square attacked by a set of pawns
+///
+/// * `pawns` - The bitboard of the pawns
+/// * `color` - The color of the pawns
+/// * returns - The bitboard of all squares the pawns attack,
+///   whether or not there is anything on them
+pub fn pawn_attacks(pawns: Bitboard, color: Color) -> Bitboard {
+    let not_a = !Bitboard::file(File::A);
+    let not_h = !Bitboard::file(File::H);
+
+    match color {
+        Color::White => (pawns & not_a) << 7 | (pawns & not_h) << 9,
+        Color::Black => (pawns & not_a) >> 9 | (pawns & not_h) >> 7,
+    }
+}
@@ -1,12 +1,13 @@
 pub(crate) use crate::{
     board::{
-        bitboard::{Bitboard, BitboardU64},
+        bitboard::Bitboard,
         castling::CastlingRights,
         color::Color,
+        moves::Move,
         position::Position,
-        square::{Square, SquareU8},
+        square::{File, Offset, Rank, Square},
     },
-    evaluation::{self, StaticEvaluator},
+    evaluation::{Score, StaticEvaluator},
     movegen::magic,
     rng,
 };
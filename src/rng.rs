@@ -1,26 +1,91 @@
-use std::ffi::c_int;
+// Seedable pseudo-random numbers, xorshift64*
+//
+// See https://vigna.di.unimi.it/ftp/papers/xorshift.pdf
+//
+// There's a global generator for anything that doesn't care where its
+// numbers come from, like picking a book move. Work spread over threads
+// should make its own `Rng` instead, seeded from something that doesn't
+// depend on scheduling, so the same seed always gives the same results.
+use std::sync::Mutex;
 
-#[link(name = "c")]
-extern "C" {
-    fn srandom(seed: c_int);
-    fn random() -> c_int;
+/// Seed used until `init` or `set_seed` is called
+const DEFAULT_SEED: u64 = 0x2545_f491_4f6c_dd1d;
+
+#[derive(Debug, Clone)]
+pub struct Rng {
+    state: u64,
+}
+
+/// Scramble a seed, so that close seeds give unrelated sequences and zero,
+/// which xorshift can't leave, never becomes the state
+const fn splitmix64(seed: u64) -> u64 {
+    let mut z = seed.wrapping_add(0x9e37_79b9_7f4a_7c15);
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    z ^ (z >> 31)
+}
+
+impl Rng {
+    pub const fn new(seed: u64) -> Self {
+        let state = splitmix64(seed);
+
+        Rng {
+            state: if state == 0 { DEFAULT_SEED } else { state },
+        }
+    }
+
+    /// A generator for the `index`th of many independent jobs, like one game
+    /// of self-play. Doesn't depend on which thread runs the job
+    pub const fn derive(seed: u64, index: u64) -> Self {
+        Self::new(splitmix64(seed) ^ index)
+    }
+
+    pub fn next_u64(&mut self) -> u64 {
+        self.state ^= self.state >> 12;
+        self.state ^= self.state << 25;
+        self.state ^= self.state >> 27;
+
+        self.state.wrapping_mul(0x2545_f491_4f6c_dd1d)
+    }
+
+    /// Uniform in `0..n`, `n` must not be zero
+    pub fn below(&mut self, n: u64) -> u64 {
+        ((self.next_u64() as u128 * n as u128) >> 64) as u64
+    }
+
+    /// Few bits set, which makes good magic number candidates
+    pub fn sparse_u64(&mut self) -> u64 {
+        self.next_u64() & self.next_u64() & self.next_u64()
+    }
 }
 
+static GLOBAL: Mutex<Rng> = Mutex::new(Rng::new(DEFAULT_SEED));
+
+/// Random number from the global generator
 pub fn dense_random() -> u64 {
-    let n1 = unsafe { random() & 0xffff } as u64;
-    let n2 = unsafe { random() & 0xffff } as u64;
-    let n3 = unsafe { random() & 0xffff } as u64;
-    let n4 = unsafe { random() & 0xffff } as u64;
+    GLOBAL.lock().unwrap().next_u64()
+}
+
+/// Uniform in `0..n` from the global generator
+pub fn below(n: u64) -> u64 {
+    GLOBAL.lock().unwrap().below(n)
+}
 
-    return n1 | (n2 << 16) | (n3 << 32) | (n4 << 48);
+pub fn set_seed(seed: u64) {
+    *GLOBAL.lock().unwrap() = Rng::new(seed);
 }
 
+/// Seed the global generator from `CHESS_SEED` if it is set, or the clock
 pub fn init() {
-    let now = std::time::SystemTime::now();
-    let unix = now.duration_since(std::time::UNIX_EPOCH).unwrap();
-    let seed = unix.subsec_nanos();
+    let seed = std::env::var("CHESS_SEED")
+        .ok()
+        .and_then(|seed| seed.parse().ok())
+        .unwrap_or_else(|| {
+            let now = std::time::SystemTime::now();
+            let unix = now.duration_since(std::time::UNIX_EPOCH).unwrap();
 
-    unsafe {
-        srandom(seed as i32);
-    }
+            unix.as_nanos() as u64
+        });
+
+    set_seed(seed);
 }
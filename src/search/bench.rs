@@ -0,0 +1,73 @@
+// `chess bench-smp`: how much faster more threads reach a depth
+//
+// Lazy SMP doesn't split the tree, the threads race through it and share
+// what they find in the transposition table, so nodes per second isn't the
+// number to look at. Time to depth is: each thread count searches the same
+// positions to the same depth, each with an empty table.
+use std::{sync::Arc, time::Instant};
+
+use super::{tt, SearchLimits, Searcher};
+use crate::prelude::*;
+
+const DEFAULT_DEPTH: i32 = 7;
+
+const POSITIONS: [&str; 5] = [
+    "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1",
+    "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1",
+    "8/2p5/3p4/KP5r/1R3p1k/8/4P1P1/8 w - - 0 1",
+    "rnbq1k1r/pp1Pbppp/2p5/8/2B5/8/PPP1NnPP/RNBQK2R w KQ - 1 8",
+    "r4rk1/1pp1qppp/p1np1n2/2b1p1B1/2B1P1b1/P1NP1N2/1PP1QPPP/R4RK1 w - - 0 10",
+];
+
+/// `bench-smp [depth] [max threads]`, doubling the threads from one
+pub fn run(args: &[String]) {
+    let depth = args
+        .first()
+        .and_then(|d| d.parse().ok())
+        .unwrap_or(DEFAULT_DEPTH);
+
+    let max_threads = args
+        .get(1)
+        .and_then(|t| t.parse().ok())
+        .unwrap_or_else(|| std::thread::available_parallelism().map_or(1, |n| n.get()));
+
+    let positions = POSITIONS
+        .iter()
+        .map(|fen| Position::from_fen(fen).unwrap())
+        .collect_vec();
+
+    let limits = SearchLimits {
+        depth: Some(depth),
+        ..Default::default()
+    };
+
+    let mut baseline = None;
+    let mut threads = 1;
+
+    println!("depth {depth}, {} positions", positions.len());
+
+    while threads <= max_threads {
+        let start = Instant::now();
+        let mut nodes = 0;
+
+        for position in &positions {
+            let tt = Arc::new(tt::TranspositionTable::new(tt::DEFAULT_HASH_MB));
+            let result = Searcher::with_table(limits, tt)
+                .threads(threads)
+                .search(position, |_| {});
+
+            nodes += result.nodes;
+        }
+
+        let secs = start.elapsed().as_secs_f64();
+        let baseline = *baseline.get_or_insert(secs);
+
+        println!(
+            "{threads:>3} threads: {secs:>7.2} s, {:>10.0} nodes/s, {:.2}x time to depth",
+            nodes as f64 / secs,
+            baseline / secs,
+        );
+
+        threads *= 2;
+    }
+}
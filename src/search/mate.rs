@@ -0,0 +1,375 @@
+// Forced mates for puzzles, the `chess mate` subcommand and `go mate`
+//
+// Unlike the main search nothing is pruned or reduced: every defence is
+// tried, so a mate that is found is proven, and no mate found means there
+// is none within the limit. Mates are tried shortest first, so the first
+// one found is the shortest.
+use std::{
+    cmp::Reverse,
+    collections::HashMap,
+    fmt::Write,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    time::Instant,
+};
+
+use super::{SearchLimits, NODE_BATCH};
+use crate::prelude::*;
+
+/// Positions remembered as mating or not, about 50 MB of them. The memo
+/// starts over when it's full
+const MAX_PROVEN: usize = 1 << 20;
+
+/// A proven forced mate: the attacker's move, and how the mate goes on
+/// after every reply to it
+#[derive(Debug, Clone)]
+pub struct MateTree {
+    pub mv: Move,
+    /// Every legal reply with the shortest mate after it, the longest
+    /// resistance first. Empty when `mv` mates
+    pub replies: Vec<(Move, MateTree)>,
+}
+
+impl MateTree {
+    /// Moves of the attacker until mate, however the defender resists
+    pub fn moves(&self) -> u32 {
+        1 + self.replies.first().map_or(0, |(_, tree)| tree.moves())
+    }
+
+    /// The mate with the longest defence at every move
+    pub fn main_line(&self) -> Vec<Move> {
+        let mut line = vec![self.mv];
+
+        if let Some((reply, tree)) = self.replies.first() {
+            line.push(*reply);
+            line.extend(tree.main_line());
+        }
+
+        line
+    }
+
+    /// Every line of the solution, in UCI notation, one move of the
+    /// attacker and its replies per indentation level:
+    ///
+    /// ```text
+    /// 1. d1h5
+    ///   1... g7g6 2. h5g6#
+    ///   1... e8e7 2. h5f7#
+    /// ```
+    pub fn to_text(&self, position: &Position, chess960: bool) -> String {
+        let mut text = String::new();
+        self.write(&mut text, position, chess960, 1);
+
+        text
+    }
+
+    fn write(&self, text: &mut String, position: &Position, chess960: bool, number: u32) {
+        let mut after = *position;
+        after.play(self.mv);
+
+        let mate = if self.replies.is_empty() { "#" } else { "" };
+        writeln!(
+            text,
+            "{number}. {}{mate}",
+            position.to_uci(self.mv, chess960)
+        )
+        .unwrap();
+
+        let indent = "  ".repeat(number as usize);
+
+        for (reply, tree) in &self.replies {
+            let mut next = after;
+            next.play(*reply);
+
+            write!(
+                text,
+                "{indent}{number}... {} ",
+                after.to_uci(*reply, chess960)
+            )
+            .unwrap();
+            tree.write(text, &next, chess960, number + 1);
+        }
+    }
+}
+
+/// Proves forced mates, see [`MateSolver::solve`]
+pub struct MateSolver {
+    /// Only try checking moves for the attacker. Much faster, but misses
+    /// mates with a quiet move
+    checks_only: bool,
+    /// Only the node and time limits are used, the depth is the number of
+    /// moves given to [`MateSolver::solve`]
+    limits: SearchLimits,
+    stop: Option<Arc<AtomicBool>>,
+    start: Instant,
+    nodes: u64,
+    /// A limit was hit or the search was stopped
+    stopped: bool,
+    /// Whether the attacker to move mates within the given moves, for
+    /// positions reached by different move orders
+    proven: HashMap<(u64, u32), bool>,
+}
+
+fn gives_check(position: &Position, mv: Move) -> bool {
+    let mut child = *position;
+    child.play(mv);
+
+    child.in_check(child.turn)
+}
+
+impl MateSolver {
+    pub fn new(checks_only: bool) -> Self {
+        Self {
+            checks_only,
+            limits: SearchLimits::default(),
+            stop: None,
+            start: Instant::now(),
+            nodes: 0,
+            stopped: false,
+            proven: HashMap::new(),
+        }
+    }
+
+    pub fn limits(mut self, limits: SearchLimits) -> Self {
+        self.limits = limits;
+        self
+    }
+
+    /// Give up as soon as `stop` is set, from another thread
+    pub fn stop_flag(mut self, stop: Arc<AtomicBool>) -> Self {
+        self.stop = Some(stop);
+        self
+    }
+
+    /// Positions visited so far
+    pub fn nodes(&self) -> u64 {
+        self.nodes
+    }
+
+    /// Whether the last [`MateSolver::solve`] gave up before it was done,
+    /// so that not finding a mate proves nothing
+    pub fn stopped(&self) -> bool {
+        self.stopped
+    }
+
+    /// The shortest forced mate by the side to move in at most `max_moves`
+    /// of its moves, or `None` if there isn't one or a limit was hit first
+    pub fn solve(&mut self, position: &Position, max_moves: u32) -> Option<MateTree> {
+        self.start = Instant::now();
+        self.stopped = false;
+
+        self.shortest(position, max_moves)
+    }
+
+    fn shortest(&mut self, position: &Position, max_moves: u32) -> Option<MateTree> {
+        (1..=max_moves).find_map(|moves| self.tree(position, moves))
+    }
+
+    /// A mate in exactly `moves`, when none is shorter
+    fn tree(&mut self, position: &Position, moves: u32) -> Option<MateTree> {
+        let mv = self
+            .attacker_moves(position, moves)
+            .into_iter()
+            .find(|&mv| self.mates_after(position, mv, moves))?;
+
+        let mut after = *position;
+        after.play(mv);
+
+        // every reply leads to mate, but the memo may have forgotten it and
+        // the search may be stopped while finding it again
+        let mut replies = after
+            .legal_moves()
+            .into_iter()
+            .map(|reply| {
+                let mut next = after;
+                next.play(reply);
+
+                Some((reply, self.shortest(&next, moves - 1)?))
+            })
+            .collect::<Option<Vec<_>>>()?;
+
+        replies.sort_by_key(|(_, tree)| Reverse(tree.moves()));
+
+        Some(MateTree { mv, replies })
+    }
+
+    /// Checks first, they are the likeliest to mate. On the last move only
+    /// checks can mate at all
+    fn attacker_moves(&self, position: &Position, moves: u32) -> Vec<Move> {
+        let (checks, quiet): (Vec<_>, Vec<_>) = position
+            .legal_moves()
+            .into_iter()
+            .partition(|&mv| gives_check(position, mv));
+
+        if self.checks_only || moves == 1 {
+            checks
+        } else {
+            checks.into_iter().chain(quiet).collect()
+        }
+    }
+
+    /// Whether the attacker to move mates within `moves`
+    fn mates_in(&mut self, position: &Position, moves: u32) -> bool {
+        let key = (position.hash_key(), moves);
+
+        if let Some(&mates) = self.proven.get(&key) {
+            return mates;
+        }
+
+        let mates = self
+            .attacker_moves(position, moves)
+            .into_iter()
+            .any(|mv| self.mates_after(position, mv, moves));
+
+        // a mate is proven either way, but after a stop no mate proves nothing
+        if self.stopped {
+            return mates;
+        }
+
+        if self.proven.len() >= MAX_PROVEN {
+            self.proven.clear();
+        }
+
+        self.proven.insert(key, mates);
+        mates
+    }
+
+    fn should_stop(&mut self) -> bool {
+        if self.stopped {
+            return true;
+        }
+
+        let out_of_nodes = self.limits.nodes.is_some_and(|n| self.nodes >= n);
+
+        let interrupted = self.nodes.is_multiple_of(NODE_BATCH)
+            && (self.limits.time.is_some_and(|t| self.start.elapsed() >= t)
+                || self
+                    .stop
+                    .as_ref()
+                    .is_some_and(|stop| stop.load(Ordering::Relaxed)));
+
+        self.stopped = out_of_nodes || interrupted;
+        self.stopped
+    }
+
+    /// Whether playing `mv` mates within `moves` whatever the defence,
+    /// false once the search is stopped
+    fn mates_after(&mut self, position: &Position, mv: Move, moves: u32) -> bool {
+        if self.should_stop() {
+            return false;
+        }
+
+        self.nodes += 1;
+
+        let mut after = *position;
+        after.play(mv);
+
+        let replies = after.legal_moves();
+
+        if replies.is_empty() {
+            return after.in_check(after.turn);
+        }
+
+        moves > 1
+            && replies.into_iter().all(|reply| {
+                let mut next = after;
+                next.play(reply);
+
+                self.mates_in(&next, moves - 1)
+            })
+    }
+}
+
+/// `mate <moves> [checks] <fen>`
+pub fn run(args: &[String]) {
+    let usage = "usage: chess mate <moves> [checks] <fen>";
+
+    let Some(max_moves) = args.first().and_then(|n| n.parse().ok()) else {
+        eprintln!("{usage}");
+        return;
+    };
+
+    let checks_only = args.get(1).is_some_and(|a| a == "checks");
+    let fen = args[if checks_only { 2 } else { 1 }..].join(" ");
+
+    let position = match Position::from_fen(&fen) {
+        Ok(position) => position,
+        Err(e) => {
+            eprintln!("invalid fen: {e}\n{usage}");
+            return;
+        }
+    };
+
+    let start = Instant::now();
+    let mut solver = MateSolver::new(checks_only);
+    let solution = solver.solve(&position, max_moves);
+    let secs = start.elapsed().as_secs_f32();
+
+    match solution {
+        Some(tree) => {
+            println!(
+                "mate in {}, {} nodes in {secs:.2} s",
+                tree.moves(),
+                solver.nodes()
+            );
+            print!("{}", tree.to_text(&position, false));
+        }
+        None => println!(
+            "no mate in {max_moves}, {} nodes in {secs:.2} s",
+            solver.nodes()
+        ),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MATE_IN_TWO: &str = "kbK5/pp6/1P6/8/8/8/8/R7 w - - 0 1";
+
+    fn position(fen: &str) -> Position {
+        Position::from_fen(fen).unwrap()
+    }
+
+    #[test]
+    fn finds_the_shortest_mate() {
+        let mut solver = MateSolver::new(false);
+        let tree = solver.solve(&position(MATE_IN_TWO), 4).unwrap();
+
+        assert_eq!(tree.moves(), 2);
+        assert_eq!(tree.mv.to_string(), "a1a6");
+        assert!(!solver.stopped());
+    }
+
+    #[test]
+    fn proves_there_is_no_mate() {
+        let mut solver = MateSolver::new(false);
+
+        assert!(solver.solve(&Position::new(), 2).is_none());
+        assert!(!solver.stopped());
+    }
+
+    #[test]
+    fn stops_at_the_node_limit() {
+        let limits = SearchLimits {
+            nodes: Some(10),
+            ..Default::default()
+        };
+        let mut solver = MateSolver::new(false).limits(limits);
+
+        assert!(solver.solve(&position(MATE_IN_TWO), 4).is_none());
+        assert!(solver.stopped());
+        assert!(solver.nodes() <= 10);
+    }
+
+    #[test]
+    fn stops_when_told() {
+        let stop = Arc::new(AtomicBool::new(true));
+        let mut solver = MateSolver::new(false).stop_flag(stop);
+
+        assert!(solver.solve(&position(MATE_IN_TWO), 4).is_none());
+        assert!(solver.stopped());
+    }
+}
@@ -0,0 +1,707 @@
+// Monte Carlo tree search, the other engine: `UseMCTS` over UCI, and
+// `chess mcts-match` to play it against alpha-beta
+//
+// The tree is `evaluation::depth::GameTree`. Every visit walks down from
+// the root, picking the child with the best mix of value and exploration,
+// until it reaches a leaf. The leaf is valued by its static evaluation or a
+// short playout, gets its children with their priors, and the value is
+// backed up the path. The move played is the most visited one. The tree
+// is kept for the next search, which starts from the node of its position
+// when that's a move or two further.
+//
+// Leaves are valued in batches on rayon threads. Selection, expansion and
+// backing up stay on the searching thread: the paths of a batch are selected
+// one after the other, each adding a virtual loss to every node on the way,
+// so the next selections avoid it until the batch is backed up. Valuing the
+// leaves is the costly part, and the only one that runs in parallel.
+use std::{
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    time::Instant,
+};
+
+use super::{order_value, SearchLimits, SearchResult, Searcher};
+use crate::{
+    evaluation::{
+        depth::{GameTree, NodeId, DEFAULT_TREE_MB, ROOT},
+        CompoundEvaluator,
+    },
+    prelude::*,
+    rng::Rng,
+};
+
+/// Centipawns to values in -1..=1: `tanh(cp / VALUE_SCALE)`
+const VALUE_SCALE: f64 = 400.0;
+
+/// Order values are divided by this before the softmax that makes priors
+const PRIOR_TEMPERATURE: f32 = 2000.0;
+
+/// An unvisited child is assumed to be this much worse than its parent
+const FIRST_PLAY_REDUCTION: f64 = 0.25;
+
+/// Without a node or time limit, or a stop flag to end the search, stop
+/// after this many visits. Also the most a depth limit searches, as the
+/// most visited line may never get that long, like when it ends in mate
+const DEFAULT_VISITS: u64 = 50_000;
+
+/// `on_iteration` is called when the visits reach this, and again at
+/// every doubling
+const FIRST_REPORT: u32 = 1024;
+
+const DEFAULT_GAMES: usize = 20;
+const DEFAULT_MATCH_NODES: u64 = 20_000;
+const DEFAULT_MATCH_VISITS: u64 = 2_000;
+const MAX_GAME_PLIES: usize = 400;
+
+/// How a child is picked on the way down
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Selection {
+    /// AlphaZero's PUCT: exploration in proportion to the move's prior
+    Puct,
+    /// UCB1 on trees: every child once, then exploration by visits alone
+    Uct,
+}
+
+/// Where the value of a new leaf comes from
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LeafValue {
+    /// The static evaluation of the leaf
+    Evaluation,
+    /// The static evaluation after this many plies of moves sampled from
+    /// the priors
+    Playout(u32),
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct MctsOptions {
+    pub selection: Selection,
+    /// How much selection favours rarely visited children over good ones
+    pub exploration: f64,
+    pub leaf_value: LeafValue,
+    /// Leaves valued together per thread
+    pub batch: usize,
+    /// For the playouts
+    pub seed: u64,
+    /// Cap on the memory of the tree. When it's full, leaves are still
+    /// visited but no longer expanded
+    pub tree_mb: usize,
+}
+
+impl Default for MctsOptions {
+    fn default() -> Self {
+        Self {
+            selection: Selection::Puct,
+            exploration: 1.5,
+            leaf_value: LeafValue::Evaluation,
+            batch: 8,
+            seed: 0,
+            tree_mb: DEFAULT_TREE_MB,
+        }
+    }
+}
+
+/// Monte Carlo tree search with the same limits and results as
+/// [`Searcher`]. A node is a visit, and the depth is the length of the
+/// most visited line, which a depth limit waits for
+pub struct Mcts {
+    limits: SearchLimits,
+    options: MctsOptions,
+    threads: usize,
+    stop: Option<Arc<AtomicBool>>,
+    /// From the last search
+    tree: Option<GameTree>,
+}
+
+impl Default for Mcts {
+    fn default() -> Self {
+        Self::new(SearchLimits::default())
+    }
+}
+
+/// A leaf, valued
+struct Valued {
+    /// From the point of view of the side to move
+    value: f64,
+    /// Legal moves with their priors
+    children: Vec<(Move, f32)>,
+}
+
+fn to_value(score: Score) -> f64 {
+    (score.0 as f64 / VALUE_SCALE).tanh()
+}
+
+fn to_score(value: f64) -> Score {
+    Score((value.clamp(-0.999, 0.999).atanh() * VALUE_SCALE) as i32)
+}
+
+/// Softmax over the search's move ordering values, so captures of big
+/// pieces and promotions come first
+fn priors(position: &Position, moves: &[Move]) -> Vec<f32> {
+    let logits = moves
+        .iter()
+        .map(|&mv| order_value(position, mv) as f32 / PRIOR_TEMPERATURE)
+        .collect_vec();
+
+    let max = logits.iter().copied().fold(f32::MIN, f32::max);
+    let weights = logits.iter().map(|l| (l - max).exp()).collect_vec();
+    let sum = weights.iter().sum::<f32>();
+
+    weights.into_iter().map(|w| w / sum).collect()
+}
+
+/// The value of a position that has no moves left, or `None`
+fn terminal_value(position: &Position, moves: &[Move]) -> Option<f64> {
+    if moves.is_empty() {
+        return Some(if position.in_check(position.turn) {
+            -1.0
+        } else {
+            0.0
+        });
+    }
+
+    (position.occupied() == position.kings).then_some(0.0)
+}
+
+fn static_value(position: &Position) -> f64 {
+    to_value(CompoundEvaluator::eval(position).relative(position.turn))
+}
+
+/// Sample moves from the priors for `plies`, then evaluate. From the
+/// point of view of the side to move at the start
+fn playout(position: &Position, plies: u32, rng: &mut Rng) -> f64 {
+    let mut position = *position;
+    let mut sign = 1.0;
+
+    for _ in 0..plies {
+        let moves = position.legal_moves();
+
+        if let Some(value) = terminal_value(&position, &moves) {
+            return sign * value;
+        }
+
+        let priors = priors(&position, &moves);
+        let mut sample = (rng.next_u64() >> 40) as f32 / (1u64 << 24) as f32;
+
+        let mv = moves
+            .iter()
+            .zip(&priors)
+            .find(|(_, &prior)| {
+                sample -= prior;
+                sample < 0.0
+            })
+            .map_or(moves[moves.len() - 1], |(&mv, _)| mv);
+
+        position.play(mv);
+        sign = -sign;
+    }
+
+    sign * static_value(&position)
+}
+
+fn value_leaf(position: &Position, leaf_value: LeafValue, seed: u64) -> Valued {
+    let moves = position.legal_moves();
+
+    if let Some(value) = terminal_value(position, &moves) {
+        return Valued {
+            value,
+            children: vec![],
+        };
+    }
+
+    let value = match leaf_value {
+        LeafValue::Evaluation => static_value(position),
+        LeafValue::Playout(plies) => playout(position, plies, &mut Rng::new(seed)),
+    };
+
+    let priors = priors(position, &moves);
+
+    Valued {
+        value,
+        children: moves.into_iter().zip(priors).collect(),
+    }
+}
+
+impl Mcts {
+    pub fn new(limits: SearchLimits) -> Self {
+        Self {
+            limits,
+            options: MctsOptions::default(),
+            threads: 1,
+            stop: None,
+            tree: None,
+        }
+    }
+
+    /// Limits for the next search, keeping the tree of the last one
+    pub fn limits(mut self, limits: SearchLimits) -> Self {
+        self.limits = limits;
+        self
+    }
+
+    pub fn options(mut self, options: MctsOptions) -> Self {
+        self.options = options;
+        self
+    }
+
+    /// Value leaves on `threads` threads, at least one
+    pub fn threads(mut self, threads: usize) -> Self {
+        self.threads = threads.max(1);
+        self
+    }
+
+    /// Stop the next search as soon as `stop` is set, from another thread
+    pub fn stop_flag(mut self, stop: Arc<AtomicBool>) -> Self {
+        self.stop = Some(stop);
+        self
+    }
+
+    /// Search `position` until a limit is hit, calling `on_iteration` now
+    /// and then with the result so far
+    pub fn search(
+        &mut self,
+        position: &Position,
+        mut on_iteration: impl FnMut(&SearchResult),
+    ) -> SearchResult {
+        let start = Instant::now();
+        let reusable = self
+            .tree
+            .as_mut()
+            .is_some_and(|tree| tree.advance_to(position, 2));
+
+        let mut tree = match self.tree.take() {
+            Some(tree) if reusable => tree,
+            _ => GameTree::new(*position, self.options.tree_mb),
+        };
+
+        let reused = tree.root().visits as u64;
+        let mut rng = Rng::new(self.options.seed);
+
+        let pool = (self.threads > 1).then(|| {
+            rayon::ThreadPoolBuilder::new()
+                .num_threads(self.threads)
+                .build()
+                .expect("could not start search threads")
+        });
+
+        let max_visits = match self.limits {
+            SearchLimits {
+                nodes: Some(nodes), ..
+            } => nodes,
+            SearchLimits { depth: Some(_), .. } => DEFAULT_VISITS,
+            SearchLimits { time: None, .. } if self.stop.is_none() => DEFAULT_VISITS,
+            SearchLimits { .. } => u64::MAX,
+        };
+
+        let batch = self.options.batch.max(1) * self.threads;
+        let mut report = FIRST_REPORT;
+
+        loop {
+            let visits = tree.root().visits as u64 - reused;
+
+            let stopped = self
+                .stop
+                .as_ref()
+                .is_some_and(|stop| stop.load(Ordering::Relaxed));
+
+            let deep_enough = self
+                .limits
+                .depth
+                .is_some_and(|depth| result(&tree).depth >= depth);
+
+            if visits >= max_visits
+                || stopped
+                || deep_enough
+                || self.limits.time.is_some_and(|t| start.elapsed() >= t)
+            {
+                break;
+            }
+
+            // the root alone the first time, it would be the whole batch
+            let size = if tree.root().is_leaf() {
+                1
+            } else {
+                batch.min((max_visits - visits) as usize)
+            };
+
+            let (paths, leaves): (Vec<_>, Vec<_>) = (0..size)
+                .map(|_| {
+                    let (path, position) = self.select(&mut tree);
+                    (path, (position, rng.next_u64()))
+                })
+                .unzip();
+
+            let leaf_value = self.options.leaf_value;
+            let value =
+                |&(position, seed): &(Position, u64)| value_leaf(&position, leaf_value, seed);
+
+            let valued = match &pool {
+                Some(pool) => pool.install(|| leaves.par_iter().map(value).collect::<Vec<_>>()),
+                None => leaves.iter().map(value).collect(),
+            };
+
+            for (path, valued) in paths.iter().zip(valued) {
+                back_up(&mut tree, path, valued);
+            }
+
+            // nothing to search
+            if tree.root().is_leaf() {
+                break;
+            }
+
+            if tree.root().visits >= report {
+                on_iteration(&result(&tree));
+                report *= 2;
+            }
+        }
+
+        let result = result(&tree);
+        on_iteration(&result);
+
+        self.tree = Some(tree);
+        result
+    }
+
+    /// The nodes from the root down to a leaf, with a virtual loss added to
+    /// each, and the position at the leaf
+    fn select(&self, tree: &mut GameTree) -> (Vec<NodeId>, Position) {
+        let mut path = vec![ROOT];
+        let mut position = *tree.position();
+        let mut id = ROOT;
+
+        tree.node_mut(id).virtual_loss += 1;
+
+        while !tree.node(id).is_leaf() {
+            id = self.pick(tree, id);
+
+            let node = tree.node_mut(id);
+            node.virtual_loss += 1;
+            position.play(node.mv.expect("only the root has no move"));
+            path.push(id);
+        }
+
+        (path, position)
+    }
+
+    fn pick(&self, tree: &GameTree, id: NodeId) -> NodeId {
+        let node = tree.node(id);
+        let visits = (node.visits + node.virtual_loss as u32) as f64;
+        let c = self.options.exploration;
+
+        // the children's values are from the other side's point of view
+        let first_play = -node.value() - FIRST_PLAY_REDUCTION;
+
+        let urgency = |child: NodeId| {
+            let child = tree.node(child);
+            let child_visits = (child.visits + child.virtual_loss as u32) as f64;
+
+            match self.options.selection {
+                Selection::Puct => {
+                    let value = if child_visits == 0.0 {
+                        first_play
+                    } else {
+                        child.value()
+                    };
+
+                    value + c * child.prior as f64 * visits.sqrt() / (1.0 + child_visits)
+                }
+                Selection::Uct if child_visits == 0.0 => f64::INFINITY,
+                Selection::Uct => child.value() + c * (visits.ln() / child_visits).sqrt(),
+            }
+        };
+
+        node.children()
+            .max_by(|&a, &b| urgency(a).total_cmp(&urgency(b)))
+            .expect("only called with children")
+    }
+}
+
+/// Add the leaf's value to every node on its path, take back the virtual
+/// losses, and expand the leaf unless another path of the batch already did
+fn back_up(tree: &mut GameTree, path: &[NodeId], valued: Valued) {
+    // nodes store values for the side that moved into them, which is the
+    // side not to move at the leaf, and every ply up the other one
+    let mut value = -valued.value;
+
+    for &id in path.iter().rev() {
+        let node = tree.node_mut(id);
+        node.visits += 1;
+        node.virtual_loss -= 1;
+        node.value_sum += value as f32;
+
+        value = -value;
+    }
+
+    let leaf = *path.last().expect("the root at least");
+
+    // a full tree keeps its leaves
+    if tree.node(leaf).is_leaf() {
+        tree.expand(leaf, valued.children.into_iter());
+    }
+}
+
+fn most_visited(tree: &GameTree, id: NodeId) -> Option<NodeId> {
+    tree.node(id)
+        .children()
+        .filter(|&child| tree.node(child).visits > 0)
+        .max_by(|&a, &b| {
+            let (a, b) = (tree.node(a), tree.node(b));
+            (a.visits, a.value())
+                .partial_cmp(&(b.visits, b.value()))
+                .unwrap()
+        })
+}
+
+fn result(tree: &GameTree) -> SearchResult {
+    let mut pv = vec![];
+    let mut id = ROOT;
+
+    while let Some(child) = most_visited(tree, id) {
+        pv.extend(tree.node(child).mv);
+        id = child;
+    }
+
+    let position = tree.position();
+    let score = match most_visited(tree, ROOT) {
+        Some(best) => to_score(tree.node(best).value()),
+        None if position.legal_moves().is_empty() && position.in_check(position.turn) => {
+            Score::mated_in(0)
+        }
+        None => Score::DRAW,
+    };
+
+    SearchResult {
+        best_move: pv.first().copied(),
+        depth: pv.len() as i32,
+        pv,
+        score,
+        nodes: tree.root().visits as u64,
+    }
+}
+
+/// Play one game from `opening`, returning the result for MCTS: 1.0 for a
+/// win, 0.5 for a draw and 0.0 for a loss
+fn play_game(opening: Position, mcts_color: Color, nodes: u64, visits: u64, seed: u64) -> f64 {
+    let mut position = opening;
+    let mut history = vec![position.hash_key()];
+
+    let mut searcher = Searcher::new(SearchLimits {
+        nodes: Some(nodes),
+        ..Default::default()
+    });
+
+    let mut mcts = Mcts::new(SearchLimits {
+        nodes: Some(visits),
+        ..Default::default()
+    })
+    .options(MctsOptions {
+        seed,
+        ..Default::default()
+    });
+
+    loop {
+        if position.legal_moves().is_empty() {
+            if !position.in_check(position.turn) {
+                return 0.5;
+            }
+
+            return if position.turn == mcts_color {
+                0.0
+            } else {
+                1.0
+            };
+        }
+
+        let key = position.hash_key();
+        let repeated = history.iter().filter(|&&k| k == key).count() >= 3;
+        let bare_kings = position.occupied() == position.kings;
+
+        if repeated || bare_kings || history.len() >= MAX_GAME_PLIES {
+            return 0.5;
+        }
+
+        let result = if position.turn == mcts_color {
+            mcts.search(&position, |_| {})
+        } else {
+            searcher.search(&position, |_| {})
+        };
+
+        let Some(mv) = result.best_move else {
+            return 0.5;
+        };
+
+        position.play(mv);
+        history.push(position.hash_key());
+    }
+}
+
+/// `mcts-match [games] [alpha-beta nodes per move] [mcts visits per move] [seed]`
+///
+/// Every opening is played twice, with colors swapped
+pub fn run(args: &[String]) {
+    let arg = |i: usize| args.get(i).and_then(|a| a.parse::<u64>().ok());
+
+    if args.iter().any(|a| a.parse::<u64>().is_err()) {
+        eprintln!(
+            "usage: chess mcts-match [games] [alpha-beta nodes per move] [mcts visits per move] [seed]"
+        );
+        return;
+    }
+
+    let games = arg(0).map_or(DEFAULT_GAMES, |g| g as usize);
+    let nodes = arg(1).unwrap_or(DEFAULT_MATCH_NODES);
+    let visits = arg(2).unwrap_or(DEFAULT_MATCH_VISITS);
+    let seed = arg(3).unwrap_or_else(rng::dense_random);
+
+    println!("{games} games, alpha-beta {nodes} nodes against mcts {visits} visits per move");
+
+    let results = (0..games)
+        .into_par_iter()
+        .map(|game| {
+            let mut rng = Rng::derive(seed, (game / 2) as u64);
+            let opening = crate::datagen::random_opening(&mut rng);
+            let mcts_color = if game % 2 == 0 {
+                Color::White
+            } else {
+                Color::Black
+            };
+
+            let result = play_game(opening, mcts_color, nodes, visits, rng.next_u64());
+            println!("game {}: mcts {mcts_color:?} scored {result:.1}", game + 1);
+
+            result
+        })
+        .collect::<Vec<_>>();
+
+    let count = |r: f64| results.iter().filter(|&&result| result == r).count();
+    let score = results.iter().sum::<f64>() / games.max(1) as f64;
+
+    // Elo difference for the score, capped short of infinite
+    let elo = -400.0 * (1.0 / score.clamp(0.001, 0.999) - 1.0).log10();
+
+    println!(
+        "mcts +{} ={} -{} against alpha-beta, {:.1}%, {elo:+.0} Elo",
+        count(1.0),
+        count(0.5),
+        count(0.0),
+        score * 100.0
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A tree of the start position with two of its moves, `a` visited
+    /// ten times for half a point each and `b` never
+    fn visited_tree(prior_a: f32) -> GameTree {
+        let mut tree = GameTree::new(Position::new(), 1);
+        let moves = Position::new().legal_moves();
+
+        tree.expand(
+            ROOT,
+            [(moves[0], prior_a), (moves[1], 1.0 - prior_a)].into_iter(),
+        );
+        tree.node_mut(ROOT).visits = 10;
+
+        let a = tree.node_mut(1);
+        a.visits = 10;
+        a.value_sum = 5.0;
+
+        tree
+    }
+
+    fn mcts(selection: Selection) -> Mcts {
+        Mcts::default().options(MctsOptions {
+            selection,
+            ..Default::default()
+        })
+    }
+
+    #[test]
+    fn uct_tries_every_child_first() {
+        assert_eq!(mcts(Selection::Uct).pick(&visited_tree(0.99), ROOT), 2);
+    }
+
+    #[test]
+    fn puct_follows_the_priors() {
+        let puct = mcts(Selection::Puct);
+
+        assert_eq!(puct.pick(&visited_tree(0.99), ROOT), 1);
+        assert_eq!(puct.pick(&visited_tree(0.01), ROOT), 2);
+    }
+
+    #[test]
+    fn backs_up_for_each_side() {
+        let mut tree = visited_tree(0.5);
+        let (path, position) = mcts(Selection::Uct).select(&mut tree);
+
+        assert_eq!(path, [ROOT, 2]);
+        assert_eq!(position.turn, Color::Black);
+
+        // good for black, who is to move at the leaf
+        let valued = Valued {
+            value: 0.5,
+            children: vec![],
+        };
+
+        back_up(&mut tree, &path, valued);
+
+        let (root, leaf) = (tree.root(), tree.node(2));
+
+        assert_eq!((root.visits, root.virtual_loss), (11, 0));
+        assert_eq!((leaf.visits, leaf.virtual_loss), (1, 0));
+        assert_eq!(leaf.value(), -0.5);
+        assert_eq!(root.value_sum, 0.5);
+    }
+
+    #[test]
+    fn keeps_the_tree_two_plies_down() {
+        let limits = |nodes| SearchLimits {
+            nodes: Some(nodes),
+            ..Default::default()
+        };
+
+        let mut mcts = Mcts::new(limits(2000));
+        let first = mcts.search(&Position::new(), |_| {});
+
+        let mut position = Position::new();
+        position.play(first.pv[0]);
+        position.play(first.pv[1]);
+
+        let tree = mcts.tree.as_ref().unwrap();
+        let kept = tree
+            .node(most_visited(tree, ROOT).unwrap())
+            .children()
+            .map(|id| tree.node(id))
+            .find(|node| node.mv == Some(first.pv[1]))
+            .unwrap()
+            .visits;
+        assert!(kept > 0);
+
+        // no new visits, only those of the kept tree
+        let mut mcts = mcts.limits(limits(0));
+        assert_eq!(mcts.search(&position, |_| {}).nodes, kept as u64);
+
+        // too far from the last root to be found
+        position.play(position.legal_moves()[0]);
+        position.play(position.legal_moves()[0]);
+        position.play(position.legal_moves()[0]);
+        assert_eq!(mcts.search(&position, |_| {}).nodes, 0);
+    }
+
+    #[test]
+    fn depth_limit_ends_a_search_that_can_be_stopped() {
+        let mut mcts = Mcts::new(SearchLimits {
+            depth: Some(3),
+            ..Default::default()
+        })
+        .stop_flag(Arc::new(AtomicBool::new(false)));
+
+        let result = mcts.search(&Position::new(), |_| {});
+
+        assert!(result.depth >= 3);
+        assert!(result.best_move.is_some());
+    }
+}
@@ -0,0 +1,1040 @@
+use std::{
+    sync::{
+        atomic::{AtomicBool, AtomicU64, Ordering},
+        Arc,
+    },
+    time::{Duration, Instant},
+};
+
+use crate::{
+    evaluation::{AccumulatorStack, CompoundEvaluator, MAX_PLY},
+    prelude::{gui::PieceType, *},
+    syzygy,
+};
+
+/// `chess bench-smp`: time to depth for different thread counts
+pub mod bench;
+
+/// Exhaustive forced mate search, for puzzles
+pub mod mate;
+
+/// Monte Carlo tree search, the alternative to alpha-beta
+pub mod mcts;
+
+/// Proof-number search for forced wins, with bounded memory
+pub mod proof;
+
+/// Null-move pruning, late move reductions and futility pruning
+pub mod pruning;
+
+/// The triangular table the principal variation is collected in
+mod pv;
+
+/// The transposition table shared by the search threads
+pub mod tt;
+
+use pv::PvTable;
+use tt::{Bound, TranspositionTable, TtEntry};
+
+/// When to stop searching. Unset limits are unbounded
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SearchLimits {
+    pub depth: Option<i32>,
+    pub nodes: Option<u64>,
+    pub time: Option<Duration>,
+}
+
+/// Which selectivity techniques the search uses, all of them by default.
+/// Each can be switched off to measure what it's worth in engine matches
+#[derive(Debug, Clone, Copy)]
+pub struct SearchOptions {
+    /// Let the opponent move twice; if that still fails high, so will a real move
+    pub null_move: bool,
+    /// Search late quiet moves shallower, and again at full depth if they turn out good
+    pub late_move_reductions: bool,
+    /// Return beta near the leaves when the static evaluation is far above it
+    pub reverse_futility: bool,
+    /// Skip quiet moves near the leaves when the static evaluation is far below alpha
+    pub futility: bool,
+    /// Search a ply deeper when in check
+    pub check_extensions: bool,
+}
+
+impl Default for SearchOptions {
+    fn default() -> Self {
+        Self {
+            null_move: true,
+            late_move_reductions: true,
+            reverse_futility: true,
+            futility: true,
+            check_extensions: true,
+        }
+    }
+}
+
+/// The game before the root, for draws by repetition and the fifty-move rule
+#[derive(Debug, Clone, Default)]
+pub struct GameHistory {
+    /// Positions since the last capture or pawn move, oldest first
+    keys: Vec<u64>,
+    /// Plies since the last capture or pawn move
+    halfmove_clock: u32,
+}
+
+impl GameHistory {
+    /// A game that starts `halfmove_clock` plies after a capture or pawn
+    /// move, as FEN gives it
+    pub fn new(halfmove_clock: u32) -> Self {
+        Self {
+            keys: vec![],
+            halfmove_clock,
+        }
+    }
+
+    pub fn halfmove_clock(&self) -> u32 {
+        self.halfmove_clock
+    }
+
+    /// `mv` is played from `position`
+    pub fn push(&mut self, position: &Position, mv: Move) {
+        if position.is_capture(mv) || position.pawns.at(mv.from) {
+            self.keys.clear();
+            self.halfmove_clock = 0;
+        } else {
+            self.keys.push(position.hash_key());
+            self.halfmove_clock += 1;
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct SearchResult {
+    pub best_move: Option<Move>,
+
+    /// The line the search expects, starting with the best move
+    pub pv: Vec<Move>,
+
+    /// Relative to the side to move
+    pub score: Score,
+    pub depth: i32,
+    pub nodes: u64,
+}
+
+/// Most valuable victim, least valuable attacker ordering value for each piece type
+const ORDER_VALUE: [i32; 6] = [100, 300, 300, 500, 900, 20_000];
+
+/// Threads add their node counts to the total in batches this big
+const NODE_BATCH: u64 = 1024;
+
+/// Iterations from this depth start with a window around the last score
+const ASPIRATION_DEPTH: i32 = 4;
+
+/// Half the first aspiration window, doubled every time it fails
+const ASPIRATION_WINDOW: i32 = 40;
+
+/// Alpha-beta with quiescence and a transposition table, deepened
+/// iteratively, on one or more threads.
+///
+/// The threads run Lazy SMP: each searches the whole tree from the root,
+/// half of them one ply deeper, and they only help each other through the
+/// transposition table. The first thread's result is the one reported.
+pub struct Searcher {
+    limits: SearchLimits,
+    options: SearchOptions,
+    threads: usize,
+    tt: Arc<TranspositionTable>,
+    stop: Arc<AtomicBool>,
+    history: GameHistory,
+}
+
+/// What the threads of one search share besides the table
+struct Shared {
+    stop: Arc<AtomicBool>,
+    /// Flushed every [`NODE_BATCH`] nodes
+    nodes: AtomicU64,
+}
+
+/// One search thread
+struct Worker<'a> {
+    /// 0 for the thread that reports, helpers count up from 1
+    id: usize,
+    limits: SearchLimits,
+    options: SearchOptions,
+    start: Instant,
+    nodes: u64,
+    stopped: bool,
+    tt: &'a TranspositionTable,
+    shared: &'a Shared,
+    pv: PvTable,
+    /// The network's accumulators down the current line, when NNUE is on
+    nnue: Option<AccumulatorStack>,
+    /// Positions before the current one, in the game and then the search
+    keys: Vec<u64>,
+    /// The halfmove clock of every position down the current line
+    clocks: Vec<u32>,
+}
+
+fn order_value(position: &Position, mv: Move) -> i32 {
+    let mut value = 0;
+
+    if position.is_capture(mv) {
+        let victim = position.piece_at(mv.to).unwrap_or(PieceType::Pawn);
+        let attacker = position.piece_at(mv.from).unwrap_or(PieceType::Pawn);
+
+        value += ORDER_VALUE[victim as usize] * 10 - ORDER_VALUE[attacker as usize];
+    }
+
+    if let Some(promotion) = mv.promotion {
+        value += ORDER_VALUE[promotion as usize] * 10;
+    }
+
+    value
+}
+
+/// Legal moves, best first, starting with the transposition table's move
+fn ordered_moves(position: &Position, tt_move: Option<Move>) -> Vec<Move> {
+    let mut moves = position.legal_moves();
+    moves.sort_by_cached_key(|&mv| {
+        if Some(mv) == tt_move {
+            i32::MIN
+        } else {
+            -order_value(position, mv)
+        }
+    });
+
+    moves
+}
+
+impl Searcher {
+    /// A single threaded search with a table of its own
+    pub fn new(limits: SearchLimits) -> Self {
+        Self::with_table(
+            limits,
+            Arc::new(TranspositionTable::new(tt::DEFAULT_HASH_MB)),
+        )
+    }
+
+    /// Search with `tt`, which can be kept between searches, like the UCI
+    /// loop does for a whole game
+    pub fn with_table(limits: SearchLimits, tt: Arc<TranspositionTable>) -> Self {
+        Self {
+            limits,
+            options: SearchOptions::default(),
+            threads: 1,
+            tt,
+            stop: Arc::default(),
+            history: GameHistory::default(),
+        }
+    }
+
+    pub fn limits(mut self, limits: SearchLimits) -> Self {
+        self.limits = limits;
+        self
+    }
+
+    pub fn options(mut self, options: SearchOptions) -> Self {
+        self.options = options;
+        self
+    }
+
+    /// Search on `threads` threads, at least one
+    pub fn threads(mut self, threads: usize) -> Self {
+        self.threads = threads.max(1);
+        self
+    }
+
+    /// The game so far, so the search knows which moves repeat
+    pub fn history(mut self, history: GameHistory) -> Self {
+        self.history = history;
+        self
+    }
+
+    /// Stop as soon as `stop` is set, from another thread. It is set when
+    /// the search ends on its own too, so it can't be used twice
+    pub fn stop_flag(mut self, stop: Arc<AtomicBool>) -> Self {
+        self.stop = stop;
+        self
+    }
+
+    /// Search `position` until a limit is hit, calling `on_iteration` after every completed depth
+    pub fn search(
+        &mut self,
+        position: &Position,
+        on_iteration: impl FnMut(&SearchResult),
+    ) -> SearchResult {
+        let start = Instant::now();
+        let shared = Shared {
+            stop: Arc::clone(&self.stop),
+            nodes: AtomicU64::new(0),
+        };
+
+        let worker = |id| Worker {
+            id,
+            limits: self.limits,
+            options: self.options,
+            start,
+            nodes: 0,
+            stopped: false,
+            tt: &self.tt,
+            shared: &shared,
+            pv: PvTable::new(),
+            nnue: CompoundEvaluator::nnue().map(|nnue| nnue.stack(position)),
+            keys: self.history.keys.clone(),
+            clocks: vec![self.history.halfmove_clock],
+        };
+
+        let mut result = if self.threads == 1 {
+            worker(0).search(position, on_iteration)
+        } else {
+            // threads of their own, so the helpers really run at the same
+            // time as the main thread instead of queueing on a busy pool
+            let pool = rayon::ThreadPoolBuilder::new()
+                .num_threads(self.threads - 1)
+                .build()
+                .expect("could not start search threads");
+
+            pool.in_place_scope(|scope| {
+                for id in 1..self.threads {
+                    let mut helper = worker(id);
+                    scope.spawn(move |_| {
+                        helper.search(position, |_| {});
+                    });
+                }
+
+                worker(0).search(position, on_iteration)
+            })
+        };
+
+        result.nodes = shared.nodes.load(Ordering::Relaxed);
+        result
+    }
+}
+
+impl Worker<'_> {
+    /// Deepen iteratively until a limit is hit or the search is stopped.
+    /// The main thread stops the helpers when it's done
+    fn search(
+        &mut self,
+        position: &Position,
+        mut on_iteration: impl FnMut(&SearchResult),
+    ) -> SearchResult {
+        let result = self.iterate(position, &mut on_iteration);
+
+        if self.id == 0 {
+            self.shared.stop.store(true, Ordering::Relaxed);
+        }
+
+        self.shared
+            .nodes
+            .fetch_add(self.nodes % NODE_BATCH, Ordering::Relaxed);
+
+        result
+    }
+
+    fn iterate(
+        &mut self,
+        position: &Position,
+        on_iteration: &mut impl FnMut(&SearchResult),
+    ) -> SearchResult {
+        let max_depth = self.limits.depth.unwrap_or(MAX_PLY - 1).min(MAX_PLY - 1);
+
+        // the tablebase already knows the perfect move
+        if let Some((mv, wdl)) = syzygy::tablebase(position).and_then(|tb| tb.best_move(position)) {
+            let result = SearchResult {
+                best_move: Some(mv),
+                pv: vec![mv],
+                score: wdl.score(0),
+                depth: 1,
+                nodes: 0,
+            };
+
+            on_iteration(&result);
+            return result;
+        }
+
+        let mut result = SearchResult {
+            best_move: None,
+            pv: vec![],
+            score: CompoundEvaluator::eval(position).relative(position.turn),
+            depth: 0,
+            nodes: 0,
+        };
+
+        for depth in 1..=max_depth {
+            // every other helper is a ply ahead, so the threads don't all
+            // search the same tree at the same time
+            let depth = depth + (self.id % 2) as i32;
+
+            if depth > max_depth {
+                break;
+            }
+
+            let (best_move, score) = if depth >= ASPIRATION_DEPTH && !result.score.is_mate() {
+                self.aspiration(position, depth, result.score)
+            } else {
+                self.root(position, depth, -Score::INFINITE, Score::INFINITE)
+            };
+
+            // an interrupted iteration is only trusted if it's all we have
+            if self.stopped && result.best_move.is_some() {
+                break;
+            }
+
+            let pv = match self.pv.line() {
+                line if line.first() == best_move.as_ref() => line,
+                _ => best_move.into_iter().collect(),
+            };
+
+            result = SearchResult {
+                best_move: best_move.or(result.best_move),
+                pv,
+                score,
+                depth,
+                nodes: self.total_nodes(),
+            };
+
+            if self.stopped {
+                break;
+            }
+
+            on_iteration(&result);
+
+            if best_move.is_none() || score.is_mate() {
+                break;
+            }
+        }
+
+        result
+    }
+
+    /// Nodes searched by every thread, give or take what the others
+    /// haven't flushed yet
+    fn total_nodes(&self) -> u64 {
+        self.shared.nodes.load(Ordering::Relaxed) + self.nodes % NODE_BATCH
+    }
+
+    fn should_stop(&mut self) -> bool {
+        if self.stopped {
+            return true;
+        }
+
+        let mut out_of_time = false;
+
+        if self.nodes.is_multiple_of(NODE_BATCH) {
+            self.shared.nodes.fetch_add(NODE_BATCH, Ordering::Relaxed);
+            out_of_time = self.limits.time.is_some_and(|t| self.start.elapsed() >= t);
+        }
+
+        let out_of_nodes = self.limits.nodes.is_some_and(|n| self.total_nodes() >= n);
+
+        if out_of_nodes || out_of_time {
+            self.shared.stop.store(true, Ordering::Relaxed);
+        }
+
+        self.stopped = self.shared.stop.load(Ordering::Relaxed);
+        self.stopped
+    }
+
+    /// The static evaluation, from the side to move's point of view
+    fn evaluate(&self, position: &Position) -> Score {
+        match &self.nnue {
+            Some(accumulators) => CompoundEvaluator::eval_incremental(position, accumulators),
+            None => CompoundEvaluator::eval(position),
+        }
+        .relative(position.turn)
+    }
+
+    /// Keep the network's accumulators and the history in step with the
+    /// search going down `mv`. Every call is paired with a
+    /// [`Worker::unmake`] on the way back
+    fn make(&mut self, position: &Position, mv: Move) {
+        if let Some(accumulators) = &mut self.nnue {
+            accumulators.make_move(position, mv);
+        }
+
+        let zeroing = position.is_capture(mv) || position.pawns.at(mv.from);
+        let clock = if zeroing { 0 } else { self.clock() + 1 };
+
+        self.keys.push(position.hash_key());
+        self.clocks.push(clock);
+    }
+
+    fn unmake(&mut self) {
+        if let Some(accumulators) = &mut self.nnue {
+            accumulators.unmake_move();
+        }
+
+        self.keys.pop();
+        self.clocks.pop();
+    }
+
+    /// A null move can't be repeated through, so the clock starts over
+    fn make_null(&mut self, position: &Position) {
+        self.keys.push(position.hash_key());
+        self.clocks.push(0);
+    }
+
+    fn unmake_null(&mut self) {
+        self.keys.pop();
+        self.clocks.pop();
+    }
+
+    fn clock(&self) -> u32 {
+        *self.clocks.last().expect("the root's clock at least")
+    }
+
+    /// Whether `position` repeats one since the last capture or pawn move,
+    /// or fifty moves went by without one. A single repetition is enough,
+    /// whatever was best there is best again
+    fn is_draw(&self, position: &Position) -> bool {
+        let clock = self.clock();
+
+        if clock >= 100 {
+            return !(position.in_check(position.turn) && position.legal_moves().is_empty());
+        }
+
+        let key = position.hash_key();
+
+        // the same side to move every other ply
+        self.keys
+            .iter()
+            .rev()
+            .take(clock as usize)
+            .skip(1)
+            .step_by(2)
+            .any(|&k| k == key)
+    }
+
+    /// Search with a window around the last iteration's score, widening
+    /// the side it fails on until the score lands inside
+    fn aspiration(
+        &mut self,
+        position: &Position,
+        depth: i32,
+        last: Score,
+    ) -> (Option<Move>, Score) {
+        let mut delta = ASPIRATION_WINDOW;
+        let mut alpha = (last - Score::cp(delta)).max(-Score::INFINITE);
+        let mut beta = (last + Score::cp(delta)).min(Score::INFINITE);
+
+        loop {
+            let (best_move, score) = self.root(position, depth, alpha, beta);
+
+            if self.stopped {
+                return (best_move, score);
+            }
+
+            delta *= 2;
+
+            if score <= alpha {
+                alpha = (score - Score::cp(delta)).max(-Score::INFINITE);
+            } else if score >= beta {
+                beta = (score + Score::cp(delta)).min(Score::INFINITE);
+            } else {
+                return (best_move, score);
+            }
+        }
+    }
+
+    /// The first move gets the whole window, the others a null window
+    /// that only proves them worse, searched again if it doesn't
+    fn root(
+        &mut self,
+        position: &Position,
+        depth: i32,
+        mut alpha: Score,
+        beta: Score,
+    ) -> (Option<Move>, Score) {
+        let original_alpha = alpha;
+        let key = position.hash_key();
+        let tt_move = self.tt.probe(key).and_then(|entry| entry.best_move);
+        let moves = ordered_moves(position, tt_move);
+
+        self.pv.clear(0);
+
+        if moves.is_empty() {
+            return (None, self.terminal(position, 0));
+        }
+
+        let mut best_move = Some(moves[0]);
+
+        for (index, mv) in moves.into_iter().enumerate() {
+            let mut child = *position;
+            child.play(mv);
+            self.make(position, mv);
+
+            let mut score = if index == 0 {
+                -self.negamax(&child, depth - 1, 1, -beta, -alpha, true)
+            } else {
+                -self.negamax(&child, depth - 1, 1, -alpha - Score::cp(1), -alpha, true)
+            };
+
+            if index > 0 && score > alpha && score < beta && !self.stopped {
+                score = -self.negamax(&child, depth - 1, 1, -beta, -alpha, true);
+            }
+
+            self.unmake();
+
+            if self.stopped {
+                break;
+            }
+
+            if score > alpha {
+                alpha = score;
+                best_move = Some(mv);
+                self.pv.update(0, mv);
+            }
+
+            if alpha >= beta {
+                break;
+            }
+        }
+
+        if !self.stopped {
+            let bound = if alpha >= beta {
+                Bound::Lower
+            } else if alpha <= original_alpha {
+                Bound::Upper
+            } else {
+                Bound::Exact
+            };
+
+            self.store(key, best_move, alpha.min(beta), depth, 0, bound);
+        }
+
+        (best_move, alpha.min(beta))
+    }
+
+    /// Score of a position without legal moves
+    fn terminal(&self, position: &Position, ply: i32) -> Score {
+        if position.in_check(position.turn) {
+            Score::mated_in(ply)
+        } else {
+            Score::DRAW
+        }
+    }
+
+    /// `null_allowed` is false right after a null move, two in a row would
+    /// only search the same position shallower
+    fn negamax(
+        &mut self,
+        position: &Position,
+        mut depth: i32,
+        ply: i32,
+        mut alpha: Score,
+        beta: Score,
+        null_allowed: bool,
+    ) -> Score {
+        self.pv.clear(ply);
+
+        if self.is_draw(position) {
+            return Score::DRAW;
+        }
+
+        let in_check = position.in_check(position.turn);
+
+        // checks are forcing, look a ply further past them
+        if in_check && self.options.check_extensions {
+            depth += 1;
+        }
+
+        if depth <= 0 || ply >= MAX_PLY - 1 {
+            return self.quiescence(position, ply, alpha, beta);
+        }
+
+        self.nodes += 1;
+
+        if self.should_stop() {
+            return Score::ZERO;
+        }
+
+        let key = position.hash_key();
+        let entry = self.tt.probe(key);
+
+        // a cutoff would leave the line from here empty, so nodes on the
+        // principal variation, searched with an open window, search on
+        let pv_node = beta.0 - alpha.0 > 1;
+
+        if let Some(entry) = entry.filter(|entry| !pv_node && entry.depth >= depth) {
+            let score = entry.score.from_tt(ply);
+
+            match entry.bound {
+                Bound::Exact => return score,
+                Bound::Lower if score >= beta => return beta,
+                Bound::Upper if score <= alpha => return alpha,
+                _ => {}
+            }
+        }
+
+        if let Some(wdl) = syzygy::tablebase(position).and_then(|tb| tb.probe_wdl(position)) {
+            return wdl.score(ply);
+        }
+
+        let eval = self.evaluate(position);
+
+        if self.options.reverse_futility
+            && !in_check
+            && depth <= pruning::REVERSE_FUTILITY_DEPTH
+            && !beta.is_mate()
+            && eval - Score::cp(pruning::REVERSE_FUTILITY_MARGIN * depth) >= beta
+        {
+            return beta;
+        }
+
+        if self.options.null_move
+            && null_allowed
+            && !in_check
+            && depth >= pruning::NULL_MOVE_DEPTH
+            && eval >= beta
+            && pruning::has_non_pawn_material(position, position.turn)
+        {
+            let mut child = *position;
+            child.play_null();
+            self.make_null(position);
+
+            let reduction = pruning::NULL_MOVE_REDUCTION + depth / 4;
+            let score = -self.negamax(
+                &child,
+                depth - 1 - reduction,
+                ply + 1,
+                -beta,
+                -beta + Score::cp(1),
+                false,
+            );
+
+            self.unmake_null();
+
+            if self.stopped {
+                return Score::ZERO;
+            }
+
+            if score >= beta {
+                return beta;
+            }
+        }
+
+        let moves = ordered_moves(position, entry.and_then(|entry| entry.best_move));
+
+        if moves.is_empty() {
+            return self.terminal(position, ply);
+        }
+
+        let futile = self.options.futility
+            && !in_check
+            && (depth as usize) < pruning::FUTILITY_MARGIN.len()
+            && !alpha.is_mate()
+            && eval + Score::cp(pruning::FUTILITY_MARGIN[depth as usize]) <= alpha;
+
+        let mut best_move = None;
+
+        for (index, mv) in moves.into_iter().enumerate() {
+            let quiet = !position.is_capture(mv) && mv.promotion.is_none();
+
+            let mut child = *position;
+            child.play(mv);
+
+            let gives_check = quiet && child.in_check(child.turn);
+
+            // the first move is searched anyway, so there is a score to return
+            if futile && index > 0 && quiet && !gives_check {
+                continue;
+            }
+
+            self.make(position, mv);
+
+            let reduction = if self.options.late_move_reductions
+                && depth >= pruning::LMR_DEPTH
+                && index >= pruning::LMR_MOVES
+                && quiet
+                && !in_check
+                && !gives_check
+            {
+                pruning::lmr_reduction(depth, index)
+            } else {
+                0
+            };
+
+            // principal variation search: after the first move, only prove
+            // the others worse with a null window, at reduced depth if late
+            let mut score = if index == 0 {
+                -self.negamax(&child, depth - 1, ply + 1, -beta, -alpha, true)
+            } else {
+                let null_window = -alpha - Score::cp(1);
+                let mut score = -self.negamax(
+                    &child,
+                    depth - 1 - reduction,
+                    ply + 1,
+                    null_window,
+                    -alpha,
+                    true,
+                );
+
+                if reduction > 0 && score > alpha && !self.stopped {
+                    score = -self.negamax(&child, depth - 1, ply + 1, null_window, -alpha, true);
+                }
+
+                score
+            };
+
+            if index > 0 && score > alpha && score < beta && !self.stopped {
+                score = -self.negamax(&child, depth - 1, ply + 1, -beta, -alpha, true);
+            }
+
+            self.unmake();
+
+            if self.stopped {
+                return Score::ZERO;
+            }
+
+            if score >= beta {
+                self.store(key, Some(mv), beta, depth, ply, Bound::Lower);
+                return beta;
+            }
+
+            if score > alpha {
+                alpha = score;
+                best_move = Some(mv);
+                self.pv.update(ply, mv);
+            }
+        }
+
+        // without a move that raised alpha, all we know is an upper bound
+        let bound = if best_move.is_some() {
+            Bound::Exact
+        } else {
+            Bound::Upper
+        };
+
+        self.store(key, best_move, alpha, depth, ply, bound);
+        alpha
+    }
+
+    fn store(
+        &self,
+        key: u64,
+        best_move: Option<Move>,
+        score: Score,
+        depth: i32,
+        ply: i32,
+        bound: Bound,
+    ) {
+        self.tt.store(
+            key,
+            TtEntry {
+                best_move,
+                score: score.to_tt(ply),
+                depth,
+                bound,
+            },
+        );
+    }
+
+    /// Only search captures and promotions, so the static evaluation is
+    /// never taken in the middle of an exchange. In check, every evasion is
+    /// searched instead, as standing pat would miss mates
+    fn quiescence(&mut self, position: &Position, ply: i32, mut alpha: Score, beta: Score) -> Score {
+        self.pv.clear(ply);
+        self.nodes += 1;
+
+        if self.should_stop() {
+            return Score::ZERO;
+        }
+
+        let moves = ordered_moves(position, None);
+
+        if moves.is_empty() {
+            return self.terminal(position, ply);
+        }
+
+        if ply >= MAX_PLY - 1 {
+            return self.evaluate(position);
+        }
+
+        let in_check = position.in_check(position.turn);
+
+        if !in_check {
+            let stand_pat = self.evaluate(position);
+
+            if stand_pat >= beta {
+                return stand_pat;
+            }
+
+            if stand_pat > alpha {
+                alpha = stand_pat;
+            }
+        }
+
+        for mv in moves {
+            if !in_check && !position.is_capture(mv) && mv.promotion.is_none() {
+                continue;
+            }
+
+            let mut child = *position;
+            child.play(mv);
+
+            self.make(position, mv);
+            let score = -self.quiescence(&child, ply + 1, -beta, -alpha);
+            self.unmake();
+
+            if self.stopped {
+                return Score::ZERO;
+            }
+
+            if score >= beta {
+                return beta;
+            }
+
+            if score > alpha {
+                alpha = score;
+            }
+        }
+
+        alpha
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn shared() -> Shared {
+        Shared {
+            stop: Arc::default(),
+            nodes: AtomicU64::new(0),
+        }
+    }
+
+    /// A worker after `history`, without check extensions
+    fn worker<'a>(
+        tt: &'a TranspositionTable,
+        shared: &'a Shared,
+        history: &GameHistory,
+    ) -> Worker<'a> {
+        Worker {
+            id: 0,
+            limits: SearchLimits::default(),
+            options: SearchOptions {
+                check_extensions: false,
+                ..Default::default()
+            },
+            start: Instant::now(),
+            nodes: 0,
+            stopped: false,
+            tt,
+            shared,
+            pv: PvTable::new(),
+            nnue: None,
+            keys: history.keys.clone(),
+            clocks: vec![history.halfmove_clock],
+        }
+    }
+
+    fn quiescence(position: &Position) -> Score {
+        let (tt, shared) = (TranspositionTable::new(1), shared());
+
+        worker(&tt, &shared, &GameHistory::default()).quiescence(
+            position,
+            0,
+            -Score::INFINITE,
+            Score::INFINITE,
+        )
+    }
+
+    /// Play `moves` in SAN, in the game and in the worker's line
+    fn play(worker: &mut Worker, history: &mut GameHistory, position: &mut Position, moves: &str) {
+        for san in moves.split_whitespace() {
+            let mv = position.parse_san(san).unwrap();
+
+            worker.make(position, mv);
+            history.push(position, mv);
+            position.play(mv);
+        }
+    }
+
+    #[test]
+    fn quiescence_searches_evasions() {
+        // Kg8 is forced, then Qxg7 mates
+        let position = Position::from_fen("7k/5Npp/8/8/3B2Q1/8/8/2K5 b - - 0 1").unwrap();
+
+        assert_eq!(quiescence(&position), Score::mated_in(2));
+    }
+
+    #[test]
+    fn quiescence_stands_pat_out_of_check() {
+        let position = Position::new();
+        let eval = CompoundEvaluator::eval(&position).relative(position.turn);
+
+        assert_eq!(quiescence(&position), eval);
+    }
+
+    #[test]
+    fn repetitions_are_draws() {
+        let (tt, shared) = (TranspositionTable::new(1), shared());
+        let mut history = GameHistory::default();
+        let mut position = Position::new();
+        let mut worker = worker(&tt, &shared, &history);
+
+        play(&mut worker, &mut history, &mut position, "Nf3 Nf6 Ng1");
+        assert!(!worker.is_draw(&position));
+
+        play(&mut worker, &mut history, &mut position, "Ng8");
+        assert!(worker.is_draw(&position));
+
+        // the same from the game before the root
+        assert!(self::worker(&tt, &shared, &history).is_draw(&position));
+    }
+
+    #[test]
+    fn pawn_moves_and_captures_start_the_clock_over() {
+        let (tt, shared) = (TranspositionTable::new(1), shared());
+        let mut history = GameHistory::default();
+        let mut position = Position::new();
+        let mut worker = worker(&tt, &shared, &history);
+
+        play(&mut worker, &mut history, &mut position, "Nf3 d5 Ng1");
+        assert_eq!((worker.clock(), history.halfmove_clock), (1, 1));
+
+        let moves = "Nf6 Nf3 Ne4 Nd4 Nxf2";
+        play(&mut worker, &mut history, &mut position, moves);
+        assert_eq!((worker.clock(), history.halfmove_clock), (0, 0));
+        assert!(history.keys.is_empty());
+    }
+
+    #[test]
+    fn fifty_moves_are_draws_unless_mate() {
+        let (tt, shared) = (TranspositionTable::new(1), shared());
+        let worker = worker(&tt, &shared, &GameHistory::new(100));
+        let mated = Position::from_fen("R5k1/5ppp/8/8/8/8/8/6K1 b - - 100 80").unwrap();
+
+        assert!(worker.is_draw(&Position::new()));
+        assert!(!worker.is_draw(&mated));
+    }
+
+    #[test]
+    fn escapes_into_a_repetition() {
+        // a queen down, black only gets away from the back rank mate by
+        // going back to h8, which repeats
+        let mut position = Position::from_fen("7k/5ppp/8/8/8/8/8/2KQ4 w - - 0 1").unwrap();
+        let mut history = GameHistory::default();
+
+        for san in ["Qd2", "Kg8", "Qd1"] {
+            let mv = position.parse_san(san).unwrap();
+            history.push(&position, mv);
+            position.play(mv);
+        }
+
+        let limits = SearchLimits {
+            depth: Some(4),
+            ..Default::default()
+        };
+
+        let result = Searcher::new(limits)
+            .history(history)
+            .search(&position, |_| {});
+        assert_eq!(result.score, Score::DRAW);
+        assert_eq!(result.best_move, position.parse_san("Kh8"));
+
+        let result = Searcher::new(limits).search(&position, |_| {});
+        assert!(result.score < Score::cp(-500));
+    }
+}
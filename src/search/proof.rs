@@ -0,0 +1,624 @@
+// Proof-number search: does the side to move have a forced win?
+//
+// Every node has a proof number, how many leaves at least still have to be
+// shown won to prove it, and a disproof number, the same for showing it
+// isn't. Instead of going to a fixed depth everywhere, the search keeps
+// expanding the leaf that settles the root cheapest, so narrow forcing
+// lines get as deep as they need to. That is what proves mates and wins
+// alpha-beta would need a huge depth for.
+//
+// With `second_level` (PN²) a new leaf first gets its numbers from a small
+// proof-number search of its own, which is thrown away. The numbers are
+// much better than 1 and 1, and only the first level stays in memory.
+//
+// See https://www.chessprogramming.org/Proof-Number_Search
+use std::time::Instant;
+
+use crate::{
+    prelude::*,
+    syzygy::{self, Wdl},
+};
+
+/// Proof and disproof numbers saturate here, it can't be done
+const INFINITY: u32 = u32::MAX;
+
+const ROOT: usize = 0;
+
+pub const DEFAULT_MAX_NODES: usize = 1 << 19;
+
+/// A second level search gets a sixteenth of the nodes the first level
+/// has, within these bounds. At least more than the 218 legal moves a
+/// position can have, or it couldn't always expand its own root
+const SECOND_LEVEL_NODES: (usize, usize) = (256, 1024);
+
+/// What counts as a win for the side to move
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Goal {
+    /// Only checkmate
+    Mate,
+    /// Checkmate, or a position the tablebases count as won
+    Win,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct ProofLimits {
+    /// Most positions kept in the tree at once. The search gives up when
+    /// it needs more
+    pub max_nodes: usize,
+    /// Lines longer than this count as no win, `None` to search as deep as
+    /// the tree goes. `2 * n - 1` plies asks for a mate in `n`
+    pub max_plies: Option<u32>,
+    /// PN² rather than plain proof-number search
+    pub second_level: bool,
+    pub goal: Goal,
+}
+
+impl Default for ProofLimits {
+    fn default() -> Self {
+        Self {
+            max_nodes: DEFAULT_MAX_NODES,
+            max_plies: None,
+            second_level: true,
+            goal: Goal::Win,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Outcome {
+    /// The side to move wins by force
+    Proven,
+    /// It doesn't, within the horizon
+    Disproven,
+    /// Out of memory before either
+    Unknown,
+}
+
+/// The moves that show a solved search right. For a proof, that's one
+/// move wherever the attacker is to move and every move wherever the
+/// defender is; a disproof is the other way around
+#[derive(Debug, Clone, Default)]
+pub struct ProofTree {
+    pub moves: Vec<(Move, ProofTree)>,
+}
+
+impl ProofTree {
+    /// Positions in the tree, the root included
+    pub fn size(&self) -> usize {
+        1 + self
+            .moves
+            .iter()
+            .map(|(_, tree)| tree.size())
+            .sum::<usize>()
+    }
+
+    /// The first move at every position
+    pub fn main_line(&self) -> Vec<Move> {
+        match self.moves.first() {
+            Some((mv, tree)) => [*mv].into_iter().chain(tree.main_line()).collect(),
+            None => vec![],
+        }
+    }
+}
+
+struct Node {
+    position: Position,
+    /// The move that led here from the parent
+    mv: Option<Move>,
+    parent: Option<usize>,
+    children: Vec<usize>,
+    ply: u32,
+    proof: u32,
+    disproof: u32,
+}
+
+impl Node {
+    fn solved(&self) -> bool {
+        self.proof == 0 || self.disproof == 0
+    }
+}
+
+/// A proof-number search, kept around to read the proof tree when done
+pub struct ProofSearch {
+    limits: ProofLimits,
+    /// The side to move at the root, whose win is in question
+    attacker: Color,
+    /// Slots of freed nodes are in `free`, to be used again
+    nodes: Vec<Node>,
+    free: Vec<usize>,
+    /// Positions expanded, the second level's included
+    expanded: u64,
+}
+
+impl ProofSearch {
+    pub fn new(position: Position, limits: ProofLimits) -> Self {
+        Self::for_attacker(position, limits, position.turn)
+    }
+
+    fn for_attacker(position: Position, limits: ProofLimits, attacker: Color) -> Self {
+        let mut search = Self {
+            limits,
+            attacker,
+            nodes: vec![],
+            free: vec![],
+            expanded: 0,
+        };
+
+        let (proof, disproof) = match search.settled(&position, 0) {
+            Some(won) => numbers_of(won),
+            None => (1, 1),
+        };
+
+        search.nodes.push(Node {
+            position,
+            mv: None,
+            parent: None,
+            children: vec![],
+            ply: 0,
+            proof,
+            disproof,
+        });
+
+        search
+    }
+
+    /// Positions expanded so far
+    pub fn expanded(&self) -> u64 {
+        self.expanded
+    }
+
+    /// Positions in the tree now
+    pub fn len(&self) -> usize {
+        self.nodes.len() - self.free.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn outcome(&self) -> Outcome {
+        let root = &self.nodes[ROOT];
+
+        if root.proof == 0 {
+            Outcome::Proven
+        } else if root.disproof == 0 {
+            Outcome::Disproven
+        } else {
+            Outcome::Unknown
+        }
+    }
+
+    /// Search until the root is solved or the tree is full
+    pub fn run(&mut self) -> Outcome {
+        while !self.nodes[ROOT].solved() {
+            let leaf = self.most_proving();
+
+            if !self.expand(leaf) {
+                break;
+            }
+
+            self.update(leaf);
+        }
+
+        self.outcome()
+    }
+
+    /// The proof or disproof, once the search has one
+    pub fn proof_tree(&self) -> Option<ProofTree> {
+        match self.outcome() {
+            Outcome::Proven => Some(self.tree(ROOT, true)),
+            Outcome::Disproven => Some(self.tree(ROOT, false)),
+            Outcome::Unknown => None,
+        }
+    }
+
+    /// Whether the game is decided here without searching further, and
+    /// if so whether the attacker won
+    fn settled(&self, position: &Position, ply: u32) -> Option<bool> {
+        if position.legal_moves().is_empty() {
+            return Some(position.in_check(position.turn) && position.turn != self.attacker);
+        }
+
+        if position.occupied() == position.kings {
+            return Some(false);
+        }
+
+        if self.limits.goal == Goal::Win {
+            let wdl = syzygy::tablebase(position).and_then(|tb| tb.probe_wdl(position));
+
+            if let Some(wdl) = wdl {
+                let won = if position.turn == self.attacker {
+                    Wdl::Win
+                } else {
+                    Wdl::Loss
+                };
+
+                return Some(wdl == won);
+            }
+        }
+
+        if self.limits.max_plies.is_some_and(|max| ply >= max) {
+            return Some(false);
+        }
+
+        None
+    }
+
+    /// Numbers for a new leaf
+    fn leaf_numbers(&mut self, position: &Position, ply: u32) -> (u32, u32) {
+        if let Some(won) = self.settled(position, ply) {
+            return numbers_of(won);
+        }
+
+        if !self.limits.second_level {
+            return (1, 1);
+        }
+
+        let limits = ProofLimits {
+            max_nodes: (self.len() / 16)
+                .clamp(SECOND_LEVEL_NODES.0, SECOND_LEVEL_NODES.1)
+                .min(self.limits.max_nodes),
+            max_plies: self.limits.max_plies.map(|max| max - ply),
+            second_level: false,
+            ..self.limits
+        };
+
+        let mut search = Self::for_attacker(*position, limits, self.attacker);
+        search.run();
+        self.expanded += search.expanded;
+
+        let root = &search.nodes[ROOT];
+        (root.proof, root.disproof)
+    }
+
+    /// From the root down to a leaf, always to the child that is cheapest
+    /// to prove where the attacker picks, and cheapest to disprove where
+    /// the defender does
+    fn most_proving(&self) -> usize {
+        let mut index = ROOT;
+
+        loop {
+            let node = &self.nodes[index];
+            let attacking = node.position.turn == self.attacker;
+
+            let next = node.children.iter().copied().min_by_key(|&child| {
+                let child = &self.nodes[child];
+
+                if attacking {
+                    child.proof
+                } else {
+                    child.disproof
+                }
+            });
+
+            match next {
+                Some(next) => index = next,
+                None => return index,
+            }
+        }
+    }
+
+    /// Add every child of the leaf at `index`, false if they don't fit
+    fn expand(&mut self, index: usize) -> bool {
+        let parent = &self.nodes[index];
+        let position = parent.position;
+        let ply = parent.ply + 1;
+        let moves = position.legal_moves();
+
+        if self.len() + moves.len() > self.limits.max_nodes {
+            return false;
+        }
+
+        self.expanded += 1;
+
+        for mv in moves {
+            let mut child = position;
+            child.play(mv);
+
+            let (proof, disproof) = self.leaf_numbers(&child, ply);
+            let node = Node {
+                position: child,
+                mv: Some(mv),
+                parent: Some(index),
+                children: vec![],
+                ply,
+                proof,
+                disproof,
+            };
+
+            let slot = match self.free.pop() {
+                Some(slot) => {
+                    self.nodes[slot] = node;
+                    slot
+                }
+                None => {
+                    self.nodes.push(node);
+                    self.nodes.len() - 1
+                }
+            };
+
+            self.nodes[index].children.push(slot);
+        }
+
+        true
+    }
+
+    /// Recompute the numbers from `index` up, for as long as they change
+    fn update(&mut self, mut index: usize) {
+        loop {
+            let node = &self.nodes[index];
+            let attacking = node.position.turn == self.attacker;
+            let children = node.children.iter().map(|&child| &self.nodes[child]);
+
+            let proofs = children.clone().map(|child| child.proof);
+            let disproofs = children.map(|child| child.disproof);
+
+            // the attacker needs one winning move, the defender has to
+            // lose after all of them
+            let (proof, disproof) = if attacking {
+                (proofs.min().unwrap_or(INFINITY), saturating_sum(disproofs))
+            } else {
+                (saturating_sum(proofs), disproofs.min().unwrap_or(INFINITY))
+            };
+
+            let node = &mut self.nodes[index];
+            let changed = (node.proof, node.disproof) != (proof, disproof);
+
+            node.proof = proof;
+            node.disproof = disproof;
+
+            if node.solved() {
+                self.prune(index);
+            }
+
+            match self.nodes[index].parent {
+                Some(parent) if changed => index = parent,
+                _ => break,
+            }
+        }
+    }
+
+    /// Free the children of a solved node that its proof doesn't need
+    fn prune(&mut self, index: usize) {
+        let node = &self.nodes[index];
+        let attacking = node.position.turn == self.attacker;
+        let proven = node.proof == 0;
+
+        // where the winning side picks, one move is enough
+        if attacking != proven {
+            return;
+        }
+
+        let keep = node.children.iter().copied().find(|&child| {
+            let child = &self.nodes[child];
+
+            if proven {
+                child.proof == 0
+            } else {
+                child.disproof == 0
+            }
+        });
+
+        let children = std::mem::take(&mut self.nodes[index].children);
+
+        for child in children {
+            if Some(child) == keep {
+                self.nodes[index].children.push(child);
+            } else {
+                self.free_subtree(child);
+            }
+        }
+    }
+
+    fn free_subtree(&mut self, index: usize) {
+        let children = std::mem::take(&mut self.nodes[index].children);
+
+        for child in children {
+            self.free_subtree(child);
+        }
+
+        self.free.push(index);
+    }
+
+    fn tree(&self, index: usize, proven: bool) -> ProofTree {
+        let node = &self.nodes[index];
+
+        if node.children.is_empty() {
+            if self.settled(&node.position, node.ply).is_some() {
+                return ProofTree::default();
+            }
+
+            // solved by a second level search, which has to run again
+            let limits = ProofLimits {
+                max_plies: self.limits.max_plies.map(|max| max - node.ply),
+                second_level: false,
+                ..self.limits
+            };
+
+            let mut search = Self::for_attacker(node.position, limits, self.attacker);
+            search.run();
+
+            return search.proof_tree().unwrap_or_default();
+        }
+
+        let attacking = node.position.turn == self.attacker;
+        let solved = |&child: &usize| {
+            let child = &self.nodes[child];
+
+            if proven {
+                child.proof == 0
+            } else {
+                child.disproof == 0
+            }
+        };
+
+        let children = node.children.iter().filter(|child| solved(child));
+        let take = if attacking == proven { 1 } else { usize::MAX };
+
+        ProofTree {
+            moves: children
+                .take(take)
+                .map(|&child| {
+                    let mv = self.nodes[child].mv.expect("only the root has no move");
+                    (mv, self.tree(child, proven))
+                })
+                .collect(),
+        }
+    }
+}
+
+fn numbers_of(won: bool) -> (u32, u32) {
+    if won {
+        (0, INFINITY)
+    } else {
+        (INFINITY, 0)
+    }
+}
+
+fn saturating_sum(numbers: impl Iterator<Item = u32>) -> u32 {
+    numbers.fold(0, u32::saturating_add)
+}
+
+/// `prove [pn] [moves <n>] [nodes <n>] [mate] <fen>`
+pub fn run(args: &[String]) {
+    let usage = "usage: chess prove [pn] [mate] [moves <n>] [nodes <n>] <fen>";
+    let mut limits = ProofLimits::default();
+    let mut args = args.iter().map(|s| s.as_str()).peekable();
+
+    while let Some(&arg) = args.peek() {
+        let value = |args: &mut std::iter::Peekable<_>| -> Option<usize> {
+            args.next();
+            args.next().and_then(|n: &str| n.parse().ok())
+        };
+
+        match arg {
+            "pn" => limits.second_level = false,
+            "mate" => limits.goal = Goal::Mate,
+            "moves" => match value(&mut args) {
+                Some(moves) if moves > 0 => {
+                    limits.max_plies = Some(moves as u32 * 2 - 1);
+                    continue;
+                }
+                _ => return eprintln!("{usage}"),
+            },
+            "nodes" => match value(&mut args) {
+                Some(nodes) => {
+                    limits.max_nodes = nodes;
+                    continue;
+                }
+                None => return eprintln!("{usage}"),
+            },
+            _ => break,
+        }
+
+        args.next();
+    }
+
+    let fen = args.join(" ");
+    let position = match Position::from_fen(&fen) {
+        Ok(position) => position,
+        Err(e) => return eprintln!("invalid fen: {e}\n{usage}"),
+    };
+
+    let start = Instant::now();
+    let mut search = ProofSearch::new(position, limits);
+    let outcome = search.run();
+    let secs = start.elapsed().as_secs_f32();
+
+    println!(
+        "{outcome:?} after {} expansions in {secs:.2} s, {} nodes in the tree",
+        search.expanded(),
+        search.len()
+    );
+
+    if let Some(tree) = search.proof_tree() {
+        let line = position.line_to_uci(&tree.main_line(), false);
+        println!("{} positions in the proof, main line {line}", tree.size());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MATE_IN_TWO: &str = "kbK5/pp6/1P6/8/8/8/8/R7 w - - 0 1";
+
+    fn mate_in(moves: u32) -> ProofLimits {
+        ProofLimits {
+            max_plies: Some(moves * 2 - 1),
+            goal: Goal::Mate,
+            ..Default::default()
+        }
+    }
+
+    fn uci(position: &Position, line: &[Move]) -> String {
+        position.line_to_uci(line, false)
+    }
+
+    #[test]
+    fn proves_a_mate_in_one() {
+        let position = Position::from_fen("6k1/5ppp/8/8/8/8/8/R5K1 w - - 0 1").unwrap();
+        let mut search = ProofSearch::new(position, mate_in(1));
+
+        assert_eq!(search.run(), Outcome::Proven);
+
+        let tree = search.proof_tree().unwrap();
+        assert_eq!(uci(&position, &tree.main_line()), "a1a8");
+        assert_eq!(tree.size(), 2);
+    }
+
+    #[test]
+    fn proves_a_mate_in_two() {
+        let position = Position::from_fen(MATE_IN_TWO).unwrap();
+        let mut search = ProofSearch::new(position, mate_in(2));
+
+        assert_eq!(search.run(), Outcome::Proven);
+
+        // every one of the seven replies to Ra6 (axb6 opens the a-file),
+        // each with its mate
+        let tree = search.proof_tree().unwrap();
+        assert_eq!(tree.main_line().len(), 3);
+        assert_eq!(tree.moves[0].0.to_string(), "a1a6");
+        assert_eq!(tree.moves[0].1.moves.len(), 7);
+        assert_eq!(tree.size(), 2 + 7 * 2);
+    }
+
+    #[test]
+    fn disproves_beyond_the_horizon() {
+        let mut search = ProofSearch::new(Position::new(), mate_in(2));
+
+        assert_eq!(search.run(), Outcome::Disproven);
+        assert!(search.proof_tree().is_some());
+
+        // a mate in two isn't one in one
+        let position = Position::from_fen(MATE_IN_TWO).unwrap();
+        assert_eq!(
+            ProofSearch::new(position, mate_in(1)).run(),
+            Outcome::Disproven
+        );
+    }
+
+    #[test]
+    fn gives_up_when_the_tree_is_full() {
+        let limits = ProofLimits {
+            max_nodes: 100,
+            ..Default::default()
+        };
+        let mut search = ProofSearch::new(Position::new(), limits);
+
+        assert_eq!(search.run(), Outcome::Unknown);
+        assert!(search.proof_tree().is_none());
+        assert!(search.len() <= 100);
+    }
+
+    #[test]
+    fn second_level_expands_its_root() {
+        let mut search = ProofSearch::new(Position::new(), ProofLimits::default());
+        let mut position = Position::new();
+        position.play(position.parse_san("e4").unwrap());
+
+        // black has 20 replies, every one of which white has to prove won
+        let (proof, disproof) = search.leaf_numbers(&position, 1);
+        assert!(proof >= 20, "{proof} {disproof}");
+    }
+}
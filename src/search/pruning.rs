@@ -0,0 +1,58 @@
+// Parameters of the selective search: what gets pruned, reduced or extended
+use std::sync::OnceLock;
+
+use crate::prelude::*;
+
+/// Null-move pruning needs this much depth left
+pub const NULL_MOVE_DEPTH: i32 = 3;
+
+/// The null move is searched this much shallower, plus a ply for every 4
+/// of depth
+pub const NULL_MOVE_REDUCTION: i32 = 2;
+
+/// Reverse futility pruning only this close to the leaves
+pub const REVERSE_FUTILITY_DEPTH: i32 = 6;
+
+/// How far the static evaluation has to be above beta, per ply of depth
+pub const REVERSE_FUTILITY_MARGIN: i32 = 80;
+
+/// How much a quiet move could gain at most, by depth left. Futility
+/// pruning skips quiet moves when even that doesn't reach alpha
+pub const FUTILITY_MARGIN: [i32; 3] = [0, 150, 300];
+
+/// Late move reductions need this much depth left
+pub const LMR_DEPTH: i32 = 3;
+
+/// The first moves, best by ordering, are never reduced
+pub const LMR_MOVES: usize = 3;
+
+/// Reductions by depth and move number, see [`lmr_reduction`]
+static LMR_TABLE: OnceLock<[[i32; 64]; 64]> = OnceLock::new();
+
+/// How many plies shallower the `index`th move is searched: grows with the
+/// log of both, so late moves at high depth are reduced the most
+pub fn lmr_reduction(depth: i32, index: usize) -> i32 {
+    let table = LMR_TABLE.get_or_init(|| {
+        let mut table = [[0; 64]; 64];
+
+        for (depth, row) in table.iter_mut().enumerate().skip(1) {
+            for (index, reduction) in row.iter_mut().enumerate().skip(1) {
+                let log = (depth as f64).ln() * (index as f64).ln();
+                *reduction = (0.75 + log / 2.25) as i32;
+            }
+        }
+
+        table
+    });
+
+    // never straight into quiescence
+    let reduction = table[depth.clamp(0, 63) as usize][index.min(63)];
+    reduction.min(depth - 2).max(0)
+}
+
+/// Whether the side has anything but pawns and its king. Without, passing
+/// is often better than any move, and null-move pruning gets zugzwang wrong
+pub fn has_non_pawn_material(position: &Position, color: Color) -> bool {
+    let pieces = position.knights | position.bishops | position.rooks | position.queens;
+    !(pieces & position.pieces_of_col(color)).is_empty()
+}
@@ -0,0 +1,54 @@
+use crate::{evaluation::MAX_PLY, prelude::*};
+
+const PLIES: usize = MAX_PLY as usize;
+
+/// The best line from every ply of the current path, in a triangle: the
+/// line from `ply` is its best move followed by the line from `ply + 1`.
+///
+/// Unlike following best moves through the transposition table, the line
+/// stays whole when other positions overwrite their entries
+pub struct PvTable {
+    moves: Vec<[Option<Move>; PLIES]>,
+    len: [usize; PLIES],
+}
+
+impl PvTable {
+    pub fn new() -> Self {
+        Self {
+            moves: vec![[None; PLIES]; PLIES],
+            len: [0; PLIES],
+        }
+    }
+
+    /// Forget the line from `ply`, when a node there is entered
+    pub fn clear(&mut self, ply: i32) {
+        self.len[ply as usize] = 0;
+    }
+
+    /// `mv` is the new best move at `ply`, followed by the line its child
+    /// just left at `ply + 1`
+    pub fn update(&mut self, ply: i32, mv: Move) {
+        let ply = ply as usize;
+        let child_len = self.len.get(ply + 1).copied().unwrap_or(0);
+
+        let (rows, child_rows) = self.moves.split_at_mut(ply + 1);
+        let row = &mut rows[ply];
+
+        row[0] = Some(mv);
+
+        if let Some(child) = child_rows.first() {
+            row[1..=child_len].copy_from_slice(&child[..child_len]);
+        }
+
+        self.len[ply] = child_len + 1;
+    }
+
+    /// The line from the root
+    pub fn line(&self) -> Vec<Move> {
+        self.moves[0][..self.len[0]]
+            .iter()
+            .flatten()
+            .copied()
+            .collect()
+    }
+}
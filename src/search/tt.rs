@@ -0,0 +1,176 @@
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use crate::prelude::{gui::PieceType, *};
+
+/// Size of a table made by [`Searcher::new`](super::Searcher::new)
+pub const DEFAULT_HASH_MB: usize = 16;
+
+/// What the stored score says about the real one
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Bound {
+    Exact,
+    /// The search failed high, the real score is at least this
+    Lower,
+    /// The search failed low, the real score is at most this
+    Upper,
+}
+
+/// What a search found out about a position
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TtEntry {
+    pub best_move: Option<Move>,
+    /// Converted with [`Score::to_tt`]
+    pub score: Score,
+    pub depth: i32,
+    pub bound: Bound,
+}
+
+#[derive(Default)]
+struct Slot {
+    /// position key xored with data, so torn writes are detected on probe
+    key: AtomicU64,
+    data: AtomicU64,
+}
+
+/// Lockless transposition table, shared by every search thread
+///
+/// Like the pawn hash table, see [Hyatt's lockless hashing](https://www.chessprogramming.org/Shared_Hash_Table#Lockless).
+/// A slot written by two threads at once fails the key check and reads as
+/// empty, instead of giving one thread's move with another's score.
+pub struct TranspositionTable {
+    slots: Box<[Slot]>,
+}
+
+/// Square indices and promotion in 15 bits, 0 for no move
+fn pack_move(mv: Option<Move>) -> u64 {
+    mv.map_or(0, |mv| {
+        let promotion = mv.promotion.map_or(0, |p| p as u64 + 1);
+
+        mv.from.index() as u64 | (mv.to.index() as u64) << 6 | promotion << 12
+    })
+}
+
+fn unpack_move(bits: u64) -> Option<Move> {
+    if bits == 0 {
+        return None;
+    }
+
+    let promotion = (bits >> 12 & 7) as usize;
+
+    Some(Move {
+        from: Square::from_index((bits & 63) as u8),
+        to: Square::from_index((bits >> 6 & 63) as u8),
+        promotion: promotion
+            .checked_sub(1)
+            .and_then(|i| PieceType::every().nth(i)),
+    })
+}
+
+impl TtEntry {
+    /// Score in the low 16 bits, then depth, bound and move
+    fn pack(&self) -> u64 {
+        let bound = match self.bound {
+            Bound::Exact => 0,
+            Bound::Lower => 1,
+            Bound::Upper => 2,
+        };
+
+        self.score.0 as u16 as u64
+            | (self.depth.clamp(0, u8::MAX as i32) as u64) << 16
+            | bound << 24
+            | pack_move(self.best_move) << 26
+    }
+
+    fn unpack(data: u64) -> Self {
+        let bound = match data >> 24 & 3 {
+            0 => Bound::Exact,
+            1 => Bound::Lower,
+            _ => Bound::Upper,
+        };
+
+        Self {
+            best_move: unpack_move(data >> 26),
+            score: Score(data as u16 as i16 as i32),
+            depth: (data >> 16 & 0xff) as i32,
+            bound,
+        }
+    }
+}
+
+impl TranspositionTable {
+    /// A table of at most `mb` megabytes, rounded down to a power of two
+    /// number of entries
+    pub fn new(mb: usize) -> Self {
+        let bytes = mb.max(1) * 1024 * 1024;
+        let size = 1 << (bytes / std::mem::size_of::<Slot>()).ilog2();
+
+        Self {
+            slots: (0..size).map(|_| Slot::default()).collect(),
+        }
+    }
+
+    /// Forget everything, for a new game
+    pub fn clear(&self) {
+        for slot in self.slots.iter() {
+            slot.key.store(0, Ordering::Relaxed);
+            slot.data.store(0, Ordering::Relaxed);
+        }
+    }
+
+    fn slot(&self, key: u64) -> &Slot {
+        &self.slots[key as usize & (self.slots.len() - 1)]
+    }
+
+    pub fn probe(&self, key: u64) -> Option<TtEntry> {
+        let slot = self.slot(key);
+        let data = slot.data.load(Ordering::Relaxed);
+
+        if data == 0 || slot.key.load(Ordering::Relaxed) ^ data != key {
+            return None;
+        }
+
+        Some(TtEntry::unpack(data))
+    }
+
+    /// Replaces whatever was there, except a deeper result for the same
+    /// position. The old best move is kept if the new entry has none
+    pub fn store(&self, key: u64, mut entry: TtEntry) {
+        if let Some(old) = self.probe(key) {
+            if old.depth > entry.depth && entry.bound != Bound::Exact {
+                return;
+            }
+
+            entry.best_move = entry.best_move.or(old.best_move);
+        }
+
+        let slot = self.slot(key);
+        let data = entry.pack();
+
+        slot.key.store(key ^ data, Ordering::Relaxed);
+        slot.data.store(data, Ordering::Relaxed);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn bytes(table: &TranspositionTable) -> usize {
+        table.slots.len() * std::mem::size_of::<Slot>()
+    }
+
+    #[test]
+    fn fills_the_megabytes_it_is_given() {
+        assert_eq!(
+            bytes(&TranspositionTable::new(DEFAULT_HASH_MB)),
+            DEFAULT_HASH_MB << 20
+        );
+        assert_eq!(bytes(&TranspositionTable::new(1)), 1 << 20);
+    }
+
+    #[test]
+    fn rounds_down_to_a_power_of_two() {
+        assert_eq!(bytes(&TranspositionTable::new(3)), 2 << 20);
+        assert_eq!(bytes(&TranspositionTable::new(0)), 1 << 20);
+    }
+}
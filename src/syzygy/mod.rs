@@ -0,0 +1,624 @@
+// Syzygy endgame tablebases
+//
+// WDL tables say whether a position is won, drawn or lost, and are probed
+// inside the search. DTZ tables give the distance to the next capture or pawn
+// move, which is enough to always make progress in a won endgame, and are
+// probed at the root.
+//
+// We don't track the fifty-move counter, so cursed wins and blessed losses
+// (won or lost, but not within fifty moves) are scored as draws in the search.
+use std::{
+    collections::HashMap,
+    env, fs, io,
+    ops::Neg,
+    path::PathBuf,
+    sync::{OnceLock, RwLock},
+};
+
+use crate::prelude::{gui::PieceType, *};
+
+mod table;
+
+pub use table::{Table, TableKind, MAX_PIECES};
+
+/// Result of a position with perfect play, for the side to move
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[repr(i8)]
+pub enum Wdl {
+    Loss = -2,
+    /// Lost, but drawn by the fifty-move rule
+    BlessedLoss = -1,
+    Draw = 0,
+    /// Won, but drawn by the fifty-move rule
+    CursedWin = 1,
+    Win = 2,
+}
+
+impl Wdl {
+    fn from_value(value: i32) -> Self {
+        match value {
+            ..=-2 => Wdl::Loss,
+            -1 => Wdl::BlessedLoss,
+            0 => Wdl::Draw,
+            1 => Wdl::CursedWin,
+            _ => Wdl::Win,
+        }
+    }
+
+    fn signum(&self) -> i32 {
+        (*self as i32).signum()
+    }
+
+    /// Search score for this result at `ply`, relative to the side to move
+    pub fn score(&self, ply: i32) -> Score {
+        match self {
+            Wdl::Win => Score::TB_WIN - Score(ply),
+            Wdl::Loss => -Score::TB_WIN + Score(ply),
+            _ => Score::DRAW,
+        }
+    }
+}
+
+impl Neg for Wdl {
+    type Output = Wdl;
+
+    fn neg(self) -> Wdl {
+        Wdl::from_value(-(self as i32))
+    }
+}
+
+/// The DTZ of the move before a capture or pawn move that keeps `wdl`
+fn dtz_before_zeroing(wdl: Wdl) -> i32 {
+    match wdl {
+        Wdl::Win => 1,
+        Wdl::CursedWin => 101,
+        Wdl::BlessedLoss => -101,
+        Wdl::Loss => -1,
+        Wdl::Draw => 0,
+    }
+}
+
+/// Material of one side like `KRP`, in the order table names use
+fn material(position: &Position, color: Color) -> String {
+    let pieces = position.pieces_of_col(color);
+    let mut name = String::from("K");
+
+    for (piece_type, c) in [
+        (PieceType::Queen, 'Q'),
+        (PieceType::Rook, 'R'),
+        (PieceType::Bishop, 'B'),
+        (PieceType::Knight, 'N'),
+        (PieceType::Pawn, 'P'),
+    ] {
+        let count = (position.pieces_of_type(piece_type) & pieces).count_ones();
+        name.extend(std::iter::repeat_n(c, count as usize));
+    }
+
+    name
+}
+
+struct LazyTable {
+    path: PathBuf,
+    table: OnceLock<Option<Table>>,
+}
+
+/// Every table found in the `SyzygyPath` directories. Tables are only read
+/// when first probed
+pub struct Tablebase {
+    tables: HashMap<(String, TableKind), LazyTable>,
+
+    /// Most pieces of any table
+    pub max_pieces: usize,
+}
+
+static TABLEBASE: RwLock<Option<&'static Tablebase>> = RwLock::new(None);
+
+/// Use the tables in `paths`, a list of directories separated like `PATH`
+/// (the `SyzygyPath` UCI option). Returns how many tables were found
+pub fn set_path(paths: &str) -> io::Result<usize> {
+    if paths.is_empty() || paths == "<empty>" {
+        *TABLEBASE.write().unwrap() = None;
+        return Ok(0);
+    }
+
+    let tablebase = Tablebase::open(paths)?;
+    let count = tablebase.tables.len();
+
+    *TABLEBASE.write().unwrap() = Some(Box::leak(Box::new(tablebase)));
+
+    Ok(count)
+}
+
+/// The tablebase if one is set and could have `position` in it
+pub fn tablebase(position: &Position) -> Option<&'static Tablebase> {
+    let tablebase = (*TABLEBASE.read().unwrap())?;
+
+    let fits = position.occupied().count_ones() as usize <= tablebase.max_pieces
+        && !position.castling_rights.any();
+
+    fits.then_some(tablebase)
+}
+
+impl Tablebase {
+    pub fn open(paths: &str) -> io::Result<Self> {
+        let mut tables = HashMap::new();
+        let mut max_pieces = 0;
+
+        for dir in env::split_paths(paths) {
+            for entry in fs::read_dir(&dir)? {
+                let path = entry?.path();
+
+                let (Some(name), Some(extension)) = (
+                    path.file_stem().and_then(|s| s.to_str()),
+                    path.extension().and_then(|s| s.to_str()),
+                ) else {
+                    continue;
+                };
+
+                let kind = match extension {
+                    "rtbw" => TableKind::Wdl,
+                    "rtbz" => TableKind::Dtz,
+                    _ => continue,
+                };
+
+                if kind == TableKind::Wdl {
+                    max_pieces = max_pieces.max(name.len() - 1);
+                }
+
+                let table = LazyTable {
+                    path: path.clone(),
+                    table: OnceLock::new(),
+                };
+
+                tables.insert((name.to_string(), kind), table);
+            }
+        }
+
+        Ok(Self {
+            tables,
+            max_pieces: max_pieces.min(MAX_PIECES),
+        })
+    }
+
+    fn load(&self, name: &str, kind: TableKind) -> Option<&Table> {
+        let lazy = self.tables.get(&(name.to_string(), kind))?;
+
+        lazy.table
+            .get_or_init(|| match Table::load(&lazy.path, name, kind) {
+                Ok(table) => Some(table),
+                Err(e) => {
+                    println!("info string could not load {}: {e}", lazy.path.display());
+                    None
+                }
+            })
+            .as_ref()
+    }
+
+    /// The table for the position's material, and whether its colors are
+    /// swapped relative to the table name
+    fn table(&self, position: &Position, kind: TableKind) -> Option<(&Table, bool)> {
+        let white = material(position, Color::White);
+        let black = material(position, Color::Black);
+
+        if let Some(table) = self.load(&format!("{white}v{black}"), kind) {
+            return Some((table, false));
+        }
+
+        self.load(&format!("{black}v{white}"), kind)
+            .map(|table| (table, true))
+    }
+
+    fn probe_wdl_table(&self, position: &Position) -> Option<Wdl> {
+        if position.occupied() == position.kings {
+            return Some(Wdl::Draw);
+        }
+
+        let (table, black_stronger) = self.table(position, TableKind::Wdl)?;
+
+        table.wdl(position, black_stronger)
+    }
+
+    /// `None` if the table is missing or corrupt, `Some(None)` if it only stores the other side to move
+    fn probe_dtz_table(&self, position: &Position, wdl: Wdl) -> Option<Option<i32>> {
+        let (table, black_stronger) = self.table(position, TableKind::Dtz)?;
+
+        table.dtz(position, black_stronger, wdl)
+    }
+
+    /// Tables don't store the right value where the side to move can capture
+    /// into a better result, or take en passant, so resolve captures first.
+    /// Also returns whether the best move is a capture (or pawn move, if
+    /// `pawn_moves_zero` is set)
+    fn search(&self, position: &Position, pawn_moves_zero: bool) -> Option<(Wdl, bool)> {
+        let moves = position.legal_moves();
+        let mut best = Wdl::Loss;
+        let mut zeroing = 0;
+
+        for &mv in &moves {
+            let pawn_move = pawn_moves_zero && position.pawns.at(mv.from);
+
+            if !position.is_capture(mv) && !pawn_move {
+                continue;
+            }
+
+            zeroing += 1;
+
+            let mut child = *position;
+            child.play(mv);
+
+            let (value, _) = self.search(&child, false)?;
+            let value = -value;
+
+            if value > best {
+                best = value;
+
+                if value >= Wdl::Win {
+                    return Some((value, true));
+                }
+            }
+        }
+
+        // the table may be wrong when every move zeroes, like with en passant
+        let only_zeroing = zeroing > 0 && zeroing == moves.len();
+
+        let value = if only_zeroing {
+            best
+        } else {
+            self.probe_wdl_table(position)?
+        };
+
+        if best >= value {
+            return Some((best, best > Wdl::Draw || only_zeroing));
+        }
+
+        Some((value, false))
+    }
+
+    /// Win, draw or loss for the side to move, `None` if a table is missing
+    pub fn probe_wdl(&self, position: &Position) -> Option<Wdl> {
+        self.search(position, false).map(|(wdl, _)| wdl)
+    }
+
+    /// Plies to the next capture or pawn move, positive if the side to move
+    /// wins and negative if it loses. Off by one at most, as the tables
+    /// sometimes round to full moves. `None` if a table is missing
+    pub fn probe_dtz(&self, position: &Position) -> Option<i32> {
+        let (wdl, zeroing) = self.search(position, true)?;
+
+        if wdl == Wdl::Draw {
+            return Some(0);
+        }
+
+        if zeroing {
+            return Some(dtz_before_zeroing(wdl));
+        }
+
+        if let Some(dtz) = self.probe_dtz_table(position, wdl)? {
+            let cursed = matches!(wdl, Wdl::CursedWin | Wdl::BlessedLoss);
+
+            return Some((dtz + if cursed { 100 } else { 0 }) * wdl.signum());
+        }
+
+        // the table stores the other side to move, so look one ply ahead
+        let mut best = i32::MAX;
+
+        for mv in position.legal_moves() {
+            let zeroing = position.is_capture(mv) || position.pawns.at(mv.from);
+
+            let mut child = *position;
+            child.play(mv);
+
+            let mut dtz = if zeroing {
+                -dtz_before_zeroing(self.search(&child, false)?.0)
+            } else {
+                -self.probe_dtz(&child)?
+            };
+
+            if dtz == 1 && child.in_check(child.turn) && child.legal_moves().is_empty() {
+                best = 1;
+            }
+
+            if !zeroing {
+                dtz += dtz.signum();
+            }
+
+            if dtz < best && dtz.signum() == wdl.signum() {
+                best = dtz;
+            }
+        }
+
+        Some(if best == i32::MAX { -1 } else { best })
+    }
+
+    /// The move that keeps the best result, winning as fast as possible or
+    /// losing as slowly as possible. `None` if a table is missing
+    pub fn best_move(&self, position: &Position) -> Option<(Move, Wdl)> {
+        let mut best: Option<(Move, i32)> = None;
+
+        for mv in position.legal_moves() {
+            let zeroing = position.is_capture(mv) || position.pawns.at(mv.from);
+
+            let mut child = *position;
+            child.play(mv);
+
+            let mut dtz = if zeroing {
+                dtz_before_zeroing(-self.probe_wdl(&child)?)
+            } else {
+                let dtz = -self.probe_dtz(&child)?;
+                dtz + dtz.signum()
+            };
+
+            if dtz == 2 && child.in_check(child.turn) && child.legal_moves().is_empty() {
+                dtz = 1;
+            }
+
+            // wins by shortest DTZ first, then draws, then losses by longest DTZ
+            let rank = |dtz: i32| match dtz {
+                1.. => (2, -dtz),
+                0 => (1, 0),
+                _ => (0, -dtz),
+            };
+
+            if best.is_none_or(|(_, best)| rank(dtz) > rank(best)) {
+                best = Some((mv, dtz));
+            }
+        }
+
+        let (mv, _) = best?;
+
+        Some((mv, self.probe_wdl(position)?))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::evaluation::endgame::kpk;
+
+    /// Written by `table::tests::write_fixtures`
+    const FIXTURES: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/fixtures/syzygy");
+
+    fn fixtures() -> Tablebase {
+        Tablebase::open(FIXTURES).unwrap()
+    }
+
+    fn position(fen: &str) -> Position {
+        Position::from_fen(fen).unwrap()
+    }
+
+    /// The position with these pieces, `None` if it can't come up
+    fn setup(pieces: &[(Color, PieceType, Square)], turn: Color) -> Option<Position> {
+        let mut position = Position::empty();
+
+        for &(color, kind, square) in pieces {
+            if position.occupied().at(square) {
+                return None;
+            }
+
+            position.put(square, gui::Piece { color, kind });
+        }
+
+        position.turn = turn;
+
+        (!position.in_check(turn.other())).then_some(position)
+    }
+
+    /// The a1-d1-d4 triangle, every position without pawns is a mirror of
+    /// one with a king there
+    fn triangle() -> impl Iterator<Item = Square> {
+        Square::every().filter(|square| {
+            square.rank().index() <= square.file().index() && square.file() <= File::D
+        })
+    }
+
+    /// White to move positions of king and `piece` against king, with the
+    /// longest distance to mate
+    fn longest_win(tablebase: &Tablebase, piece: PieceType) -> (i32, Position) {
+        let mut longest = (0, Position::empty());
+
+        for white_king in triangle() {
+            for other in Square::every() {
+                for black_king in Square::every() {
+                    let pieces = [
+                        (Color::White, PieceType::King, white_king),
+                        (Color::White, piece, other),
+                        (Color::Black, PieceType::King, black_king),
+                    ];
+
+                    let Some(position) = setup(&pieces, Color::White) else {
+                        continue;
+                    };
+
+                    let dtz = tablebase.probe_dtz(&position).unwrap();
+
+                    if dtz > longest.0 {
+                        longest = (dtz, position);
+                    }
+                }
+            }
+        }
+
+        longest
+    }
+
+    #[test]
+    fn finds_the_fixtures() {
+        let tablebase = fixtures();
+
+        assert_eq!(tablebase.tables.len(), 10);
+        assert_eq!(tablebase.max_pieces, 4);
+    }
+
+    #[test]
+    fn kpk_agrees_with_the_bitbase() {
+        let tablebase = fixtures();
+        let back_rank = |square: Square| matches!(square.rank(), Rank::First | Rank::Eighth);
+
+        for pawn in Square::every().filter(|&square| square.file() <= File::D && !back_rank(square))
+        {
+            for strong_king in Square::every() {
+                for weak_king in Square::every() {
+                    for strong in [Color::White, Color::Black] {
+                        let [pawn, strong_king, weak_king] =
+                            [pawn, strong_king, weak_king].map(|square| square.relative(strong));
+
+                        let pieces = [
+                            (strong, PieceType::King, strong_king),
+                            (strong, PieceType::Pawn, pawn),
+                            (strong.other(), PieceType::King, weak_king),
+                        ];
+
+                        for strong_to_move in [true, false] {
+                            let turn = if strong_to_move {
+                                strong
+                            } else {
+                                strong.other()
+                            };
+
+                            let Some(position) = setup(&pieces, turn) else {
+                                continue;
+                            };
+
+                            let expected = match kpk::probe(
+                                strong,
+                                strong_king,
+                                pawn,
+                                weak_king,
+                                strong_to_move,
+                            ) {
+                                false => Wdl::Draw,
+                                true if strong_to_move => Wdl::Win,
+                                true => Wdl::Loss,
+                            };
+
+                            assert_eq!(
+                                tablebase.probe_wdl(&position),
+                                Some(expected),
+                                "{}",
+                                position.fen()
+                            );
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn krk_is_won_unless_the_rook_falls() {
+        let tablebase = fixtures();
+
+        for white_king in triangle() {
+            for rook in Square::every() {
+                for black_king in Square::every() {
+                    let pieces = [
+                        (Color::White, PieceType::King, white_king),
+                        (Color::White, PieceType::Rook, rook),
+                        (Color::Black, PieceType::King, black_king),
+                    ];
+
+                    for turn in [Color::White, Color::Black] {
+                        let Some(position) = setup(&pieces, turn) else {
+                            continue;
+                        };
+
+                        let moves = position.legal_moves();
+
+                        let expected = if turn == Color::White {
+                            Wdl::Win
+                        } else if moves.is_empty() && position.in_check(turn) {
+                            Wdl::Loss
+                        } else if moves.is_empty()
+                            || moves.iter().any(|&mv| position.is_capture(mv))
+                        {
+                            Wdl::Draw
+                        } else {
+                            Wdl::Loss
+                        };
+
+                        assert_eq!(
+                            tablebase.probe_wdl(&position),
+                            Some(expected),
+                            "{}",
+                            position.fen()
+                        );
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn longest_wins_are_mates_in_10_and_16() {
+        let tablebase = fixtures();
+
+        assert_eq!(longest_win(&tablebase, PieceType::Queen).0, 19);
+        assert_eq!(longest_win(&tablebase, PieceType::Rook).0, 31);
+    }
+
+    #[test]
+    fn dtz_counts_down_to_mate() {
+        let tablebase = fixtures();
+        let (dtz, mut position) = longest_win(&tablebase, PieceType::Rook);
+
+        for left in (1..=dtz).rev() {
+            let sign = if (dtz - left) % 2 == 0 { 1 } else { -1 };
+            assert_eq!(
+                tablebase.probe_dtz(&position),
+                Some(left * sign),
+                "{}",
+                position.fen()
+            );
+
+            let (mv, wdl) = tablebase.best_move(&position).unwrap();
+            assert_eq!(wdl.signum(), sign);
+
+            position.play(mv);
+        }
+
+        assert!(position.legal_moves().is_empty() && position.in_check(position.turn));
+        assert_eq!(tablebase.probe_dtz(&position), Some(-1));
+    }
+
+    #[test]
+    fn known_results() {
+        let tablebase = fixtures();
+
+        // Rh8 mates
+        let mate_in_one = position("k7/8/1K6/8/4n3/8/8/7R w - - 0 1");
+        assert_eq!(tablebase.probe_wdl(&mate_in_one), Some(Wdl::Win));
+        assert_eq!(tablebase.probe_dtz(&mate_in_one), Some(1));
+        assert_eq!(
+            tablebase.best_move(&mate_in_one).unwrap().0.to_string(),
+            "h1h8"
+        );
+
+        let mated = position("k6R/8/1K6/8/4n3/8/8/8 b - - 0 1");
+        assert_eq!(tablebase.probe_wdl(&mated), Some(Wdl::Loss));
+        assert_eq!(tablebase.probe_dtz(&mated), Some(-1));
+
+        // the rook is taken, and a knight can't win alone
+        let loses_the_rook = position("n6K/8/8/8/8/8/1k6/2R5 b - - 0 1");
+        assert_eq!(tablebase.probe_wdl(&loses_the_rook), Some(Wdl::Draw));
+        assert_eq!(tablebase.probe_dtz(&loses_the_rook), Some(0));
+
+        let wins_the_knight = position("7k/8/8/R7/8/8/8/n3K3 w - - 0 1");
+        assert_eq!(tablebase.probe_wdl(&wins_the_knight), Some(Wdl::Win));
+        assert_eq!(tablebase.probe_dtz(&wins_the_knight), Some(1));
+        assert_eq!(
+            tablebase.best_move(&wins_the_knight).unwrap().0.to_string(),
+            "a5a1"
+        );
+
+        let promotes = position("8/4P3/8/8/8/8/k7/4K3 w - - 0 1");
+        assert_eq!(tablebase.probe_dtz(&promotes), Some(1));
+        assert_eq!(
+            tablebase.best_move(&promotes).unwrap().0.to_string(),
+            "e7e8q"
+        );
+
+        let bishop = position("8/8/8/8/8/8/8/KB5k w - - 0 1");
+        assert_eq!(tablebase.probe_wdl(&bishop), Some(Wdl::Draw));
+        assert_eq!(tablebase.probe_dtz(&bishop), Some(0));
+    }
+}
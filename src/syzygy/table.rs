@@ -0,0 +1,1881 @@
+// Decoding of Syzygy table files, following the layout written by the
+// generator at https://github.com/syzygy1/tb
+//
+// A table stores one value per position index. Positions are turned into an
+// index by mirroring them into a canonical orientation and encoding pieces of
+// the same kind together as combinations. The values are compressed with
+// recursive pairing and a canonical Huffman code, in blocks that can be found
+// through a sparse index.
+use std::{fs, io, path::Path, sync::OnceLock};
+
+use super::Wdl;
+use crate::{movegen, prelude::*};
+
+pub const MAX_PIECES: usize = 7;
+
+const WDL_MAGIC: [u8; 4] = [0x71, 0xe8, 0x23, 0x5d];
+const DTZ_MAGIC: [u8; 4] = [0xd7, 0x66, 0x0c, 0xa5];
+
+// flags of each PairsData
+const STM: u8 = 1;
+const MAPPED: u8 = 2;
+const WIN_PLIES: u8 = 4;
+const LOSS_PLIES: u8 = 8;
+const WIDE: u8 = 16;
+const SINGLE_VALUE: u8 = 128;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum TableKind {
+    Wdl,
+    Dtz,
+}
+
+impl TableKind {
+    fn magic(&self) -> [u8; 4] {
+        match self {
+            TableKind::Wdl => WDL_MAGIC,
+            TableKind::Dtz => DTZ_MAGIC,
+        }
+    }
+}
+
+/// Lookup tables for turning positions into indices
+struct Maps {
+    /// a2-h7 to 0..47, highest for the leading pawn
+    pawns: [usize; 64],
+
+    /// squares below the a1-h8 diagonal to 0..27
+    b1h1h7: [usize; 64],
+
+    /// the a1-d1-d4 triangle to 0..9, diagonal last
+    a1d1d4: [usize; 64],
+
+    /// the 462 legal placements of two kings, the first in the a1-d1-d4 triangle
+    kk: [[usize; 64]; 10],
+
+    /// `binomial[k][n]` ways to choose k of n
+    binomial: [[u64; 64]; 6],
+
+    lead_pawn_idx: [[u64; 64]; 6],
+    lead_pawns_size: [[u64; 4]; 6],
+}
+
+fn off_a1h8(square: usize) -> i32 {
+    (square >> 3) as i32 - (square & 7) as i32
+}
+
+impl Maps {
+    fn new() -> Self {
+        let mut maps = Maps {
+            pawns: [0; 64],
+            b1h1h7: [0; 64],
+            a1d1d4: [0; 64],
+            kk: [[0; 64]; 10],
+            binomial: [[0; 64]; 6],
+            lead_pawn_idx: [[0; 64]; 6],
+            lead_pawns_size: [[0; 4]; 6],
+        };
+
+        let mut code = 0;
+
+        for square in 0..64 {
+            if off_a1h8(square) < 0 {
+                maps.b1h1h7[square] = code;
+                code += 1;
+            }
+        }
+
+        let mut diagonal = vec![];
+        code = 0;
+
+        // up to d4
+        for square in 0..28 {
+            if square & 7 > 3 {
+                continue;
+            }
+
+            if off_a1h8(square) < 0 {
+                maps.a1d1d4[square] = code;
+                code += 1;
+            } else if off_a1h8(square) == 0 {
+                diagonal.push(square);
+            }
+        }
+
+        for square in diagonal {
+            maps.a1d1d4[square] = code;
+            code += 1;
+        }
+
+        let mut both_on_diagonal = vec![];
+        code = 0;
+
+        for idx in 0..10 {
+            // b1 is the only square of the triangle mapped to 0
+            for first in (0..28).filter(|&s| maps.a1d1d4[s] == idx && (idx != 0 || s == 1)) {
+                let first_square = Square::from_index(first as u8);
+                let blocked = movegen::king_attacks(first_square) | first_square.to_bitboard();
+
+                for second in 0..64 {
+                    if blocked.at(Square::from_index(second as u8)) {
+                        continue;
+                    }
+
+                    match (off_a1h8(first), off_a1h8(second)) {
+                        (0, off) if off > 0 => continue,
+                        (0, 0) => both_on_diagonal.push((idx, second)),
+                        _ => {
+                            maps.kk[idx][second] = code;
+                            code += 1;
+                        }
+                    }
+                }
+            }
+        }
+
+        for (idx, second) in both_on_diagonal {
+            maps.kk[idx][second] = code;
+            code += 1;
+        }
+
+        maps.binomial[0][0] = 1;
+
+        for n in 1..64 {
+            for k in 0..6.min(n + 1) {
+                let with = if k > 0 {
+                    maps.binomial[k - 1][n - 1]
+                } else {
+                    0
+                };
+                let without = if k < n { maps.binomial[k][n - 1] } else { 0 };
+
+                maps.binomial[k][n] = with + without;
+            }
+        }
+
+        let mut available = 47;
+
+        for lead_pawns in 1..6 {
+            for file in 0..4 {
+                let mut idx = 0;
+
+                for rank in 1..7 {
+                    let square = rank * 8 + file;
+
+                    if lead_pawns == 1 {
+                        maps.pawns[square] = available;
+                        maps.pawns[square ^ 7] = available.saturating_sub(1);
+                        available = available.saturating_sub(2);
+                    }
+
+                    maps.lead_pawn_idx[lead_pawns][square] = idx;
+                    idx += maps.binomial[lead_pawns - 1][maps.pawns[square]];
+                }
+
+                maps.lead_pawns_size[lead_pawns][file] = idx;
+            }
+        }
+
+        maps
+    }
+}
+
+fn maps() -> &'static Maps {
+    static MAPS: OnceLock<Maps> = OnceLock::new();
+    MAPS.get_or_init(Maps::new)
+}
+
+fn read_u8(data: &[u8], at: usize) -> Option<u8> {
+    data.get(at).copied()
+}
+
+fn read_u16_le(data: &[u8], at: usize) -> Option<u16> {
+    let bytes = data.get(at..)?.get(..2)?;
+    Some(u16::from_le_bytes([bytes[0], bytes[1]]))
+}
+
+fn read_u32_le(data: &[u8], at: usize) -> Option<u32> {
+    let bytes = data.get(at..)?.get(..4)?;
+    Some(u32::from_le_bytes(bytes.try_into().unwrap()))
+}
+
+/// Reads past the end of the file as zeroes, the last block may be cut short
+fn read_be<const N: usize>(data: &[u8], at: usize) -> [u8; N] {
+    let mut bytes = [0; N];
+
+    for (i, byte) in bytes.iter_mut().enumerate() {
+        *byte = data.get(at + i).copied().unwrap_or(0);
+    }
+
+    bytes
+}
+
+/// Everything needed to decompress one subtable, for one side to move and
+/// leading pawn file. Positions are offsets into the file
+#[derive(Debug, Clone, Default)]
+struct PairsData {
+    flags: u8,
+    min_sym_len: u8,
+    block_size: usize,
+    span: usize,
+    num_blocks: usize,
+
+    lowest_sym: usize,
+    btree: usize,
+    block_length: usize,
+    block_length_size: usize,
+    sparse_index: usize,
+    sparse_index_size: usize,
+    data: usize,
+
+    /// `base64[l - min_sym_len]` is the lowest symbol of length l, padded to 64 bits
+    base64: Vec<u64>,
+
+    /// How many values (minus one) each symbol expands to
+    symlen: Vec<u8>,
+
+    /// Piece codes in the order they are encoded, which defines the groups
+    pieces: [u8; MAX_PIECES],
+    group_idx: [u64; MAX_PIECES + 1],
+    group_len: [usize; MAX_PIECES + 1],
+
+    /// Where the DTZ value maps start for each WDL
+    map_idx: [usize; 4],
+}
+
+impl PairsData {
+    fn left(&self, data: &[u8], sym: usize) -> Option<usize> {
+        let at = self.btree + 3 * sym;
+        Some(((read_u8(data, at + 1)? as usize & 0xf) << 8) | read_u8(data, at)? as usize)
+    }
+
+    fn right(&self, data: &[u8], sym: usize) -> Option<usize> {
+        let at = self.btree + 3 * sym;
+        Some(((read_u8(data, at + 2)? as usize) << 4) | (read_u8(data, at + 1)? as usize >> 4))
+    }
+
+    fn set_symlen(&mut self, data: &[u8], sym: usize, visited: &mut [bool]) -> Option<u8> {
+        visited[sym] = true;
+
+        let right = self.right(data, sym)?;
+
+        if right == 0xfff {
+            return Some(0);
+        }
+
+        let left = self.left(data, sym)?;
+
+        if left >= self.symlen.len() || right >= self.symlen.len() {
+            return None;
+        }
+
+        if !visited[left] {
+            self.symlen[left] = self.set_symlen(data, left, visited)?;
+        }
+
+        if !visited[right] {
+            self.symlen[right] = self.set_symlen(data, right, visited)?;
+        }
+
+        Some(
+            self.symlen[left]
+                .wrapping_add(self.symlen[right])
+                .wrapping_add(1),
+        )
+    }
+
+    /// Read the Huffman code and block sizes, returning where they end
+    fn set_sizes(&mut self, data: &[u8], mut at: usize) -> Option<usize> {
+        self.flags = read_u8(data, at)?;
+        at += 1;
+
+        if self.flags & SINGLE_VALUE != 0 {
+            // the single value is stored in place of the symbol length
+            self.min_sym_len = read_u8(data, at)?;
+            return Some(at + 1);
+        }
+
+        let groups = self.group_len.iter().position(|&len| len == 0).unwrap();
+        let size = self.group_idx[groups];
+
+        self.block_size = 1usize.checked_shl(read_u8(data, at)? as u32)?;
+        self.span = 1usize.checked_shl(read_u8(data, at + 1)? as u32)?;
+        self.sparse_index_size = size.div_ceil(self.span as u64) as usize;
+
+        let padding = read_u8(data, at + 2)? as usize;
+        self.num_blocks = read_u32_le(data, at + 3)? as usize;
+        self.block_length_size = self.num_blocks + padding;
+
+        let max_sym_len = read_u8(data, at + 7)?;
+        self.min_sym_len = read_u8(data, at + 8)?;
+        at += 9;
+
+        self.lowest_sym = at;
+
+        let lengths = max_sym_len.checked_sub(self.min_sym_len)? as usize + 1;
+        let lowest_sym = |len: usize| read_u16_le(data, self.lowest_sym + 2 * len).map(u64::from);
+
+        // longer codes have lower values, so every base is at least half the next one
+        self.base64 = vec![0; lengths];
+
+        for len in (0..lengths - 1).rev() {
+            self.base64[len] = self.base64[len + 1]
+                .wrapping_add(lowest_sym(len)?)
+                .wrapping_sub(lowest_sym(len + 1)?)
+                / 2;
+        }
+
+        for (len, base) in self.base64.iter_mut().enumerate() {
+            // codes are at most 64 bits long
+            let shift = 64u32.checked_sub(len as u32 + self.min_sym_len as u32)?;
+            *base = base.checked_shl(shift).unwrap_or(0);
+        }
+
+        at += lengths * 2;
+
+        let symbols = read_u16_le(data, at)? as usize;
+        at += 2;
+
+        self.btree = at;
+        self.symlen = vec![0; symbols];
+
+        let mut visited = vec![false; symbols];
+
+        for sym in 0..symbols {
+            if !visited[sym] {
+                self.symlen[sym] = self.set_symlen(data, sym, &mut visited)?;
+            }
+        }
+
+        Some(at + symbols * 3 + (symbols & 1))
+    }
+
+    /// The value at `idx`, `None` if the data doesn't make sense
+    fn decompress(&self, data: &[u8], idx: u64) -> Option<usize> {
+        if self.flags & SINGLE_VALUE != 0 {
+            return Some(self.min_sym_len as usize);
+        }
+
+        // the sparse index points into the block lengths for every `span` values
+        let sparse = self.sparse_index + 6 * (idx / self.span as u64) as usize;
+        let mut block = read_u32_le(data, sparse)? as usize;
+        let mut offset = read_u16_le(data, sparse + 4)? as i64;
+
+        offset += (idx % self.span as u64) as i64 - (self.span / 2) as i64;
+
+        let block_length =
+            |block: usize| read_u16_le(data, self.block_length + 2 * block).map(i64::from);
+
+        while offset < 0 {
+            block = block.checked_sub(1)?;
+            offset += block_length(block)? + 1;
+        }
+
+        while offset > block_length(block)? {
+            offset -= block_length(block)? + 1;
+            block += 1;
+        }
+
+        if block >= self.num_blocks {
+            return None;
+        }
+
+        let mut at = self.data.checked_add(block.checked_mul(self.block_size)?)?;
+
+        if at >= data.len() {
+            return None;
+        }
+
+        let mut buf = u64::from_be_bytes(read_be(data, at));
+        let mut buf_size: usize = 64;
+        at += 8;
+
+        let min_sym_len = self.min_sym_len as usize;
+
+        let mut sym = loop {
+            let mut len = 0;
+
+            // the last base is zero, so this always stops
+            while buf < self.base64[len] {
+                len += 1;
+            }
+
+            let code = (buf - self.base64[len]).checked_shr((64 - len - min_sym_len) as u32);
+            let sym =
+                code.unwrap_or(0) as usize + read_u16_le(data, self.lowest_sym + 2 * len)? as usize;
+
+            let symlen = *self.symlen.get(sym)? as i64;
+
+            if offset < symlen + 1 {
+                break sym;
+            }
+
+            offset -= symlen + 1;
+
+            let len = len + min_sym_len;
+            buf = buf.checked_shl(len as u32).unwrap_or(0);
+            buf_size = buf_size.checked_sub(len)?;
+
+            if buf_size <= 32 {
+                buf_size += 32;
+                buf |= (u32::from_be_bytes(read_be(data, at)) as u64) << (64 - buf_size);
+                at += 4;
+            }
+        };
+
+        // every symbol is a pair of symbols, expand until a single value is left
+        while self.symlen[sym] != 0 {
+            let left = self.left(data, sym)?;
+            let left_len = *self.symlen.get(left)? as i64;
+
+            let next = if offset < left_len + 1 {
+                left
+            } else {
+                offset -= left_len + 1;
+                self.right(data, sym)?
+            };
+
+            // halves are shorter than the pair, anything else would loop
+            if *self.symlen.get(next)? >= self.symlen[sym] {
+                return None;
+            }
+
+            sym = next;
+        }
+
+        self.left(data, sym)
+    }
+}
+
+/// The pieces of a table, from its file name
+#[derive(Debug, Clone, Copy)]
+struct Material {
+    piece_count: usize,
+    has_pawns: bool,
+    has_unique_pieces: bool,
+
+    /// Pawns of the leading color, then of the other one
+    pawn_count: [usize; 2],
+
+    /// Both sides have the same pieces
+    symmetric: bool,
+
+    /// Codes of every piece, see `piece_code`, lowest first
+    codes: [u8; MAX_PIECES],
+}
+
+impl Material {
+    fn parse(name: &str) -> Option<Self> {
+        let (white, black) = name.split_once('v')?;
+
+        let count = |side: &str, c: char| side.chars().filter(|&p| p == c).count();
+        let valid =
+            |side: &str| side.starts_with('K') && side.chars().all(|c| "KQRBNP".contains(c));
+
+        if !valid(white) || !valid(black) || white.len() + black.len() > MAX_PIECES {
+            return None;
+        }
+
+        let pawns = [count(white, 'P'), count(black, 'P')];
+
+        // the side with fewer pawns leads, it compresses better
+        let white_leads = pawns[1] == 0 || (pawns[0] > 0 && pawns[1] >= pawns[0]);
+
+        let has_unique_pieces = [white, black]
+            .iter()
+            .any(|side| "QRBNP".chars().any(|c| count(side, c) == 1));
+
+        let mut codes = [0; MAX_PIECES];
+        let pieces = white
+            .chars()
+            .map(|c| (c, 0))
+            .chain(black.chars().map(|c| (c, 8)));
+
+        for (code, (c, color)) in codes.iter_mut().zip(pieces) {
+            *code = "PNBRQK".find(c).unwrap() as u8 + 1 + color;
+        }
+
+        codes[..white.len() + black.len()].sort_unstable();
+
+        Some(Material {
+            piece_count: white.len() + black.len(),
+            has_pawns: pawns[0] + pawns[1] > 0,
+            has_unique_pieces,
+            pawn_count: if white_leads {
+                pawns
+            } else {
+                [pawns[1], pawns[0]]
+            },
+            symmetric: white == black,
+            codes,
+        })
+    }
+}
+
+/// One memory-resident table file
+pub struct Table {
+    kind: TableKind,
+    data: Vec<u8>,
+    material: Material,
+
+    /// Start of the DTZ value maps
+    map: usize,
+
+    /// By side to move, then leading pawn file
+    items: [[PairsData; 4]; 2],
+}
+
+/// Code of a piece in table files, 1..=6 for white pawn to king and 9..=14 for black
+fn piece_code(piece: gui::Piece) -> u8 {
+    piece.kind as u8 + 1 + if piece.color == Color::Black { 8 } else { 0 }
+}
+
+impl Table {
+    /// Read a table, `name` being the material like `KRPvKR`
+    pub fn load(path: &Path, name: &str, kind: TableKind) -> io::Result<Self> {
+        let invalid = |what: &str| {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("{what}: {}", path.display()),
+            )
+        };
+
+        let material = Material::parse(name).ok_or_else(|| invalid("not a table name"))?;
+        let data = fs::read(path)?;
+
+        Self::parse(data, material, kind).ok_or_else(|| invalid("not a syzygy table, or cut short"))
+    }
+
+    /// `None` if the data isn't a table of this kind, or is truncated or corrupt
+    fn parse(data: Vec<u8>, material: Material, kind: TableKind) -> Option<Self> {
+        if data.len() < 16 || data.len() % 64 != 16 || data[..4] != kind.magic() {
+            return None;
+        }
+
+        let mut table = Table {
+            kind,
+            data,
+            material,
+            map: 0,
+            items: Default::default(),
+        };
+
+        table.setup()?;
+
+        Some(table)
+    }
+
+    fn sides(&self) -> usize {
+        if self.kind == TableKind::Wdl && !self.material.symmetric {
+            2
+        } else {
+            1
+        }
+    }
+
+    fn setup(&mut self) -> Option<()> {
+        let material = self.material;
+        let sides = self.sides();
+        let files = if material.has_pawns { 4 } else { 1 };
+        let both_pawns = material.has_pawns && material.pawn_count[1] > 0;
+
+        let data = &self.data;
+
+        // the first byte only repeats what the file name says
+        let mut at = 5;
+
+        for file in 0..files {
+            let first = read_u8(data, at)?;
+            let second = if both_pawns {
+                read_u8(data, at + 1)?
+            } else {
+                0xff
+            };
+            let order = [[first & 0xf, second & 0xf], [first >> 4, second >> 4]];
+
+            at += 1 + both_pawns as usize;
+
+            for k in 0..material.piece_count {
+                let pieces = read_u8(data, at)?;
+                self.items[0][file].pieces[k] = pieces & 0xf;
+                self.items[1][file].pieces[k] = pieces >> 4;
+                at += 1;
+            }
+
+            // the index relies on every subtable leading with the same pawns
+            let lead = self.items[0][0].pieces[0];
+
+            for (side, order) in order.iter().enumerate().take(sides) {
+                let d = &mut self.items[side][file];
+
+                if !valid_pieces(&material, &d.pieces)
+                    || (material.has_pawns && d.pieces[0] != lead)
+                {
+                    return None;
+                }
+
+                set_groups(&material, d, *order, file);
+            }
+        }
+
+        at += at & 1;
+
+        for file in 0..files {
+            for side in 0..sides {
+                at = self.items[side][file].set_sizes(data, at)?;
+            }
+        }
+
+        if self.kind == TableKind::Dtz {
+            self.map = at;
+
+            for file in 0..files {
+                let d = &mut self.items[0][file];
+
+                if d.flags & MAPPED == 0 {
+                    continue;
+                }
+
+                if d.flags & WIDE != 0 {
+                    at += at & 1;
+
+                    for i in 0..4 {
+                        d.map_idx[i] = (at - self.map) / 2 + 1;
+                        at += 2 * read_u16_le(data, at)? as usize + 2;
+                    }
+                } else {
+                    for i in 0..4 {
+                        d.map_idx[i] = at - self.map + 1;
+                        at += read_u8(data, at)? as usize + 1;
+                    }
+                }
+            }
+
+            at += at & 1;
+        }
+
+        for file in 0..files {
+            for side in 0..sides {
+                let d = &mut self.items[side][file];
+                d.sparse_index = at;
+                at = at.checked_add(d.sparse_index_size.checked_mul(6)?)?;
+            }
+        }
+
+        for file in 0..files {
+            for side in 0..sides {
+                let d = &mut self.items[side][file];
+                d.block_length = at;
+                at = at.checked_add(d.block_length_size.checked_mul(2)?)?;
+            }
+        }
+
+        // only the blocks themselves may run past the end
+        if at > data.len() {
+            return None;
+        }
+
+        for file in 0..files {
+            for side in 0..sides {
+                let d = &mut self.items[side][file];
+                at = (at + 0x3f) & !0x3f;
+                d.data = at;
+                at = at.checked_add(d.num_blocks.checked_mul(d.block_size)?)?;
+            }
+        }
+
+        Some(())
+    }
+
+    /// Find the subtable and index of a position. `None` if this is a DTZ
+    /// table that only stores the other side to move
+    ///
+    /// * `black_stronger` - The position's colors are swapped relative to the table name
+    fn index(&self, position: &Position, black_stronger: bool) -> Option<(&PairsData, u64)> {
+        let maps = maps();
+        let material = &self.material;
+
+        // symmetric tables only store white to move
+        let flip = black_stronger || (material.symmetric && position.turn == Color::Black);
+        let flip_color = if flip { 8 } else { 0 };
+        let flip_squares = if flip { 56 } else { 0 };
+        let stm = flip as usize ^ (position.turn == Color::Black) as usize;
+
+        let mut squares = [0usize; MAX_PIECES];
+        let mut pieces = [0u8; MAX_PIECES];
+        let mut size = 0;
+        let mut lead_pawns = Bitboard::EMPTY;
+        let mut file = 0;
+
+        if material.has_pawns {
+            // the leading pawns are always the first pieces
+            let lead_color = if self.items[0][0].pieces[0] ^ flip_color < 8 {
+                Color::White
+            } else {
+                Color::Black
+            };
+
+            lead_pawns = position.pawns & position.pieces_of_col(lead_color);
+
+            for square in lead_pawns.bit_pos_iter() {
+                squares[size] = square.index() ^ flip_squares;
+                size += 1;
+            }
+
+            // the one closest to the edge, then lowest, leads
+            let lead = (0..size).max_by_key(|&i| maps.pawns[squares[i]]).unwrap();
+            squares.swap(0, lead);
+
+            file = (squares[0] & 7).min(7 - (squares[0] & 7));
+        }
+
+        let lead_pawns_count = size;
+
+        if self.kind == TableKind::Dtz {
+            let stored_stm = (self.items[0][file].flags & STM) as usize;
+
+            if stored_stm != stm && (!material.symmetric || material.has_pawns) {
+                return None;
+            }
+        }
+
+        for square in (position.occupied() & !lead_pawns).bit_pos_iter() {
+            squares[size] = square.index() ^ flip_squares;
+            pieces[size] = piece_code(position.full_piece_at(square).unwrap()) ^ flip_color;
+            size += 1;
+        }
+
+        let side = if self.kind == TableKind::Wdl {
+            stm % self.sides()
+        } else {
+            0
+        };
+        let d = &self.items[side][file];
+
+        // same order as the table
+        for i in lead_pawns_count..size - 1 {
+            if let Some(j) = (i + 1..size).find(|&j| d.pieces[i] == pieces[j]) {
+                pieces.swap(i, j);
+                squares.swap(i, j);
+            }
+        }
+
+        // the leading piece goes on the queenside
+        if squares[0] & 7 > 3 {
+            for square in &mut squares[..size] {
+                *square ^= 7;
+            }
+        }
+
+        let mut idx;
+
+        if material.has_pawns {
+            idx = maps.lead_pawn_idx[lead_pawns_count][squares[0]];
+
+            squares[1..lead_pawns_count].sort_by_key(|&s| maps.pawns[s]);
+
+            for (i, &square) in squares.iter().enumerate().take(lead_pawns_count).skip(1) {
+                idx += maps.binomial[i][maps.pawns[square]];
+            }
+        } else {
+            // and below the fifth rank
+            if squares[0] >> 3 > 3 {
+                for square in &mut squares[..size] {
+                    *square ^= 56;
+                }
+            }
+
+            // and below the a1-h8 diagonal
+            let off_diagonal = (0..d.group_len[0]).find(|&i| off_a1h8(squares[i]) != 0);
+
+            if let Some(i) = off_diagonal.filter(|&i| off_a1h8(squares[i]) > 0) {
+                for square in &mut squares[i..size] {
+                    *square = ((*square >> 3) | (*square << 3)) & 63;
+                }
+            }
+
+            if material.has_unique_pieces {
+                let [s0, s1, s2] = [squares[0], squares[1], squares[2]];
+                let rank = |s: usize| (s >> 3) as u64;
+
+                let adjust1 = (s1 > s0) as u64;
+                let adjust2 = (s2 > s0) as u64 + (s2 > s1) as u64;
+
+                idx = if off_a1h8(s0) != 0 {
+                    (maps.a1d1d4[s0] as u64 * 63 + (s1 as u64 - adjust1)) * 62 + s2 as u64 - adjust2
+                } else if off_a1h8(s1) != 0 {
+                    (6 * 63 + rank(s0) * 28 + maps.b1h1h7[s1] as u64) * 62 + s2 as u64 - adjust2
+                } else if off_a1h8(s2) != 0 {
+                    6 * 63 * 62
+                        + 4 * 28 * 62
+                        + rank(s0) * 7 * 28
+                        + (rank(s1) - adjust1) * 28
+                        + maps.b1h1h7[s2] as u64
+                } else {
+                    6 * 63 * 62
+                        + 4 * 28 * 62
+                        + 4 * 7 * 28
+                        + rank(s0) * 7 * 6
+                        + (rank(s1) - adjust1) * 6
+                        + (rank(s2) - adjust2)
+                };
+            } else {
+                idx = maps.kk[maps.a1d1d4[squares[0]]][squares[1]] as u64;
+            }
+        }
+
+        idx *= d.group_idx[0];
+
+        let mut start = d.group_len[0];
+        let mut remaining_pawns = material.has_pawns && material.pawn_count[1] > 0;
+        let mut next = 1;
+
+        // the other groups, as combinations of the squares not taken by earlier groups
+        while d.group_len[next] != 0 {
+            let len = d.group_len[next];
+            squares[start..start + len].sort();
+
+            let mut n = 0;
+
+            for i in 0..len {
+                let square = squares[start + i];
+                let taken = squares[..start].iter().filter(|&&s| square > s).count();
+                let pawn_ranks = if remaining_pawns { 8 } else { 0 };
+
+                n += maps.binomial[i + 1][square - taken - pawn_ranks];
+            }
+
+            remaining_pawns = false;
+            idx += n * d.group_idx[next];
+            start += len;
+            next += 1;
+        }
+
+        Some((d, idx))
+    }
+
+    /// `None` if the table is corrupt
+    pub fn wdl(&self, position: &Position, black_stronger: bool) -> Option<Wdl> {
+        // WDL tables store both sides to move
+        let (d, idx) = self.index(position, black_stronger)?;
+        let value = d.decompress(&self.data, idx)?;
+
+        (value <= 4).then(|| Wdl::from_value(value as i32 - 2))
+    }
+
+    /// Distance to zeroing the fifty-move counter in plies, not signed. `None`
+    /// if the table is corrupt, `Some(None)` if it only stores the other side
+    /// to move
+    pub fn dtz(&self, position: &Position, black_stronger: bool, wdl: Wdl) -> Option<Option<i32>> {
+        // wins, losses, cursed wins and blessed losses each have their own map
+        const MAP_FOR: [usize; 5] = [1, 3, 0, 2, 0];
+
+        let Some((d, idx)) = self.index(position, black_stronger) else {
+            return Some(None);
+        };
+
+        let mut value = d.decompress(&self.data, idx)?;
+
+        if d.flags & MAPPED != 0 {
+            let at = d.map_idx[MAP_FOR[(wdl as i32 + 2) as usize]] + value;
+
+            value = if d.flags & WIDE != 0 {
+                read_u16_le(&self.data, self.map + 2 * at)? as usize
+            } else {
+                read_u8(&self.data, self.map + at)? as usize
+            };
+        }
+
+        let in_plies = match wdl {
+            Wdl::Win => d.flags & WIN_PLIES != 0,
+            Wdl::Loss => d.flags & LOSS_PLIES != 0,
+            _ => false,
+        };
+
+        let value = value as i32;
+
+        Some(Some(if in_plies { value } else { value * 2 } + 1))
+    }
+}
+
+/// Whether a subtable's pieces are the table's, with the leading pawns first
+fn valid_pieces(material: &Material, pieces: &[u8; MAX_PIECES]) -> bool {
+    let count = material.piece_count;
+    let mut sorted = *pieces;
+    sorted[..count].sort_unstable();
+
+    if sorted[..count] != material.codes[..count] {
+        return false;
+    }
+
+    if !material.has_pawns {
+        return true;
+    }
+
+    let [lead, other] = material.pawn_count;
+    let lead_pawn = pieces[0];
+
+    lead_pawn & 7 == 1
+        && pieces[..lead].iter().all(|&piece| piece == lead_pawn)
+        && pieces[lead..lead + other]
+            .iter()
+            .all(|&piece| piece == lead_pawn ^ 8)
+}
+
+/// Split the pieces into groups that are encoded together, and find how
+/// much each group's index has to be multiplied by
+fn set_groups(material: &Material, d: &mut PairsData, order: [u8; 2], file: usize) {
+    let maps = maps();
+
+    // without pawns, the first group is three unique pieces, or just the kings
+    let mut first_len = if material.has_pawns {
+        0
+    } else if material.has_unique_pieces {
+        3
+    } else {
+        2
+    };
+
+    let mut n = 0;
+    d.group_len[0] = 1;
+
+    for i in 1..material.piece_count {
+        first_len -= 1;
+
+        if first_len > 0 || d.pieces[i] == d.pieces[i - 1] {
+            d.group_len[n] += 1;
+        } else {
+            n += 1;
+            d.group_len[n] = 1;
+        }
+    }
+
+    n += 1;
+    d.group_len[n] = 0;
+
+    let both_pawns = material.has_pawns && material.pawn_count[1] > 0;
+    let mut next = if both_pawns { 2 } else { 1 };
+    let mut free_squares = 64 - d.group_len[0] - if both_pawns { d.group_len[1] } else { 0 };
+    let mut idx = 1u64;
+    let mut k = 0;
+
+    while next < n || k == order[0] as usize || k == order[1] as usize {
+        if k == order[0] as usize {
+            d.group_idx[0] = idx;
+
+            idx *= if material.has_pawns {
+                maps.lead_pawns_size[d.group_len[0]][file]
+            } else if material.has_unique_pieces {
+                31332
+            } else {
+                462
+            };
+        } else if k == order[1] as usize {
+            d.group_idx[1] = idx;
+            idx *= maps.binomial[d.group_len[1]][48 - d.group_len[0]];
+        } else {
+            d.group_idx[next] = idx;
+            idx *= maps.binomial[d.group_len[next]][free_squares];
+            free_squares -= d.group_len[next];
+            next += 1;
+        }
+
+        k += 1;
+    }
+
+    d.group_idx[n] = idx;
+}
+
+#[cfg(test)]
+mod tests {
+    // The fixture tables are made here, as the real ones can't be shipped:
+    // each endgame is solved by retrograde analysis, then compressed into the
+    // layout above the way the generator does it. The tests in `syzygy`
+    // check what they hold against known results
+    use std::{
+        cmp::Reverse,
+        collections::{BinaryHeap, HashMap},
+        ptr,
+    };
+
+    use super::*;
+    use crate::{board::fen::piece_from_char, rng::Rng, syzygy::Tablebase};
+
+    const FIXTURES: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/fixtures/syzygy");
+
+    /// Smaller endgames first, as the bigger ones are solved through them
+    const FIXTURE_TABLES: [&str; 6] = ["KBvK", "KNvK", "KQvK", "KRvK", "KPvK", "KRvKN"];
+
+    const BLOCK_BITS: u8 = 8;
+    const SPAN_BITS: u8 = 14;
+
+    /// Symbol ids have 12 bits, and 0xfff marks a value
+    const MAX_SYMBOLS: usize = 0xfff;
+
+    /// Moves that zero the fifty-move counter, in `Solved::children`
+    const ZEROING: u32 = 1 << 31;
+
+    /// One subtable, as it is written
+    struct Encoded {
+        flags: u8,
+
+        /// What `PairsData::set_sizes` reads after the flags
+        sizes: Vec<u8>,
+
+        sparse_index: Vec<u8>,
+        block_lengths: Vec<u8>,
+        blocks: Vec<u8>,
+    }
+
+    #[derive(Clone, Copy)]
+    enum Symbol {
+        Value(u16),
+        Pair(usize, usize),
+    }
+
+    fn pieces(name: &str) -> Vec<gui::Piece> {
+        let (white, black) = name.split_once('v').unwrap();
+        let mut pieces = vec![];
+
+        for (side, color) in [(white, Color::White), (black, Color::Black)] {
+            pieces.extend(side.chars().map(|c| gui::Piece {
+                color,
+                kind: piece_from_char(c).unwrap(),
+            }));
+        }
+
+        pieces
+    }
+
+    /// Piece codes in the order the fixtures encode them, white's pawns first
+    fn encoding_order(name: &str) -> Vec<u8> {
+        let mut codes: Vec<u8> = pieces(name).into_iter().map(piece_code).collect();
+        codes.sort_by_key(|&code| code != 1);
+        codes
+    }
+
+    fn single(value: u16, flags: u8) -> Encoded {
+        Encoded {
+            flags: flags | SINGLE_VALUE,
+            sizes: vec![value as u8],
+            sparse_index: vec![],
+            block_lengths: vec![],
+            blocks: vec![],
+        }
+    }
+
+    /// A table file of `subtables`, by file then side to move
+    fn table_bytes(name: &str, kind: TableKind, subtables: &[Vec<Encoded>]) -> Vec<u8> {
+        let material = Material::parse(name).unwrap();
+        let mut out = kind.magic().to_vec();
+
+        out.push((subtables[0].len() == 2) as u8 | (material.has_pawns as u8) << 1);
+
+        for _ in subtables {
+            // every group in the order of the pieces
+            out.push(0);
+
+            if material.pawn_count[1] > 0 {
+                out.push(0x11);
+            }
+
+            out.extend(encoding_order(name).iter().map(|&code| code | code << 4));
+        }
+
+        out.resize(out.len().next_multiple_of(2), 0);
+
+        for d in subtables.iter().flatten() {
+            out.push(d.flags);
+            out.extend(&d.sizes);
+        }
+
+        if kind == TableKind::Dtz {
+            out.resize(out.len().next_multiple_of(2), 0);
+        }
+
+        for d in subtables.iter().flatten() {
+            out.extend(&d.sparse_index);
+        }
+
+        for d in subtables.iter().flatten() {
+            out.extend(&d.block_lengths);
+        }
+
+        for d in subtables.iter().flatten() {
+            out.resize(out.len().next_multiple_of(64), 0);
+            out.extend(&d.blocks);
+        }
+
+        // and the checksum, which isn't read
+        out.resize(out.len().next_multiple_of(64) + 16, 0);
+        out
+    }
+
+    /// A table holding nothing, enough to index positions with
+    fn skeleton(name: &str) -> Table {
+        let material = Material::parse(name).unwrap();
+        let files = if material.has_pawns { 4 } else { 1 };
+        let subtables: Vec<_> = (0..files)
+            .map(|_| vec![single(0, 0), single(0, 0)])
+            .collect();
+
+        Table::parse(
+            table_bytes(name, TableKind::Wdl, &subtables),
+            material,
+            TableKind::Wdl,
+        )
+        .unwrap()
+    }
+
+    /// Replace the commonest neighbouring pairs of symbols with new symbols,
+    /// starting from one symbol per value. Returns the symbols, how many
+    /// values each expands to, and the values written with them
+    fn pair_up(values: &[u16]) -> (Vec<Symbol>, Vec<usize>, Vec<usize>) {
+        let mut distinct = values.to_vec();
+        distinct.sort_unstable();
+        distinct.dedup();
+
+        let mut symbols: Vec<_> = distinct.iter().map(|&value| Symbol::Value(value)).collect();
+        let mut lengths = vec![1; symbols.len()];
+        let mut stream: Vec<_> = values
+            .iter()
+            .map(|value| distinct.binary_search(value).unwrap())
+            .collect();
+
+        while symbols.len() < MAX_SYMBOLS {
+            let mut counts: HashMap<(usize, usize), usize> = HashMap::new();
+
+            for pair in stream.windows(2) {
+                *counts.entry((pair[0], pair[1])).or_default() += 1;
+            }
+
+            // expansions have to fit in a u8
+            let mut pairs: Vec<_> = counts
+                .into_iter()
+                .filter(|&((a, b), count)| count >= 4 && lengths[a] + lengths[b] <= 256)
+                .collect();
+
+            pairs.sort_unstable_by_key(|&(pair, count)| (Reverse(count), pair));
+            pairs.truncate(32.min(MAX_SYMBOLS - symbols.len()));
+
+            let before = symbols.len();
+
+            for ((a, b), _) in pairs {
+                let id = symbols.len();
+                let mut paired = Vec::with_capacity(stream.len());
+                let mut i = 0;
+
+                while i < stream.len() {
+                    if stream[i] == a && stream.get(i + 1) == Some(&b) {
+                        paired.push(id);
+                        i += 2;
+                    } else {
+                        paired.push(stream[i]);
+                        i += 1;
+                    }
+                }
+
+                // earlier pairs of this round may have taken most of them
+                if stream.len() - paired.len() >= 4 {
+                    symbols.push(Symbol::Pair(a, b));
+                    lengths.push(lengths[a] + lengths[b]);
+                    stream = paired;
+                }
+            }
+
+            if symbols.len() == before {
+                break;
+            }
+        }
+
+        (symbols, lengths, stream)
+    }
+
+    /// Huffman code lengths for symbols seen `counts` times
+    fn code_lengths(counts: &[u64]) -> Vec<u8> {
+        let mut heap: BinaryHeap<_> = counts
+            .iter()
+            .enumerate()
+            .map(|(node, &count)| Reverse((count, node)))
+            .collect();
+        let mut parent = vec![usize::MAX; counts.len()];
+
+        while heap.len() > 1 {
+            let Reverse((a_count, a)) = heap.pop().unwrap();
+            let Reverse((b_count, b)) = heap.pop().unwrap();
+
+            let node = parent.len();
+            parent.push(usize::MAX);
+            parent[a] = node;
+            parent[b] = node;
+
+            heap.push(Reverse((a_count + b_count, node)));
+        }
+
+        (0..counts.len())
+            .map(|mut node| {
+                let mut depth = 0;
+
+                while parent[node] != usize::MAX {
+                    node = parent[node];
+                    depth += 1;
+                }
+
+                depth
+            })
+            .collect()
+    }
+
+    /// Compress `values` into one subtable
+    fn encode(values: &[u16], flags: u8) -> Encoded {
+        if values.iter().all(|&value| value == values[0]) {
+            return single(values[0], flags);
+        }
+
+        let span = 1 << SPAN_BITS;
+        let block_size = 1 << BLOCK_BITS;
+
+        // so the middle of the last span has a value
+        let mut values = values.to_vec();
+        values.resize(values.len().next_multiple_of(span), *values.last().unwrap());
+
+        let (symbols, expansions, stream) = pair_up(&values);
+
+        let mut counts = vec![0; symbols.len()];
+
+        for &sym in &stream {
+            counts[sym] += 1;
+        }
+
+        let mut used: Vec<_> = (0..symbols.len()).filter(|&sym| counts[sym] > 0).collect();
+
+        // a code needs two symbols, any will do as the second
+        if used.len() == 1 {
+            used.push(if used[0] == 0 { 1 } else { 0 });
+        }
+
+        let used_counts: Vec<_> = used.iter().map(|&sym| counts[sym]).collect();
+
+        // ids by code length, longest first, then the symbols without a code
+        let mut order: Vec<_> = code_lengths(&used_counts).into_iter().zip(used).collect();
+        order.sort_by_key(|&(len, sym)| (Reverse(len), sym));
+
+        let mut id = vec![usize::MAX; symbols.len()];
+        let mut by_id = vec![];
+
+        for sym in order.iter().map(|&(_, sym)| sym).chain(0..symbols.len()) {
+            if id[sym] == usize::MAX {
+                id[sym] = by_id.len();
+                by_id.push(sym);
+            }
+        }
+
+        let max_len = order[0].0;
+        let min_len = order.last().unwrap().0;
+        let lengths = (max_len - min_len + 1) as usize;
+        assert!(max_len <= 32, "codes too long for the decoder");
+
+        // the canonical code `PairsData::set_sizes` expects
+        let mut count = vec![0; lengths];
+
+        for &(len, _) in &order {
+            count[(len - min_len) as usize] += 1;
+        }
+
+        let mut lowest = vec![0; lengths];
+        let mut base = vec![0u64; lengths];
+
+        for len in (0..lengths - 1).rev() {
+            lowest[len] = lowest[len + 1] + count[len + 1];
+            base[len] = (base[len + 1] + count[len + 1] as u64) / 2;
+        }
+
+        let mut codes = vec![(0, 0); symbols.len()];
+
+        for &(len, sym) in &order {
+            let l = (len - min_len) as usize;
+            codes[sym] = (base[l] + (id[sym] - lowest[l]) as u64, len as usize);
+        }
+
+        let mut blocks = vec![];
+        let mut block_values = vec![0];
+        let mut bits = 0;
+
+        for &sym in &stream {
+            let (code, len) = codes[sym];
+
+            if bits + len > block_size * 8
+                || block_values.last().unwrap() + expansions[sym] > 1 << 16
+            {
+                blocks.resize(blocks.len().next_multiple_of(block_size), 0);
+                block_values.push(0);
+                bits = 0;
+            }
+
+            for bit in (0..len).rev() {
+                if bits % 8 == 0 {
+                    blocks.push(0);
+                }
+
+                *blocks.last_mut().unwrap() |= ((code >> bit) as u8 & 1) << (7 - bits % 8);
+                bits += 1;
+            }
+
+            *block_values.last_mut().unwrap() += expansions[sym];
+        }
+
+        blocks.resize(blocks.len().next_multiple_of(block_size), 0);
+
+        let starts: Vec<usize> = block_values
+            .iter()
+            .scan(0, |start, &values| {
+                *start += values;
+                Some(*start - values)
+            })
+            .collect();
+
+        let mut sparse_index = vec![];
+
+        for middle in (span / 2..values.len()).step_by(span) {
+            let block = starts.partition_point(|&start| start <= middle) - 1;
+
+            sparse_index.extend((block as u32).to_le_bytes());
+            sparse_index.extend(((middle - starts[block]) as u16).to_le_bytes());
+        }
+
+        let block_lengths = block_values
+            .iter()
+            .flat_map(|&values| (values as u16 - 1).to_le_bytes())
+            .collect();
+
+        let mut sizes = vec![BLOCK_BITS, SPAN_BITS, 0];
+        sizes.extend((block_values.len() as u32).to_le_bytes());
+        sizes.extend([max_len, min_len]);
+        sizes.extend(
+            lowest
+                .iter()
+                .flat_map(|&lowest| (lowest as u16).to_le_bytes()),
+        );
+        sizes.extend((by_id.len() as u16).to_le_bytes());
+
+        for &sym in &by_id {
+            let (left, right) = match symbols[sym] {
+                Symbol::Value(value) => (value as usize, 0xfff),
+                Symbol::Pair(a, b) => (id[a], id[b]),
+            };
+
+            sizes.extend([
+                left as u8,
+                (left >> 8) as u8 | (right << 4) as u8,
+                (right >> 4) as u8,
+            ]);
+        }
+
+        sizes.resize(sizes.len() + by_id.len() % 2, 0);
+
+        Encoded {
+            flags,
+            sizes,
+            sparse_index,
+            block_lengths,
+            blocks,
+        }
+    }
+
+    /// Every position of an endgame, one entry per index, solved ignoring
+    /// the fifty-move rule, which no fixture needs
+    struct Solved {
+        table: Table,
+
+        /// Entry at each index, by side to move and file
+        entries: [[Vec<u32>; 4]; 2],
+
+        /// One position of each entry
+        positions: Vec<Position>,
+
+        /// For the side to move
+        wdl: Vec<Wdl>,
+        dtz: Vec<i32>,
+    }
+
+    /// Side to move, file and index of a position
+    fn locate(table: &Table, position: &Position) -> (usize, usize, usize) {
+        let (d, idx) = table.index(position, false).unwrap();
+        let (side, file) = (0..2)
+            .flat_map(|side| (0..4).map(move |file| (side, file)))
+            .find(|&(side, file)| ptr::eq(&table.items[side][file], d))
+            .unwrap();
+
+        (side, file, idx as usize)
+    }
+
+    /// Solve an endgame, probing `tablebase` for what captures and
+    /// promotions lead to
+    fn solve(name: &str, tablebase: &Tablebase) -> Solved {
+        let table = skeleton(name);
+        let pieces = pieces(name);
+        let mut entries: [[Vec<u32>; 4]; 2] = Default::default();
+
+        for (side, subtables) in entries.iter_mut().enumerate() {
+            for (file, entries) in subtables.iter_mut().enumerate() {
+                let d = &table.items[side][file];
+                let groups = d.group_len.iter().position(|&len| len == 0).unwrap();
+
+                *entries = vec![u32::MAX; d.group_idx[groups] as usize];
+            }
+        }
+
+        let mut positions = vec![];
+
+        for placement in 0..1usize << (6 * pieces.len()) {
+            let squares =
+                (0..pieces.len()).map(|i| Square::from_index((placement >> (6 * i) & 63) as u8));
+            let mut position = Position::empty();
+
+            for (square, &piece) in squares.zip(&pieces) {
+                let back_rank = matches!(square.rank(), Rank::First | Rank::Eighth);
+
+                if position.occupied().at(square)
+                    || (piece.kind == gui::PieceType::Pawn && back_rank)
+                {
+                    break;
+                }
+
+                position.put(square, piece);
+            }
+
+            if position.occupied().count_ones() as usize != pieces.len() {
+                continue;
+            }
+
+            for turn in [Color::White, Color::Black] {
+                position.turn = turn;
+
+                if position.in_check(turn.other()) {
+                    continue;
+                }
+
+                let (side, file, idx) = locate(&table, &position);
+                let entry = &mut entries[side][file][idx];
+
+                if *entry == u32::MAX {
+                    *entry = positions.len() as u32;
+                    positions.push(position);
+                }
+            }
+        }
+
+        // moves within the table, and the best result of those leaving it
+        let mut first_child = vec![0];
+        let mut children = vec![];
+        let mut outside = vec![];
+        let mut wdl = vec![None; positions.len()];
+
+        for (entry, position) in positions.iter().enumerate() {
+            let moves = position.legal_moves();
+            let mut best = None;
+
+            for &mv in &moves {
+                let zeroing = position.is_capture(mv) || position.pawns.at(mv.from);
+
+                let mut child = *position;
+                child.play(mv);
+
+                if position.is_capture(mv) || mv.promotion.is_some() {
+                    let value = -tablebase
+                        .probe_wdl(&child)
+                        .expect("smaller tables are solved first");
+                    best = best.max(Some(value));
+                } else {
+                    let (side, file, idx) = locate(&table, &child);
+                    children.push(entries[side][file][idx] | if zeroing { ZEROING } else { 0 });
+                }
+            }
+
+            if moves.is_empty() {
+                wdl[entry] = Some(if position.in_check(position.turn) {
+                    Wdl::Loss
+                } else {
+                    Wdl::Draw
+                });
+            }
+
+            first_child.push(children.len());
+            outside.push(best);
+        }
+
+        let children_of =
+            |entry: usize| children[first_child[entry]..first_child[entry + 1]].iter();
+        let child = |child: &u32| (child & !ZEROING) as usize;
+
+        // wins and losses spread from mates and captures until nothing changes,
+        // what is left is drawn
+        loop {
+            let mut changed = false;
+
+            for entry in 0..positions.len() {
+                if wdl[entry].is_some() {
+                    continue;
+                }
+
+                let mut best = outside[entry];
+                let mut known = true;
+
+                for value in children_of(entry).map(|c| wdl[child(c)]) {
+                    match value {
+                        Some(value) => best = best.max(Some(-value)),
+                        None => known = false,
+                    }
+                }
+
+                if best == Some(Wdl::Win) || known {
+                    wdl[entry] = best;
+                    changed = true;
+                }
+            }
+
+            if !changed {
+                break;
+            }
+        }
+
+        let wdl: Vec<_> = wdl
+            .into_iter()
+            .map(|wdl| wdl.unwrap_or(Wdl::Draw))
+            .collect();
+
+        // then distances, one ply further each round. A capture, pawn move or
+        // mate that keeps the result counts as one
+        let mut dtz = vec![0; positions.len()];
+        let mut round = vec![0; positions.len()];
+        let mut ply = 0;
+
+        loop {
+            ply += 1;
+            let mut left = false;
+
+            for entry in 0..positions.len() {
+                if wdl[entry] == Wdl::Draw || round[entry] != 0 {
+                    continue;
+                }
+
+                let mut done = true;
+                let mut distance = None::<i32>;
+
+                if wdl[entry] == Wdl::Win {
+                    let mated = |c: usize| {
+                        wdl[c] == Wdl::Loss
+                            && first_child[c] == first_child[c + 1]
+                            && outside[c].is_none()
+                    };
+
+                    if outside[entry] == Some(Wdl::Win)
+                        || children_of(entry).any(|c| {
+                            wdl[child(c)] == Wdl::Loss && (c & ZEROING != 0 || mated(child(c)))
+                        })
+                    {
+                        distance = Some(1);
+                    }
+
+                    for c in children_of(entry).map(child) {
+                        if wdl[c] == Wdl::Loss && round[c] != 0 && round[c] < ply {
+                            distance = Some(distance.map_or(1 - dtz[c], |d| d.min(1 - dtz[c])));
+                        }
+                    }
+
+                    if let Some(distance) = distance {
+                        dtz[entry] = distance;
+                    } else {
+                        done = false;
+                    }
+                } else {
+                    // mated, or every move zeroes
+                    let mut longest = 1;
+
+                    for c in children_of(entry).filter(|&c| c & ZEROING == 0).map(child) {
+                        if round[c] != 0 && round[c] < ply {
+                            longest = longest.max(dtz[c] + 1);
+                        } else {
+                            done = false;
+                        }
+                    }
+
+                    dtz[entry] = -longest;
+                }
+
+                if done {
+                    round[entry] = ply;
+                } else {
+                    left = true;
+                }
+            }
+
+            if !left {
+                break;
+            }
+        }
+
+        assert!(
+            dtz.iter().all(|dtz| dtz.abs() <= 100),
+            "{name} has cursed wins"
+        );
+
+        Solved {
+            table,
+            entries,
+            positions,
+            wdl,
+            dtz,
+        }
+    }
+
+    impl Solved {
+        /// What a subtable stores, `value` giving `None` where it doesn't
+        /// matter. Those indices repeat a neighbour, which compresses best
+        fn values(
+            &self,
+            side: usize,
+            file: usize,
+            value: impl Fn(Wdl, i32) -> Option<u16>,
+        ) -> Vec<u16> {
+            let values: Vec<_> = self.entries[side][file]
+                .iter()
+                .map(|&entry| {
+                    let entry = entry as usize;
+                    (entry < self.wdl.len())
+                        .then(|| value(self.wdl[entry], self.dtz[entry]))
+                        .flatten()
+                })
+                .collect();
+
+            let first = values.iter().flatten().next().copied().unwrap_or(0);
+
+            values
+                .iter()
+                .scan(first, |last, value| {
+                    *last = value.unwrap_or(*last);
+                    Some(*last)
+                })
+                .collect()
+        }
+    }
+
+    fn write_fixture(name: &str) {
+        let tablebase = Tablebase::open(FIXTURES).unwrap();
+        let solved = solve(name, &tablebase);
+        let files = if solved.table.material.has_pawns {
+            4
+        } else {
+            1
+        };
+
+        let wdl: Vec<_> = (0..files)
+            .map(|file| {
+                (0..2)
+                    .map(|side| {
+                        encode(
+                            &solved.values(side, file, |wdl, _| Some((wdl as i32 + 2) as u16)),
+                            0,
+                        )
+                    })
+                    .collect()
+            })
+            .collect();
+
+        let path = Path::new(FIXTURES).join(name);
+        fs::write(
+            path.with_extension("rtbw"),
+            table_bytes(name, TableKind::Wdl, &wdl),
+        )
+        .unwrap();
+
+        if solved.wdl.iter().all(|&wdl| wdl == Wdl::Draw) {
+            return;
+        }
+
+        // white to move only, in plies
+        let dtz: Vec<_> = (0..files)
+            .map(|file| {
+                let values = solved.values(0, file, |wdl, dtz| {
+                    (wdl != Wdl::Draw).then(|| dtz.unsigned_abs() as u16 - 1)
+                });
+                vec![encode(&values, WIN_PLIES | LOSS_PLIES)]
+            })
+            .collect();
+
+        fs::write(
+            path.with_extension("rtbz"),
+            table_bytes(name, TableKind::Dtz, &dtz),
+        )
+        .unwrap();
+    }
+
+    /// Rewrites the fixtures, run with `cargo test --release -- --ignored write_fixtures`
+    #[test]
+    #[ignore]
+    fn write_fixtures() {
+        fs::create_dir_all(FIXTURES).unwrap();
+
+        for name in FIXTURE_TABLES {
+            write_fixture(name);
+        }
+    }
+
+    fn fixture(name: &str, kind: TableKind) -> Vec<u8> {
+        let extension = match kind {
+            TableKind::Wdl => "rtbw",
+            TableKind::Dtz => "rtbz",
+        };
+
+        fs::read(Path::new(FIXTURES).join(name).with_extension(extension)).unwrap()
+    }
+
+    /// Random positions of an endgame
+    fn sample(name: &str, count: usize, rng: &mut Rng) -> Vec<Position> {
+        let mut positions = vec![];
+
+        while positions.len() < count {
+            let mut position = Position::empty();
+
+            for piece in pieces(name) {
+                let square = loop {
+                    let square = Square::from_index(rng.below(64) as u8);
+                    let back_rank = matches!(square.rank(), Rank::First | Rank::Eighth);
+                    let pawn_on_back_rank = piece.kind == gui::PieceType::Pawn && back_rank;
+
+                    if !position.occupied().at(square) && !pawn_on_back_rank {
+                        break square;
+                    }
+                };
+
+                position.put(square, piece);
+            }
+
+            position.turn = if rng.below(2) == 0 {
+                Color::White
+            } else {
+                Color::Black
+            };
+
+            if !position.in_check(position.turn.other()) {
+                positions.push(position);
+            }
+        }
+
+        positions
+    }
+
+    /// Where the block lengths end, after which only the blocks are read
+    fn headers(table: &Table) -> usize {
+        table
+            .items
+            .iter()
+            .flatten()
+            .filter(|d| d.num_blocks > 0)
+            .map(|d| d.block_length + 2 * d.block_length_size)
+            .max()
+            .unwrap()
+    }
+
+    /// Probe every position, which may fail but mustn't panic
+    fn probe(table: &Table, positions: &[Position]) {
+        for position in positions {
+            match table.kind {
+                TableKind::Wdl => {
+                    table.wdl(position, false);
+                }
+                TableKind::Dtz => {
+                    table.dtz(position, false, Wdl::Win);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn decodes_every_position() {
+        let tablebase = Tablebase::open(FIXTURES).unwrap();
+
+        for name in ["KRvK", "KPvK"] {
+            let solved = solve(name, &tablebase);
+            let material = Material::parse(name).unwrap();
+
+            let wdl =
+                Table::parse(fixture(name, TableKind::Wdl), material, TableKind::Wdl).unwrap();
+            let dtz =
+                Table::parse(fixture(name, TableKind::Dtz), material, TableKind::Dtz).unwrap();
+
+            for (entry, position) in solved.positions.iter().enumerate() {
+                let result = solved.wdl[entry];
+                assert_eq!(wdl.wdl(position, false), Some(result), "{}", position.fen());
+
+                if position.turn == Color::White && result != Wdl::Draw {
+                    let expected = Some(Some(solved.dtz[entry].abs()));
+                    assert_eq!(
+                        dtz.dtz(position, false, result),
+                        expected,
+                        "{}",
+                        position.fen()
+                    );
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn truncated_tables_are_refused() {
+        let mut rng = Rng::new(33);
+
+        for name in ["KRvK", "KPvK"] {
+            let material = Material::parse(name).unwrap();
+            let positions = sample(name, 20, &mut rng);
+
+            for kind in [TableKind::Wdl, TableKind::Dtz] {
+                let data = fixture(name, kind);
+                let headers = headers(&Table::parse(data.clone(), material, kind).unwrap());
+
+                for len in 0..data.len() {
+                    if let Some(table) = Table::parse(data[..len].to_vec(), material, kind) {
+                        assert!(
+                            len >= headers,
+                            "{name} cut to {len} bytes of {}",
+                            data.len()
+                        );
+                        probe(&table, &positions);
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn corrupt_tables_dont_panic() {
+        let mut rng = Rng::new(47);
+
+        for name in ["KRvK", "KPvK"] {
+            let material = Material::parse(name).unwrap();
+            let positions = sample(name, 20, &mut rng);
+
+            for kind in [TableKind::Wdl, TableKind::Dtz] {
+                let data = fixture(name, kind);
+
+                // every way to break the first bytes, which say how to read the rest
+                let mut damage: Vec<(usize, u8)> = (0..256)
+                    .flat_map(|at| [(at, 0), (at, 0xff), (at, data[at] ^ 0x81)])
+                    .collect();
+
+                for _ in 0..1000 {
+                    damage.push((rng.below(data.len() as u64) as usize, rng.below(256) as u8));
+                }
+
+                for (at, byte) in damage {
+                    let mut corrupt = data.clone();
+                    corrupt[at] = byte;
+
+                    if let Some(table) = Table::parse(corrupt, material, kind) {
+                        probe(&table, &positions);
+                    }
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,535 @@
+// See https://www.wbec-ridderkerk.nl/html/UCIProtocol.html
+use std::{
+    io::{self, BufRead},
+    mem,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    thread::{self, JoinHandle},
+    time::{Duration, Instant},
+};
+
+use crate::{
+    book::{self, Book},
+    evaluation::CompoundEvaluator,
+    prelude::*,
+    search::{
+        mate::MateSolver,
+        mcts::Mcts,
+        tt::{self, TranspositionTable},
+        GameHistory, SearchLimits, SearchOptions, SearchResult, Searcher,
+    },
+    syzygy,
+};
+
+/// Assume this many moves are left when the GUI doesn't say
+const DEFAULT_MOVES_TO_GO: u64 = 30;
+
+const MAX_THREADS: usize = 256;
+const MAX_HASH_MB: usize = 65_536;
+
+struct Uci {
+    position: Position,
+
+    /// Plies played since the start of the game, for the book
+    ply: usize,
+
+    /// The moves that led to `position`, for draws by repetition
+    history: GameHistory,
+
+    book: Option<Book>,
+    own_book: bool,
+    book_depth: usize,
+
+    /// Castling is written as the king taking its rook
+    chess960: bool,
+
+    /// Kept for the whole game, cleared by `ucinewgame`
+    tt: Arc<TranspositionTable>,
+    threads: usize,
+    search_options: SearchOptions,
+
+    /// `go mate` only tries checks for the attacker
+    mate_checks_only: bool,
+
+    /// Search with Monte Carlo tree search instead of alpha-beta
+    use_mcts: bool,
+    /// Its tree is kept for the whole game like the table, and cleared by
+    /// `ucinewgame`
+    mcts: Mcts,
+
+    /// The search started by the last `go`, until it has printed its move
+    running: Option<Running>,
+}
+
+/// A search on a thread of its own, so that `stop` can still be read
+struct Running {
+    stop: Arc<AtomicBool>,
+    /// `go infinite`, which doesn't print its move until stopped
+    infinite: bool,
+    /// Hands the MCTS tree back
+    thread: JoinHandle<Mcts>,
+}
+
+fn print_options() {
+    println!("option name UseNNUE type check default false");
+    println!("option name EvalFile type string default <empty>");
+    println!("option name OwnBook type check default false");
+    println!("option name BookFile type string default <empty>");
+    println!(
+        "option name BookDepth type spin default {} min 0 max 1000",
+        book::DEFAULT_DEPTH
+    );
+    println!("option name SyzygyPath type string default <empty>");
+    println!("option name Threads type spin default 1 min 1 max {MAX_THREADS}");
+    println!(
+        "option name Hash type spin default {} min 1 max {MAX_HASH_MB}",
+        tt::DEFAULT_HASH_MB
+    );
+    println!("option name UCI_Chess960 type check default false");
+    println!("option name MateChecksOnly type check default false");
+    println!("option name UseMCTS type check default false");
+
+    // to measure each one's worth in engine matches
+    for name in [
+        "NullMove",
+        "LateMoveReductions",
+        "ReverseFutility",
+        "Futility",
+        "CheckExtensions",
+    ] {
+        println!("option name {name} type check default true");
+    }
+}
+
+/// `setoption name <name> [value <value>]`
+fn parse_setoption(line: &str) -> Option<(&str, &str)> {
+    let rest = line
+        .trim()
+        .strip_prefix("setoption")?
+        .trim()
+        .strip_prefix("name")?
+        .trim();
+
+    match rest.split_once(" value ") {
+        Some((name, value)) => Some((name.trim(), value.trim())),
+        None => Some((rest, "")),
+    }
+}
+
+/// `position [startpos | fen <fen> | chess960 <number>] [moves <move>...]`, how many moves were
+/// played, and the game they make
+fn parse_position(args: &[&str], chess960: bool) -> Option<(Position, usize, GameHistory)> {
+    let moves_at = args.iter().position(|&a| a == "moves").unwrap_or(args.len());
+    let mut history = GameHistory::default();
+
+    let mut position = match *args.first()? {
+        "startpos" => Position::new(),
+        "fen" => {
+            let fen = &args[1..moves_at];
+            // the halfmove clock is the fifth field, and optional
+            history = GameHistory::new(fen.get(4).and_then(|c| c.parse().ok()).unwrap_or(0));
+
+            Position::from_fen(&fen.join(" ")).ok()?
+        }
+        // not part of UCI, a Chess960 start position by its number
+        "chess960" => Position::from_960_index(args.get(1)?.parse().ok()?)?,
+        _ => return None,
+    };
+
+    let moves = args.iter().skip(moves_at + 1).collect_vec();
+
+    for mv in &moves {
+        let mv = position.parse_uci(mv, chess960)?;
+        history.push(&position, mv);
+        position.play(mv);
+    }
+
+    Some((position, moves.len(), history))
+}
+
+/// `go [depth <d>] [nodes <n>] [movetime <ms>] [wtime <ms>] [btime <ms>] ...`, `go mate` is
+/// handled by [`Uci::go`]. `go infinite` has no limits at all
+fn parse_go(args: &[&str], turn: Color) -> SearchLimits {
+    if args.contains(&"infinite") {
+        return SearchLimits::default();
+    }
+
+    let value = |name: &str| {
+        args.iter()
+            .position(|&a| a == name)
+            .and_then(|i| args.get(i + 1))
+            .and_then(|v| v.parse::<u64>().ok())
+    };
+
+    let (time, increment) = match turn {
+        Color::White => (value("wtime"), value("winc")),
+        Color::Black => (value("btime"), value("binc")),
+    };
+
+    let moves_to_go = value("movestogo").unwrap_or(DEFAULT_MOVES_TO_GO).max(1);
+
+    // spend an even share of the clock, never all of it
+    let clock = time.map(|t| (t / moves_to_go + increment.unwrap_or(0) / 2).min(t / 2));
+
+    SearchLimits {
+        depth: value("depth").map(|d| d as i32),
+        nodes: value("nodes"),
+        time: value("movetime").or(clock).map(Duration::from_millis),
+    }
+}
+
+impl Uci {
+    fn new() -> Self {
+        Self {
+            position: Position::new(),
+            ply: 0,
+            history: GameHistory::default(),
+            book: None,
+            own_book: false,
+            book_depth: book::DEFAULT_DEPTH,
+            chess960: false,
+            tt: Arc::new(TranspositionTable::new(tt::DEFAULT_HASH_MB)),
+            threads: 1,
+            search_options: SearchOptions::default(),
+            mate_checks_only: false,
+            use_mcts: false,
+            mcts: Mcts::default(),
+            running: None,
+        }
+    }
+
+    fn set_option(&mut self, name: &str, value: &str) {
+        match name.to_lowercase().as_str() {
+            "usennue" => CompoundEvaluator::set_use_nnue(value == "true"),
+            "evalfile" => {
+                if let Err(e) = CompoundEvaluator::load_network(value) {
+                    println!("info string could not load {value}: {e}");
+                }
+            }
+            "ownbook" => self.own_book = value == "true",
+            "bookfile" => match Book::load(value) {
+                Ok(mut book) => {
+                    book.depth = self.book_depth;
+                    self.book = Some(book);
+                }
+                Err(e) => println!("info string could not load {value}: {e}"),
+            },
+            "bookdepth" => match value.parse() {
+                Ok(depth) => {
+                    self.book_depth = depth;
+
+                    if let Some(book) = &mut self.book {
+                        book.depth = depth;
+                    }
+                }
+                Err(_) => println!("info string invalid book depth {value}"),
+            },
+            "syzygypath" => match syzygy::set_path(value) {
+                Ok(count) => println!("info string found {count} tablebase files"),
+                Err(e) => println!("info string could not read {value}: {e}"),
+            },
+            "uci_chess960" => self.chess960 = value == "true",
+            "matechecksonly" => self.mate_checks_only = value == "true",
+            "usemcts" => self.use_mcts = value == "true",
+            "nullmove" => self.search_options.null_move = value == "true",
+            "latemovereductions" => self.search_options.late_move_reductions = value == "true",
+            "reversefutility" => self.search_options.reverse_futility = value == "true",
+            "futility" => self.search_options.futility = value == "true",
+            "checkextensions" => self.search_options.check_extensions = value == "true",
+            "threads" => match value.parse::<usize>() {
+                Ok(threads @ 1..=MAX_THREADS) => self.threads = threads,
+                _ => println!("info string invalid thread count {value}"),
+            },
+            "hash" => match value.parse::<usize>() {
+                Ok(mb @ 1..=MAX_HASH_MB) => self.tt = Arc::new(TranspositionTable::new(mb)),
+                _ => println!("info string invalid hash size {value}"),
+            },
+            _ => println!("info string unknown option {name}"),
+        }
+    }
+
+    /// Start a search for `go`, which prints its move when it's done. A
+    /// search that is still running is waited for first, or stopped if it's
+    /// infinite
+    fn go(&mut self, args: &[&str]) {
+        self.wait();
+
+        let mate = args
+            .iter()
+            .position(|&a| a == "mate")
+            .and_then(|i| args.get(i + 1))
+            .and_then(|n| n.parse::<u32>().ok());
+
+        let book_move = self
+            .book
+            .as_ref()
+            .filter(|_| self.own_book)
+            .and_then(|book| book.probe(&self.position, self.ply));
+
+        let mut limits = parse_go(args, self.position.turn);
+
+        // without a mate, still answer with a move in reasonable time
+        if let Some(moves) = mate {
+            limits.depth = limits.depth.or(Some(moves as i32 * 2));
+        }
+
+        let infinite = args.contains(&"infinite");
+        let stop = Arc::new(AtomicBool::new(false));
+
+        let mut solver = MateSolver::new(self.mate_checks_only)
+            .limits(limits)
+            .stop_flag(Arc::clone(&stop));
+
+        let searcher = (!self.use_mcts).then(|| {
+            Searcher::with_table(limits, Arc::clone(&self.tt))
+                .options(self.search_options)
+                .history(self.history.clone())
+                .threads(self.threads)
+                .stop_flag(Arc::clone(&stop))
+        });
+
+        let mcts = mem::take(&mut self.mcts)
+            .threads(self.threads)
+            .stop_flag(Arc::clone(&stop));
+
+        let position = self.position;
+        let chess960 = self.chess960;
+        let stopped = Arc::clone(&stop);
+
+        let thread = thread::spawn(move || {
+            let start = Instant::now();
+            let mate_move = mate.and_then(|moves| go_mate(&mut solver, &position, moves, chess960));
+
+            // without a mate, what the mate search used is gone from the limits
+            let limits = SearchLimits {
+                nodes: limits.nodes.map(|n| n.saturating_sub(solver.nodes())),
+                time: limits.time.map(|t| t.saturating_sub(start.elapsed())),
+                ..limits
+            };
+
+            let mut mcts = mcts.limits(limits);
+
+            let report = |result: &SearchResult| {
+                let pv = match result.pv.as_slice() {
+                    [] => String::new(),
+                    line => format!(" pv {}", position.line_to_uci(line, chess960)),
+                };
+
+                println!(
+                    "info depth {} score {} nodes {}{pv}",
+                    result.depth, result.score, result.nodes
+                );
+            };
+
+            let best_move = mate_move.or_else(|| {
+                if book_move.is_some() {
+                    println!("info string book move");
+                    return book_move;
+                }
+
+                match searcher {
+                    Some(searcher) => searcher.limits(limits).search(&position, report),
+                    None => mcts.search(&position, report),
+                }
+                .best_move
+            });
+
+            // `go infinite` only answers `stop`, even when it's done before
+            while infinite && !stopped.load(Ordering::Relaxed) {
+                thread::park();
+            }
+
+            match best_move {
+                Some(mv) => println!("bestmove {}", position.to_uci(mv, chess960)),
+                None => println!("bestmove 0000"),
+            }
+
+            mcts
+        });
+
+        self.running = Some(Running {
+            stop,
+            infinite,
+            thread,
+        });
+    }
+
+    /// Wait for the running search to print its move. `go infinite` never
+    /// finishes on its own, so it's stopped instead
+    fn wait(&mut self) {
+        if self.running.as_ref().is_some_and(|running| running.infinite) {
+            self.stop();
+            return;
+        }
+
+        if let Some(running) = self.running.take() {
+            self.mcts = running.thread.join().expect("the search thread panicked");
+        }
+    }
+
+    /// Stop the running search, which prints the best move it has found
+    fn stop(&mut self) {
+        if let Some(running) = self.running.take() {
+            running.stop.store(true, Ordering::Relaxed);
+            running.thread.thread().unpark();
+            self.mcts = running.thread.join().expect("the search thread panicked");
+        }
+    }
+
+    /// `ucinewgame`: forget the game, and what was learned searching it
+    fn new_game(&mut self) {
+        self.wait();
+        self.position = Position::new();
+        self.ply = 0;
+        self.history = GameHistory::default();
+        self.tt.clear();
+        self.mcts = Mcts::default();
+    }
+}
+
+/// `go mate <moves>`: prove the shortest mate, if there is one and the
+/// solver finds it within its limits
+fn go_mate(
+    solver: &mut MateSolver,
+    position: &Position,
+    max_moves: u32,
+    chess960: bool,
+) -> Option<Move> {
+    let Some(tree) = solver.solve(position, max_moves) else {
+        if solver.stopped() {
+            println!("info string mate search stopped");
+        } else {
+            println!("info string no mate in {max_moves}");
+        }
+
+        return None;
+    };
+
+    let moves = tree.moves();
+    let line = position.line_to_uci(&tree.main_line(), chess960);
+
+    println!(
+        "info depth {} score mate {moves} nodes {} pv {line}",
+        moves * 2 - 1,
+        solver.nodes()
+    );
+
+    Some(tree.mv)
+}
+
+pub fn run() {
+    let mut uci = Uci::new();
+
+    for line in io::stdin().lock().lines() {
+        let Ok(line) = line else {
+            break;
+        };
+
+        let args = line.split_whitespace().collect_vec();
+
+        match args.first() {
+            Some(&"uci") => {
+                println!("id name chess");
+                println!("id author OnlyCS");
+                print_options();
+                println!("uciok");
+            }
+            Some(&"isready") => println!("readyok"),
+            Some(&"ucinewgame") => uci.new_game(),
+            Some(&"setoption") => match parse_setoption(&line) {
+                Some((name, value)) => uci.set_option(name, value),
+                None => println!("info string malformed setoption"),
+            },
+            Some(&"position") => match parse_position(&args[1..], uci.chess960) {
+                Some((position, ply, history)) => {
+                    uci.position = position;
+                    uci.ply = ply;
+                    uci.history = history;
+                }
+                None => println!("info string invalid position"),
+            },
+            Some(&"go") => uci.go(&args[1..]),
+            Some(&"stop") => uci.stop(),
+            // not part of UCI, but handy for debugging
+            Some(&"eval") => println!("{}", CompoundEvaluator::eval(&uci.position)),
+            Some(&"quit") => {
+                uci.stop();
+                return;
+            }
+            _ => {}
+        }
+    }
+
+    // the input ended without `quit`, let a search it started finish
+    uci.wait();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn infinite_has_no_limits() {
+        let limits = parse_go(&["infinite"], Color::White);
+
+        assert_eq!(limits.depth, None);
+        assert_eq!(limits.nodes, None);
+        assert_eq!(limits.time, None);
+    }
+
+    #[test]
+    fn spends_a_share_of_the_clock() {
+        let args = ["wtime", "60000", "btime", "1000", "winc", "2000"];
+
+        // a thirtieth of the clock and half the increment
+        assert_eq!(
+            parse_go(&args, Color::White).time,
+            Some(Duration::from_millis(3000))
+        );
+        // never more than half of what's left
+        assert_eq!(
+            parse_go(&["btime", "1000", "movestogo", "1"], Color::Black).time,
+            Some(Duration::from_millis(500))
+        );
+    }
+
+    #[test]
+    fn movetime_and_depth() {
+        let limits = parse_go(&["depth", "7", "movetime", "250"], Color::White);
+
+        assert_eq!(limits.depth, Some(7));
+        assert_eq!(limits.time, Some(Duration::from_millis(250)));
+    }
+
+    #[test]
+    fn position_keeps_the_game() {
+        let args = ["startpos", "moves", "g1f3", "g8f6", "f3g1", "f6g8"];
+        let (position, ply, history) = parse_position(&args, false).unwrap();
+
+        assert_eq!(ply, 4);
+        assert!(position == Position::new());
+        assert_eq!(history.halfmove_clock(), 4);
+
+        let fen = "4k3/8/8/8/8/8/8/4K2R w K - 37 60";
+        let args = fen.split(' ').chain(["moves", "h1h5"]).collect_vec();
+        let (_, _, history) = parse_position(&[["fen"].as_slice(), &args].concat(), false).unwrap();
+
+        assert_eq!(history.halfmove_clock(), 38);
+    }
+
+    #[test]
+    fn go_and_ucinewgame_stop_an_infinite_search() {
+        let mut uci = Uci::new();
+
+        // these would wait for a `stop` that's never read
+        uci.go(&["infinite"]);
+        uci.go(&["depth", "1"]);
+        assert!(!uci.running.as_ref().unwrap().infinite);
+
+        uci.go(&["infinite"]);
+        uci.new_game();
+        assert!(uci.running.is_none());
+    }
+}